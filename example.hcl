@@ -1,12 +1,42 @@
 process "test_prod" {
   script = "node ./test.js"
+  cwd = "."
+  instances = 2
+
+  restart {
+    mode = "on-failure"
+  }
+
+  logs {
+    out = "logs/test_prod.out.log"
+    error = "logs/test_prod.error.log"
+  }
+
+  env_mode = "snapshot"
+  # env_file = [".env", ".env.local"]
 
   env {
     NODE_ENV = "production"
   }
+
+  env "staging" {
+    NODE_ENV = "staging"
+  }
+
+  readiness {
+    http = "http://localhost:3000/health"
+    interval = 5
+  }
+
+  cgroup {
+    cpu = 0.5
+    memory = "512M"
+  }
   
   watch {
     path = "./test.js"
+    ignore = ["node_modules", "*.log"]
+    debounce = 500
   }
 }
 
@@ -1,3 +1,17 @@
+use macros_rs::ternary;
+use pmc::{
+    file, helpers,
+    process::{
+        Options, Process,
+        cgroup::Limits,
+        cron, environ,
+        health::{Check, Probe},
+        limit::{Action, Cpu, Memory},
+        policy::{Mode, Policy},
+        stop, watch,
+    },
+};
+
 pub trait Validatable {
     fn from_id(id: usize) -> Self;
     fn from_string(s: String) -> Self;
@@ -55,3 +69,388 @@ pub fn validate<T: Validatable>(s: &str) -> Result<T, String> {
         Ok(T::from_string(s.to_owned()))
     }
 }
+
+/// Restart policy flags, unset flags keep the current value
+#[derive(clap::Args, Clone, Default)]
+pub struct PolicyArgs {
+    /// Restart policy (always, on-failure, never)
+    #[arg(long = "restart")]
+    pub mode: Option<Mode>,
+    /// Delay before the first restart in milliseconds
+    #[arg(long = "restart-delay")]
+    pub delay: Option<u64>,
+    /// Factor the restart delay grows by on every crash
+    #[arg(long = "restart-multiplier")]
+    pub multiplier: Option<f64>,
+    /// Upper bound for the restart delay in milliseconds
+    #[arg(long = "restart-max-delay")]
+    pub max_delay: Option<u64>,
+    /// Uptime in milliseconds after which the crash counter resets
+    #[arg(long = "restart-reset-after")]
+    pub reset_after: Option<u64>,
+}
+
+impl PolicyArgs {
+    /// Apply the flags over a base policy, none when no flag was passed
+    pub fn merge(&self, base: &Policy) -> Option<Policy> {
+        let is_set = self.mode.is_some()
+            || self.delay.is_some()
+            || self.multiplier.is_some()
+            || self.max_delay.is_some()
+            || self.reset_after.is_some();
+
+        is_set.then(|| Policy {
+            mode: self.mode.unwrap_or(base.mode),
+            delay: self.delay.unwrap_or(base.delay),
+            multiplier: self.multiplier.unwrap_or(base.multiplier),
+            max_delay: self.max_delay.unwrap_or(base.max_delay),
+            reset_after: self.reset_after.unwrap_or(base.reset_after),
+        })
+    }
+}
+
+/// Memory limit flags, unset flags keep the current value
+#[derive(clap::Args, Clone, Default)]
+pub struct MemoryArgs {
+    /// Restart once resident memory exceeds this size, e.g. 512M ("off" removes it)
+    #[arg(long = "max-memory", id = "max_memory", value_name = "SIZE", value_parser = parse_memory)]
+    pub max: Option<String>,
+    /// Count the memory of child processes towards the limit
+    #[arg(long = "max-memory-children", id = "max_memory_children", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub children: Option<bool>,
+    /// Consecutive checks the limit has to be exceeded for
+    #[arg(
+        long = "max-memory-ticks",
+        id = "max_memory_ticks",
+        value_name = "TICKS"
+    )]
+    pub ticks: Option<u32>,
+}
+
+fn parse_memory(value: &str) -> Result<String, String> {
+    match value == "off" || helpers::parse_size(value).is_some() {
+        true => Ok(value.to_string()),
+        false => Err(format!(
+            "invalid size '{value}', expected something like 512M"
+        )),
+    }
+}
+
+impl MemoryArgs {
+    /// Apply the flags over a base limit, none when no flag was passed
+    pub fn merge(&self, base: Option<&Memory>) -> Option<Memory> {
+        let is_set = self.max.is_some() || self.children.is_some() || self.ticks.is_some();
+        let base = base.cloned().unwrap_or_default();
+
+        is_set.then(|| Memory {
+            max: self.max.clone().unwrap_or(base.max),
+            children: self.children.unwrap_or(base.children),
+            ticks: self.ticks.unwrap_or(base.ticks),
+        })
+    }
+}
+
+/// Cpu limit flags, unset flags keep the current value
+#[derive(clap::Args, Clone, Default)]
+pub struct CpuArgs {
+    /// Act once cpu usage stays over this percentage (0 removes it)
+    #[arg(long = "max-cpu", id = "max_cpu", value_name = "PERCENT")]
+    pub max: Option<f64>,
+    /// Seconds the cpu usage has to stay over the limit
+    #[arg(long = "max-cpu-for", id = "max_cpu_for", value_name = "SECONDS")]
+    pub duration: Option<u64>,
+    /// Action to take (log, restart, signal:<SIGNAL>, webhook:<URL>)
+    #[arg(long = "max-cpu-action", id = "max_cpu_action", value_name = "ACTION")]
+    pub action: Option<Action>,
+}
+
+impl CpuArgs {
+    /// Apply the flags over a base limit, none when no flag was passed
+    pub fn merge(&self, base: Option<&Cpu>) -> Option<Cpu> {
+        let is_set = self.max.is_some() || self.duration.is_some() || self.action.is_some();
+        let base = base.cloned().unwrap_or_default();
+
+        is_set.then(|| Cpu {
+            max: self.max.unwrap_or(base.max),
+            duration: self.duration.unwrap_or(base.duration),
+            action: self.action.clone().unwrap_or(base.action),
+        })
+    }
+}
+
+/// Health probe flags, unset flags keep the current value
+#[derive(clap::Args, Clone, Default)]
+pub struct HealthArgs {
+    /// Restart once this check keeps failing (http:<URL>, tcp:<PORT>, cmd:<COMMAND>, off)
+    #[arg(long, value_name = "CHECK")]
+    pub liveness: Option<Check>,
+    /// Seconds between liveness checks
+    #[arg(long = "liveness-interval", value_name = "SECONDS")]
+    pub liveness_interval: Option<u64>,
+    /// Seconds a single liveness check may take
+    #[arg(long = "liveness-timeout", value_name = "SECONDS")]
+    pub liveness_timeout: Option<u64>,
+    /// Consecutive liveness failures before a restart
+    #[arg(long = "liveness-threshold", value_name = "COUNT")]
+    pub liveness_threshold: Option<u32>,
+    /// Report the process unready while this check fails (http:<URL>, tcp:<PORT>, cmd:<COMMAND>, off)
+    #[arg(long, value_name = "CHECK")]
+    pub readiness: Option<Check>,
+    /// Seconds between readiness checks
+    #[arg(long = "readiness-interval", value_name = "SECONDS")]
+    pub readiness_interval: Option<u64>,
+    /// Seconds a single readiness check may take
+    #[arg(long = "readiness-timeout", value_name = "SECONDS")]
+    pub readiness_timeout: Option<u64>,
+    /// Consecutive readiness failures before the process is unready
+    #[arg(long = "readiness-threshold", value_name = "COUNT")]
+    pub readiness_threshold: Option<u32>,
+}
+
+fn probe(
+    base: Option<&Probe>,
+    check: &Option<Check>,
+    interval: Option<u64>,
+    timeout: Option<u64>,
+    threshold: Option<u32>,
+) -> Option<Probe> {
+    let is_set = check.is_some() || interval.is_some() || timeout.is_some() || threshold.is_some();
+    let base = base.cloned().unwrap_or_default();
+
+    is_set.then(|| Probe {
+        check: check.clone().unwrap_or(base.check),
+        interval: interval.unwrap_or(base.interval),
+        timeout: timeout.unwrap_or(base.timeout),
+        threshold: threshold.unwrap_or(base.threshold),
+    })
+}
+
+impl HealthArgs {
+    /// Apply the flags over the current probes, none for a probe without any flag
+    pub fn merge(&self, process: Option<&Process>) -> (Option<Probe>, Option<Probe>) {
+        let health = process.and_then(|p| p.health.as_ref());
+
+        let liveness = probe(
+            health.and_then(|health| health.liveness.as_ref()),
+            &self.liveness,
+            self.liveness_interval,
+            self.liveness_timeout,
+            self.liveness_threshold,
+        );
+
+        let readiness = probe(
+            health.and_then(|health| health.readiness.as_ref()),
+            &self.readiness,
+            self.readiness_interval,
+            self.readiness_timeout,
+            self.readiness_threshold,
+        );
+
+        (liveness, readiness)
+    }
+}
+
+/// Dependency flags, unset flags keep the current value
+#[derive(clap::Args, Clone, Default)]
+pub struct DependsArgs {
+    /// Processes started before this one on restore ("off" removes them)
+    #[arg(long = "depends-on", value_name = "NAME", value_delimiter = ',')]
+    pub on: Option<Vec<String>>,
+    /// Wait until the dependencies are online and ready before starting
+    #[arg(long = "wait-ready", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub wait: Option<bool>,
+}
+
+/// Cgroup limit flags, unset flags keep the current value
+#[derive(clap::Args, Clone, Default)]
+pub struct CgroupArgs {
+    /// Cpus the process may use, like 0.5 (0 removes it)
+    #[arg(long = "cgroup-cpu", value_name = "CPUS")]
+    pub cpu: Option<f64>,
+    /// Memory the kernel keeps the process under, like 512M (0 removes it)
+    #[arg(long = "cgroup-memory", value_name = "SIZE", value_parser = parse_size)]
+    pub memory: Option<String>,
+    /// Tasks the process may run at once (0 removes it)
+    #[arg(long = "cgroup-pids", value_name = "COUNT")]
+    pub pids: Option<u64>,
+}
+
+fn parse_size(value: &str) -> Result<String, String> {
+    match helpers::parse_size(value) {
+        Some(_) => Ok(value.to_string()),
+        None => Err(format!("invalid size '{value}'")),
+    }
+}
+
+impl CgroupArgs {
+    /// Limits from the flags, none when no flag was passed
+    pub fn limits(&self) -> Option<Limits> {
+        let limits = Limits {
+            cpu: self.cpu,
+            memory: self.memory.clone(),
+            pids: self.pids,
+        };
+
+        (limits != Limits::default()).then_some(limits)
+    }
+}
+
+/// Watch flags, unset flags keep the current value
+#[derive(clap::Args, Clone, Default)]
+pub struct WatchArgs {
+    /// Paths watched besides --watch ("off" removes them)
+    #[arg(long = "watch-path", value_name = "PATH", value_delimiter = ',')]
+    pub watch_paths: Option<Vec<String>>,
+    /// Glob patterns changes are ignored in, like node_modules,.git,*.log ("off" removes them)
+    #[arg(long = "watch-ignore", value_name = "GLOB", value_delimiter = ',')]
+    pub watch_ignore: Option<Vec<String>>,
+    /// Milliseconds without changes before restarting
+    #[arg(long = "watch-debounce", value_name = "MS")]
+    pub watch_debounce: Option<u64>,
+    /// Milliseconds to wait after the debounce before restarting
+    #[arg(long = "watch-delay", value_name = "MS")]
+    pub watch_delay: Option<u64>,
+}
+
+impl WatchArgs {
+    /// Settings from the flags, none when no flag was passed
+    pub fn settings(&self) -> Option<watch::Settings> {
+        let list = |values: &Option<Vec<String>>| {
+            (values.clone())
+                .map(|values| values.into_iter().filter(|value| value != "off").collect())
+        };
+
+        let settings = watch::Settings {
+            paths: list(&self.watch_paths),
+            ignore: list(&self.watch_ignore),
+            debounce: self.watch_debounce,
+            delay: self.watch_delay,
+            ..Default::default()
+        };
+
+        settings.is_set().then_some(settings)
+    }
+}
+
+/// Environment flags, unset flags keep the current value
+#[derive(clap::Args, Clone, Default)]
+pub struct EnvArgs {
+    /// Dotenv files read on every start, later files win ("off" removes them)
+    #[arg(long = "env-file", value_name = "PATH", value_delimiter = ',')]
+    pub env_files: Option<Vec<String>>,
+    /// Env profile of the process to use, like production ("off" removes it)
+    #[arg(long = "env", value_name = "PROFILE")]
+    pub env_profile: Option<String>,
+    /// Where the environment comes from (inherit, snapshot, clean)
+    #[arg(long = "env-mode", value_name = "MODE")]
+    pub env_mode: Option<environ::Mode>,
+}
+
+impl EnvArgs {
+    /// Settings from the flags, env files are resolved against the current directory
+    pub fn settings(&self) -> Option<environ::Settings> {
+        let settings = environ::Settings {
+            files: (self.env_files.clone()).map(|files| {
+                (files.into_iter())
+                    .filter(|file| file != "off")
+                    .map(|file| file::cwd().join(file))
+                    .collect()
+            }),
+            profile: (self.env_profile.clone())
+                .map(|profile| ternary!(profile == "off", String::new(), profile)),
+            ..Default::default()
+        };
+
+        settings.is_set().then_some(settings)
+    }
+}
+
+/// Flags for every option a process can be started with
+#[derive(clap::Args, Clone, Default)]
+pub struct OptionArgs {
+    #[command(flatten)]
+    pub policy: PolicyArgs,
+    #[command(flatten)]
+    pub memory: MemoryArgs,
+    #[command(flatten)]
+    pub cpu: CpuArgs,
+    #[command(flatten)]
+    pub health: HealthArgs,
+    #[command(flatten)]
+    pub depends: DependsArgs,
+    #[command(flatten)]
+    pub cgroup: CgroupArgs,
+    #[command(flatten)]
+    pub watch: WatchArgs,
+    #[command(flatten)]
+    pub env: EnvArgs,
+    /// Signal sent to stop the process, like SIGINT or SIGQUIT
+    #[arg(long = "stop-signal", value_name = "SIGNAL", value_parser = stop::parse_signal)]
+    pub stop_signal: Option<String>,
+    /// Milliseconds to wait after the stop signal before sending SIGKILL
+    #[arg(long = "kill-timeout", value_name = "MS", value_parser = stop::parse_kill_timeout)]
+    pub kill_timeout: Option<u64>,
+    /// Restart on a cron schedule in local time, or run on it with --restart never ("off" removes it)
+    #[arg(long, value_name = "EXPRESSION", value_parser = parse_cron)]
+    pub cron: Option<String>,
+}
+
+fn parse_cron(value: &str) -> Result<String, String> {
+    match value {
+        "off" => Ok(value.to_string()),
+        _ => cron::parse(value).map(|_| value.to_string()),
+    }
+}
+
+impl OptionArgs {
+    /// Apply the flags over the current options of a process
+    pub fn merge(&self, process: Option<&Process>) -> Options {
+        let (liveness, readiness) = self.health.merge(process);
+
+        Options {
+            policy: self
+                .policy
+                .merge(&process.map(|p| p.policy.clone()).unwrap_or_default()),
+            max_memory: self
+                .memory
+                .merge(process.and_then(|p| p.max_memory.as_ref())),
+            max_cpu: self.cpu.merge(process.and_then(|p| p.max_cpu.as_ref())),
+            cron: self.cron.clone(),
+            liveness,
+            readiness,
+            depends_on: (self.depends.on.clone())
+                .map(|names| names.into_iter().filter(|name| name != "off").collect()),
+            wait_ready: self.depends.wait,
+            stop_signal: self.stop_signal.clone(),
+            kill_timeout: self.kill_timeout,
+            cgroup: self.cgroup.limits(),
+            watch: self.watch.settings(),
+            env: self.env.settings(),
+            env_mode: self.env.env_mode,
+            ..Options::default()
+        }
+    }
+
+    /// Options for a new process
+    pub fn create(&self) -> Options {
+        self.merge(None)
+    }
+
+    pub fn is_set(&self) -> bool {
+        let options = self.create();
+        options.policy.is_some()
+            || options.max_memory.is_some()
+            || options.max_cpu.is_some()
+            || options.cron.is_some()
+            || options.liveness.is_some()
+            || options.readiness.is_some()
+            || options.depends_on.is_some()
+            || options.wait_ready.is_some()
+            || options.stop_signal.is_some()
+            || options.kill_timeout.is_some()
+            || options.cgroup.is_some()
+            || options.watch.is_some()
+            || options.env.is_some()
+            || options.env_mode.is_some()
+    }
+}
@@ -0,0 +1,71 @@
+use colored::Colorize;
+use global_placeholders::global;
+use macros_rs::{crashln, string, then};
+use std::fs;
+
+use pmc::{
+    file::{self, Exists},
+    helpers,
+    process::{dump, schema},
+};
+
+/// Upgrade the dumpfile to the current schema, keeping the old file next to it
+pub fn migrate(dry_run: &bool) {
+    let _lock = dump::lock();
+    let path = global!("pmc.dump");
+
+    then!(
+        !Exists::check(&path).file(),
+        crashln!("{} No dumpfile at {path}", *helpers::FAIL)
+    );
+
+    let migration = match schema::migrate(&file::raw(path.clone())) {
+        Ok(migration) => migration,
+        Err(err) => crashln!("{} Cannot parse dumpfile.\n{}", *helpers::FAIL, err.white()),
+    };
+
+    if migration.is_current() {
+        return println!(
+            "{} Dumpfile is already at schema v{}",
+            *helpers::SUCCESS,
+            schema::VERSION
+        );
+    }
+
+    println!(
+        "{} Dumpfile schema v{} -> v{}",
+        *helpers::SUCCESS,
+        migration.from,
+        schema::VERSION
+    );
+
+    for (version, step, _) in schema::STEPS
+        .iter()
+        .filter(|(version, ..)| *version > migration.from)
+    {
+        println!("  {} {}", format!("v{version}").bold(), step.white());
+    }
+
+    for change in &migration.changes {
+        println!("  {} {change}", "~".yellow().bold());
+    }
+
+    if *dry_run {
+        return println!("{} Dry run, nothing was written", *helpers::WARN);
+    }
+
+    let kept = format!("{path}.v{}", migration.from);
+    if let Err(err) = fs::copy(&path, &kept) {
+        crashln!(
+            "{} Cannot keep a copy of the dumpfile.\n{}",
+            *helpers::FAIL,
+            string!(err).white()
+        )
+    }
+
+    dump::write(&migration.runner);
+    println!(
+        "{} Upgraded dumpfile, the old one was kept as {kept}",
+        *helpers::SUCCESS
+    );
+}
@@ -1,51 +1,359 @@
-use super::Item;
+use super::{Item, internal::local_script};
 use colored::Colorize;
-use macros_rs::{crashln, string};
+use macros_rs::{crashln, string, ternary, then};
 use serde::{Deserialize, Serialize};
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::{self, OpenOptions},
     io::prelude::*,
+    path::PathBuf,
 };
 
 use pmc::{
-    file::Exists,
+    config,
+    file::{self, Exists},
     helpers,
-    process::{Env, Runner},
+    process::{
+        Env, Options, Runner, cgroup, cron,
+        depends::{self, Depends},
+        environ::{self, Declared},
+        health::{self, Check},
+        limit::{Cpu, Memory},
+        policy::Policy,
+        stop, watch,
+    },
 };
 
 #[derive(Deserialize, Debug)]
 struct ProcessWrapper {
     #[serde(alias = "process")]
-    list: HashMap<String, Process>,
+    list: BTreeMap<String, Process>,
+}
+
+impl ProcessWrapper {
+    /// Processes in the order they have to be started in
+    fn order(&self) -> Result<Vec<(&String, &Process)>, String> {
+        let names = depends::order(
+            (self.list.iter()).map(|(name, item)| (name.as_str(), &item.depends_on[..])),
+        )?;
+
+        Ok(names
+            .into_iter()
+            .filter_map(|name| self.list.get_key_value(name))
+            .collect())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Process {
     script: String,
     server: Option<String>,
+    cwd: Option<String>,
+    instances: Option<usize>,
     watch: Option<Watch>,
+    restart: Option<Policy>,
+    max_memory: Option<Memory>,
+    max_cpu: Option<Cpu>,
+    cron: Option<String>,
+    logs: Option<Logs>,
+    liveness: Option<Probe>,
+    readiness: Option<Probe>,
+    /// Processes in the file started before this one
+    #[serde(default)]
+    depends_on: Vec<String>,
+    wait_ready: Option<bool>,
+    stop_signal: Option<String>,
+    kill_timeout: Option<u64>,
+    cgroup: Option<cgroup::Limits>,
+    /// Dotenv files relative to the working directory
+    #[serde(default)]
+    env_file: Vec<String>,
+    env_mode: Option<environ::Mode>,
     #[serde(default)]
     env: Env,
+    /// Labeled env blocks, renamed by `parse` so they do not clash with the env block
+    #[serde(default, rename = "env_profile")]
+    profiles: BTreeMap<String, Env>,
+    /// Profile chosen with --env, when the process has it
+    #[serde(skip)]
+    profile: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Watch {
     path: String,
+    /// Paths watched besides the first one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<String>,
+    /// Glob patterns changes are ignored in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ignore: Vec<String>,
+    debounce: Option<u64>,
+    delay: Option<u64>,
+}
+
+impl Watch {
+    fn settings(&self) -> watch::Settings {
+        watch::Settings {
+            path: Some(self.path.clone()),
+            paths: Some(self.paths.clone()),
+            ignore: Some(self.ignore.clone()),
+            debounce: Some(self.debounce.unwrap_or(watch::DEFAULT_DEBOUNCE_MS)),
+            delay: Some(self.delay.unwrap_or_default()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Logs {
+    out: Option<String>,
+    error: Option<String>,
+}
+
+/// Probe block, exactly one of http, tcp or command sets the check
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Probe {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    http: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tcp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<String>,
+    interval: Option<u64>,
+    timeout: Option<u64>,
+    threshold: Option<u32>,
+}
+
+impl Probe {
+    fn probe(&self) -> Result<health::Probe, String> {
+        let check = match (&self.http, &self.tcp, &self.command) {
+            (Some(url), None, None) => Check::Http(url.clone()),
+            (None, Some(target), None) => format!("tcp:{target}").parse()?,
+            (None, None, Some(command)) => Check::Command(command.clone()),
+            _ => return Err(string!("a probe needs exactly one of http, tcp or command")),
+        };
+
+        let default = health::Probe::default();
+        Ok(health::Probe {
+            check,
+            interval: self.interval.unwrap_or(default.interval),
+            timeout: self.timeout.unwrap_or(default.timeout),
+            threshold: self.threshold.unwrap_or(default.threshold),
+        })
+    }
+}
+
+impl From<&health::Probe> for Probe {
+    fn from(probe: &health::Probe) -> Self {
+        let mut block = Probe {
+            interval: Some(probe.interval),
+            timeout: Some(probe.timeout),
+            threshold: Some(probe.threshold),
+            ..Probe::default()
+        };
+
+        match &probe.check {
+            Check::Http(url) => block.http = Some(url.clone()),
+            Check::Tcp(target) => block.tcp = Some(target.clone()),
+            Check::Command(command) => block.command = Some(command.clone()),
+            Check::Off => {}
+        }
+
+        block
+    }
+}
+
+/// Fields a process has to be restarted for, the rest apply while it runs
+const RESTART: &[&str] = &["script", "cwd", "env", "env_mode", "logs"];
+
+/// Change `apply` makes to a process group
+#[derive(Debug, PartialEq)]
+enum Change {
+    Create,
+    /// Updated in place since the listed fields differ from the file
+    Update(Vec<&'static str>),
+    Delete,
+}
+
+impl Change {
+    /// A new instance count recreates the group, other changes keep its processes
+    fn recreates(&self) -> bool {
+        matches!(self, Change::Update(changes) if changes.contains(&"instances"))
+    }
+
+    /// Whether the processes were spawned differently than the file says
+    fn restarts(&self) -> bool {
+        matches!(self, Change::Update(changes) if changes.iter().any(|change| RESTART.contains(change)))
+    }
+}
+
+struct Step {
+    name: String,
+    server: String,
+    ids: Vec<usize>,
+    change: Change,
 }
 
 impl Process {
     fn get_watch_path(&self) -> Option<String> {
         self.watch.as_ref().map(|w| w.path.clone())
     }
+
+    fn server(&self) -> String {
+        match self.server.as_deref() {
+            None | Some("") => string!("local"),
+            Some(server) => server.to_string(),
+        }
+    }
+
+    fn cwd(&self) -> Option<PathBuf> {
+        self.cwd.as_ref().map(|cwd| file::cwd().join(cwd))
+    }
+
+    /// Log files resolved against the working directory of the process
+    fn log_file(&self, file: &Option<String>) -> Option<String> {
+        let cwd = self.cwd().unwrap_or_else(file::cwd);
+        file.as_ref()
+            .map(|file| cwd.join(file).to_string_lossy().into_owned())
+    }
+
+    fn declared(&self) -> environ::Settings {
+        environ::Settings {
+            files: Some(self.env_file.iter().map(PathBuf::from).collect()),
+            vars: Some(self.env.clone()),
+            profiles: Some(self.profiles.clone()),
+            profile: Some(self.profile.clone().unwrap_or_default()),
+        }
+    }
+
+    fn options(&self) -> Options {
+        let logs = self.logs.as_ref();
+
+        Options {
+            policy: self.restart.clone(),
+            max_memory: self.max_memory.clone(),
+            max_cpu: self.max_cpu.clone(),
+            cron: self.cron.clone(),
+            script: None,
+            cwd: self.cwd(),
+            out_file: logs.and_then(|logs| self.log_file(&logs.out)),
+            error_file: logs.and_then(|logs| self.log_file(&logs.error)),
+            liveness: self.liveness.as_ref().and_then(|probe| probe.probe().ok()),
+            readiness: self.readiness.as_ref().and_then(|probe| probe.probe().ok()),
+            depends_on: Some(self.depends_on.clone()),
+            wait_ready: self.wait_ready,
+            stop_signal: (self.stop_signal.as_deref())
+                .and_then(|signal| stop::parse_signal(signal).ok()),
+            kill_timeout: self.kill_timeout,
+            cgroup: self.cgroup.clone(),
+            watch: self.watch.as_ref().map(Watch::settings),
+            env: Some(self.declared()),
+            env_mode: Some(self.env_mode.unwrap_or_default()),
+        }
+    }
+
+    /// Fields of a running group that differ from the file
+    fn changes(&self, group: &[&pmc::process::Process], local: bool) -> Vec<&'static str> {
+        let mut changes = vec![];
+        let Some(current) = group.first() else {
+            return changes;
+        };
+
+        let options = self.options();
+        let stop = options.stop(None).ok().flatten();
+        let limits = (options.cgroup.as_ref()).and_then(|limits| limits.merge(None));
+        let script = ternary!(local, local_script(&self.script), self.script.clone());
+        let watch = current.watch.enabled.then(|| current.watch.path.clone());
+        let mut settings = current.watch.clone();
+        if let Some(block) = &self.watch {
+            block.settings().apply(&mut settings);
+        }
+        let mut declared = Declared::default();
+        self.declared().apply(&mut declared);
+        // values the server redacted cannot be compared, they count as unchanged
+        declared.mask(&current.declared);
+
+        then!(current.script != script, changes.push("script"));
+        then!(
+            options.cwd.is_some_and(|cwd| cwd != current.path),
+            changes.push("cwd")
+        );
+        then!(current.declared != declared, changes.push("env"));
+        then!(
+            current.env_mode != self.env_mode.unwrap_or_default(),
+            changes.push("env_mode")
+        );
+        then!(
+            self.instances.unwrap_or(1).max(1) != group.len(),
+            changes.push("instances")
+        );
+        then!(
+            watch != self.get_watch_path() || settings != current.watch,
+            changes.push("watch")
+        );
+        then!(
+            current.policy != options.policy.unwrap_or_default(),
+            changes.push("restart")
+        );
+        then!(
+            current.max_memory != options.max_memory.filter(|memory| memory.bytes().is_some()),
+            changes.push("max_memory")
+        );
+        then!(
+            current.max_cpu != options.max_cpu.filter(|cpu| cpu.is_enabled()),
+            changes.push("max_cpu")
+        );
+        then!(
+            current.cron != options.cron.filter(|cron| cron != "off"),
+            changes.push("cron")
+        );
+        then!(
+            current.out_file != options.out_file || current.error_file != options.error_file,
+            changes.push("logs")
+        );
+
+        let health = current.health.as_ref();
+        then!(
+            health.and_then(|health| health.liveness.as_ref()) != options.liveness.as_ref(),
+            changes.push("liveness")
+        );
+        then!(
+            health.and_then(|health| health.readiness.as_ref()) != options.readiness.as_ref(),
+            changes.push("readiness")
+        );
+        then!(
+            current.depends
+                != Depends::new(self.depends_on.clone(), self.wait_ready.unwrap_or_default()),
+            changes.push("depends_on")
+        );
+        then!(current.stop != stop, changes.push("stop"));
+        then!(current.limits != limits, changes.push("cgroup"));
+
+        changes
+    }
 }
 
-pub fn read_hcl(path: &String) {
-    let mut servers: Vec<String> = vec![];
+/// Labeled env blocks are profiles, hcl merges them with the unlabeled env
+/// block into one object, so they are given their own name before decoding
+fn rename_profiles(body: &mut hcl::Body) {
+    for structure in body.0.iter_mut() {
+        let hcl::Structure::Block(process) = structure else {
+            continue;
+        };
 
-    println!("{} Applying action importProcess", *helpers::SUCCESS);
+        for structure in process.body.0.iter_mut() {
+            if let hcl::Structure::Block(block) = structure
+                && block.identifier() == "env"
+                && block.labels().len() == 1
+            {
+                block.identifier = hcl::Identifier::unchecked("env_profile");
+            }
+        }
+    }
+}
 
+fn parse(path: &String, profile: &Option<String>) -> ProcessWrapper {
     let contents = match fs::read_to_string(path) {
         Ok(contents) => contents,
         Err(err) => crashln!(
@@ -55,7 +363,12 @@ pub fn read_hcl(path: &String) {
         ),
     };
 
-    let hcl_parsed: ProcessWrapper = match hcl::from_str(&contents) {
+    let body = hcl::parse(&contents).map(|mut body| {
+        rename_profiles(&mut body);
+        body
+    });
+
+    let mut hcl_parsed: ProcessWrapper = match body.and_then(hcl::from_body) {
         Ok(hcl) => hcl,
         Err(err) => crashln!(
             "{} Cannot parse imported file.\n{}",
@@ -64,35 +377,311 @@ pub fn read_hcl(path: &String) {
         ),
     };
 
-    for (name, item) in hcl_parsed.list {
-        let mut runner = Runner::new();
-        let server_name = &item.server.clone().unwrap_or("local".into());
-        let (kind, list_name) = super::format(server_name);
+    for (name, item) in &hcl_parsed.list {
+        if let Some(Err(err)) = item.cron.as_deref().map(cron::parse) {
+            crashln!("{} Cannot import {name}.\n{}", *helpers::FAIL, err.white())
+        }
+
+        if let Some(Err(err)) = item.stop_signal.as_deref().map(stop::parse_signal) {
+            crashln!("{} Cannot import {name}.\n{}", *helpers::FAIL, err.white())
+        }
 
-        runner = super::Internal {
-            id: 0,
-            server_name,
-            kind: kind.clone(),
-            runner: runner.clone(),
+        if let Some(Err(err)) = item.kill_timeout.map(stop::check_kill_timeout) {
+            crashln!("{} Cannot import {name}.\n{}", *helpers::FAIL, err.white())
         }
-        .create(
-            &item.script,
-            &Some(name.clone()),
-            &item.get_watch_path(),
-            true,
+
+        if let Some(memory) = item
+            .cgroup
+            .as_ref()
+            .and_then(|limits| limits.memory.as_ref())
+            && helpers::parse_size(memory).is_none()
+        {
+            let err = format!("cannot parse cgroup memory '{memory}'");
+            crashln!("{} Cannot import {name}.\n{}", *helpers::FAIL, err.white())
+        }
+
+        for probe in [&item.liveness, &item.readiness].into_iter().flatten() {
+            if let Err(err) = probe.probe() {
+                crashln!("{} Cannot import {name}.\n{}", *helpers::FAIL, err.white())
+            }
+        }
+
+        if item.instances.unwrap_or(1) > 1
+            && let Some(logs) = &item.logs
+            && (logs.out.is_some() || logs.error.is_some())
+        {
+            let err = "custom log files would be shared by every instance";
+            crashln!("{} Cannot import {name}.\n{}", *helpers::FAIL, err.white())
+        }
+
+        let cwd = item.cwd().unwrap_or_else(file::cwd);
+        for env_file in &item.env_file {
+            if let Err(err) = environ::read(&cwd.join(env_file)) {
+                crashln!("{} Cannot import {name}.\n{}", *helpers::FAIL, err.white())
+            }
+        }
+
+        // exports written with --redact hold placeholders instead of secrets
+        let placeholders = (item.env.iter())
+            .chain(item.profiles.values().flatten())
+            .filter(|(_, value)| *value == environ::REDACTED);
+
+        for (key, _) in placeholders {
+            println!(
+                "{} {name} sets {key} to the placeholder {}, fill in the value",
+                *helpers::WARN,
+                environ::REDACTED
+            );
+        }
+    }
+
+    if let Some(profile) = profile {
+        let mut found = false;
+
+        for item in hcl_parsed.list.values_mut() {
+            if item.profiles.contains_key(profile) {
+                item.profile = Some(profile.clone());
+                found = true;
+            }
+        }
+
+        then!(
+            !found,
+            crashln!(
+                "{} Cannot import {path}.\n{}",
+                *helpers::FAIL,
+                format!("no process has the env profile '{profile}'").white()
+            )
         );
+    }
 
-        println!("{} Imported {kind}process {name}", *helpers::SUCCESS);
+    if let Err(err) = hcl_parsed.order() {
+        crashln!("{} Cannot import {path}.\n{}", *helpers::FAIL, err.white())
+    }
 
-        match runner.find(&name, server_name) {
-            Some(id) => {
-                let mut p = runner.get(id);
-                p.stop();
-                p.set_env(item.env);
-                p.restart();
+    hcl_parsed
+}
+
+/// Processes currently known to a server
+fn connect(server_name: &String) -> Runner {
+    if matches!(&**server_name, "internal" | "local") {
+        return Runner::new();
+    }
+
+    let Some(servers) = config::servers().servers else {
+        crashln!("{} Failed to read servers", *helpers::FAIL)
+    };
+
+    match servers.get(server_name) {
+        Some(server) => match Runner::connect(server_name.clone(), server.get(), false) {
+            // secrets can only be compared when the token may reveal them
+            Some(mut remote) => {
+                let _ = remote.reveal();
+                remote
             }
-            None => crashln!("{} Failed to write to ({name})", *helpers::FAIL),
+            None => crashln!(
+                "{} Failed to connect (name={server_name}, address={})",
+                *helpers::FAIL,
+                server.address
+            ),
+        },
+        None => crashln!("{} Server '{server_name}' does not exist", *helpers::FAIL),
+    }
+}
+
+/// Compare the file with the running processes of every server it uses
+fn plan(wrapper: &ProcessWrapper, prune: bool) -> Vec<Step> {
+    let mut runners: BTreeMap<String, Runner> = BTreeMap::new();
+
+    for item in wrapper.list.values() {
+        let server = item.server();
+        if !runners.contains_key(&server) {
+            runners.insert(server.clone(), connect(&server));
         }
+    }
+
+    steps(wrapper, &runners, prune)
+}
+
+fn steps(wrapper: &ProcessWrapper, runners: &BTreeMap<String, Runner>, prune: bool) -> Vec<Step> {
+    let mut steps = vec![];
+
+    for (name, item) in wrapper.order().unwrap_or_default() {
+        let server = item.server();
+        let list = &runners[&server].list;
+        let (ids, group): (Vec<usize>, Vec<_>) =
+            list.iter().filter(|(_, p)| &p.name == name).unzip();
+
+        let change = match group.is_empty() {
+            true => Change::Create,
+            false => Change::Update(item.changes(&group, matches!(&*server, "local"))),
+        };
+
+        steps.push(Step {
+            name: name.clone(),
+            server,
+            ids,
+            change,
+        });
+    }
+
+    if prune {
+        for (server, runner) in runners {
+            let mut removed: BTreeMap<&String, Vec<usize>> = BTreeMap::new();
+
+            for (id, process) in &runner.list {
+                if !wrapper.list.contains_key(&process.name) {
+                    removed.entry(&process.name).or_default().push(*id);
+                }
+            }
+
+            for (name, ids) in removed {
+                steps.push(Step {
+                    name: name.clone(),
+                    server: server.clone(),
+                    ids,
+                    change: Change::Delete,
+                });
+            }
+        }
+    }
+
+    steps
+}
+
+/// Print the planned steps, returns false when there is nothing to do
+fn print_plan(steps: &[Step]) -> bool {
+    let (mut created, mut updated, mut deleted, mut unchanged) = (0, 0, 0, 0);
+
+    for step in steps {
+        let target = format!("{} ({})", step.name, step.server);
+
+        match &step.change {
+            Change::Create => {
+                created += 1;
+                println!("  {} {target}", "+".green().bold());
+            }
+            Change::Update(changes) if changes.is_empty() => unchanged += 1,
+            Change::Update(changes) => {
+                updated += 1;
+                let action = match &step.change {
+                    change if change.recreates() => " (recreate)",
+                    change if change.restarts() => " (restart)",
+                    _ => "",
+                };
+
+                println!(
+                    "  {} {target} {}",
+                    "~".yellow().bold(),
+                    format!("changed: {}{action}", changes.join(", ")).white()
+                );
+            }
+            Change::Delete => {
+                deleted += 1;
+                println!("  {} {target}", "-".red().bold());
+            }
+        }
+    }
+
+    println!(
+        "{} {created} to create, {updated} to update, {deleted} to delete, {unchanged} unchanged",
+        *helpers::SUCCESS
+    );
+
+    created + updated + deleted > 0
+}
+
+fn create(name: &String, item: &Process) {
+    let server_name = &item.server();
+    let (kind, _) = super::format(server_name);
+
+    if item.wait_ready.unwrap_or_default() && !item.depends_on.is_empty() {
+        println!(
+            "{} Waiting for {} before starting {name}",
+            *helpers::SUCCESS,
+            item.depends_on.join(", ")
+        );
+
+        if let Err(err) = depends::wait(&item.depends_on, || connect(server_name)) {
+            println!("{} {err}, starting {name} anyway", *helpers::WARN);
+        }
+    }
+
+    let runner = super::Internal {
+        id: 0,
+        server_name,
+        kind,
+        runner: Runner::new(),
+    }
+    .create(
+        &item.script,
+        &Some(name.clone()),
+        &item.get_watch_path(),
+        item.instances.unwrap_or(1),
+        &item.options(),
+        true,
+    );
+
+    then!(
+        runner.find_all(name, server_name).is_empty(),
+        crashln!("{} Failed to write to ({name})", *helpers::FAIL)
+    );
+}
+
+/// Apply the file to a running group in place, so its processes keep their
+/// ids, restart counts and exit history
+fn update(step: &Step, item: &Process) {
+    let local = matches!(&*step.server, "local");
+    let mut runner = connect(&step.server);
+    let unwatched = watch::Settings {
+        path: Some(string!()),
+        ..Default::default()
+    };
+
+    let options = Options {
+        script: Some(ternary!(
+            local,
+            local_script(&item.script),
+            item.script.clone()
+        )),
+        watch: Some(item.watch.as_ref().map_or(unwatched, Watch::settings)),
+        ..item.options()
+    };
+
+    for id in &step.ids {
+        let mut process = runner.get(*id);
+        process.set_options(&options);
+        then!(step.change.restarts(), process.restart());
+        runner = process.get_runner().clone();
+    }
+}
+
+fn remove(step: &Step) {
+    let (kind, _) = super::format(&step.server);
+
+    for id in &step.ids {
+        super::Internal {
+            id: *id,
+            runner: Runner::new(),
+            server_name: &step.server,
+            kind: kind.clone(),
+        }
+        .remove();
+    }
+}
+
+pub fn read_hcl(path: &String, profile: &Option<String>) {
+    let mut servers: Vec<String> = vec![];
+
+    println!("{} Applying action importProcess", *helpers::SUCCESS);
+
+    let wrapper = parse(path, profile);
+
+    for (name, item) in wrapper.order().unwrap_or_default() {
+        let (kind, list_name) = super::format(&item.server());
+
+        create(name, item);
+        println!("{} Imported {kind}process {name}", *helpers::SUCCESS);
 
         if !servers.contains(&list_name) {
             servers.push(list_name);
@@ -108,7 +697,58 @@ pub fn read_hcl(path: &String) {
     );
 }
 
-pub fn export_hcl(item: &Item, path: &Option<String>) {
+/// Print what `apply` would change without touching any process
+pub fn diff(path: &String, prune: &bool, profile: &Option<String>) {
+    println!("{} Planned changes for {path}", *helpers::SUCCESS);
+    then!(
+        !print_plan(&plan(&parse(path, profile), *prune)),
+        println!("{} Processes match {path}", *helpers::SUCCESS)
+    );
+}
+
+/// Reconcile the running processes with a file
+pub fn apply(path: &String, prune: &bool, profile: &Option<String>) {
+    let wrapper = parse(path, profile);
+    let steps = plan(&wrapper, *prune);
+    let mut servers: Vec<String> = vec![];
+
+    println!("{} Applying changes from {path}", *helpers::SUCCESS);
+
+    if !print_plan(&steps) {
+        return println!("{} Processes match {path}", *helpers::SUCCESS);
+    }
+
+    for step in &steps {
+        match &step.change {
+            Change::Create => create(&step.name, &wrapper.list[&step.name]),
+            Change::Update(changes) if changes.is_empty() => continue,
+            change if change.recreates() => {
+                remove(step);
+                create(&step.name, &wrapper.list[&step.name]);
+            }
+            Change::Update(_) => update(step, &wrapper.list[&step.name]),
+            Change::Delete => remove(step),
+        }
+
+        if !servers.contains(&step.server) {
+            servers.push(step.server.clone());
+        }
+    }
+
+    if steps
+        .iter()
+        .any(|step| step.change == Change::Delete || step.change.recreates())
+    {
+        crate::daemon::reset();
+    }
+
+    servers
+        .iter()
+        .for_each(|server| super::Internal::list(&string!("default"), server));
+    println!("{} Applied {path} ✓", *helpers::SUCCESS);
+}
+
+pub fn export_hcl(item: &Item, path: &Option<String>, redact: bool) {
     println!("{} Applying action exportProcess", *helpers::SUCCESS);
 
     let runner = Runner::new();
@@ -116,6 +756,7 @@ pub fn export_hcl(item: &Item, path: &Option<String>) {
     let fetch_process = |id: usize| {
         let process = runner.try_info(id);
         let mut watch_parsed = None;
+        let mut restart_parsed = None;
         let mut env_parsed = HashMap::new();
 
         let current_env: HashMap<String, String> = std::env::vars().collect();
@@ -126,10 +767,34 @@ pub fn export_hcl(item: &Item, path: &Option<String>) {
         if process.watch.enabled {
             watch_parsed = Some(Watch {
                 path: process.watch.path.clone(),
+                paths: process.watch.paths.clone(),
+                ignore: process.watch.ignore.clone(),
+                debounce: Some(process.watch.debounce),
+                delay: Some(process.watch.delay),
             })
         }
 
+        if process.policy != Policy::default() {
+            restart_parsed = Some(process.policy.clone());
+        }
+
+        let instances = runner.find_all(&process.name, &string!("internal")).len();
+        let depends = process.depends.clone().unwrap_or_default();
+        let stop = process.stop.clone().unwrap_or_default();
+        let health = process.health.as_ref();
+        let liveness_parsed = health.and_then(|health| health.liveness.as_ref().map(Probe::from));
+        let readiness_parsed = health.and_then(|health| health.readiness.as_ref().map(Probe::from));
+        let logs_parsed =
+            (process.out_file.is_some() || process.error_file.is_some()).then(|| Logs {
+                out: process.out_file.clone(),
+                error: process.error_file.clone(),
+            });
+
         for (key, value) in process.env.clone() {
+            if key == "PMC_INSTANCE_ID" {
+                continue;
+            }
+
             if let Some(current_value) = current_env.get(&key) {
                 if current_value != &value {
                     env_parsed.insert(key, value);
@@ -139,15 +804,57 @@ pub fn export_hcl(item: &Item, path: &Option<String>) {
             }
         }
 
-        let data = hcl::block! {
+        env_parsed.extend(process.declared.vars.clone());
+
+        let mut profiles = process.declared.profiles.clone();
+        if redact {
+            let patterns = config::read().runner.redact;
+            environ::redact(&patterns, &mut env_parsed);
+            profiles
+                .values_mut()
+                .for_each(|env| environ::redact(&patterns, env));
+        }
+
+        let env_files: Vec<String> = (process.declared.files.iter())
+            .map(|file| file.to_string_lossy().into_owned())
+            .collect();
+
+        let mut data = hcl::block! {
             process (process.name.clone()) {
                 script = (process.script.clone())
                 server = ("")
+                cwd = (process.path.to_string_lossy().into_owned())
+                instances = (instances)
                 watch = (watch_parsed)
+                restart = (restart_parsed)
+                max_memory = (process.max_memory.clone())
+                max_cpu = (process.max_cpu.clone())
+                cron = (process.cron.clone())
+                logs = (logs_parsed)
+                liveness = (liveness_parsed)
+                readiness = (readiness_parsed)
+                depends_on = (depends.on)
+                wait_ready = (depends.wait)
+                stop_signal = (stop.signal)
+                kill_timeout = (stop.kill_timeout)
+                cgroup = (process.limits.clone())
+                env_mode = (process.env_mode.to_string())
+                env_file = (env_files)
                 env = (env_parsed)
             }
         };
 
+        for (profile, env) in &profiles {
+            let block = hcl::Block::builder("env")
+                .add_label(profile.as_str())
+                .add_attributes(
+                    env.iter()
+                        .map(|(key, value)| (key.as_str(), value.as_str())),
+                )
+                .build();
+            data.body.0.push(block.into());
+        }
+
         let serialized = hcl::to_string(&data).unwrap();
 
         if Exists::check(&path).file() {
@@ -178,3 +885,186 @@ pub fn export_hcl(item: &Item, path: &Option<String>) {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use pmc::process::{Source, id::Id, schema};
+
+    fn file(contents: &str) -> ProcessWrapper {
+        hcl::from_str(contents).unwrap()
+    }
+
+    fn running(id: usize, name: &str, script: &str) -> pmc::process::Process {
+        pmc::process::Process {
+            id,
+            name: name.to_string(),
+            path: PathBuf::from("/tmp"),
+            script: script.to_string(),
+            running: true,
+            started: Utc::now(),
+            ..Default::default()
+        }
+    }
+
+    fn runners(processes: Vec<pmc::process::Process>) -> BTreeMap<String, Runner> {
+        let runner = Runner {
+            version: schema::VERSION,
+            id: Id::new(processes.len()),
+            list: (processes.into_iter())
+                .map(|process| (process.id, process))
+                .collect(),
+            remote: None,
+            source: Source::Memory,
+            caller: None,
+            guard: None,
+        };
+
+        BTreeMap::from([(string!("local"), runner)])
+    }
+
+    fn changes(contents: &str, current: &pmc::process::Process) -> Vec<&'static str> {
+        file(contents).list["api"].changes(&[current], false)
+    }
+
+    #[test]
+    fn test_changes() {
+        let current = running(0, "api", "node api.js");
+
+        assert!(changes(r#"process "api" { script = "node api.js" }"#, &current).is_empty());
+        assert_eq!(
+            changes(r#"process "api" { script = "node server.js" }"#, &current),
+            vec!["script"]
+        );
+        assert_eq!(
+            changes(
+                r#"process "api" {
+                    script = "node api.js"
+                    cwd = "/srv/api"
+                }"#,
+                &current
+            ),
+            vec!["cwd"]
+        );
+        assert_eq!(
+            changes(
+                r#"process "api" {
+                    script = "node api.js"
+                    instances = 2
+                }"#,
+                &current
+            ),
+            vec!["instances"]
+        );
+        assert_eq!(
+            changes(
+                r#"process "api" {
+                    script = "node api.js"
+                    cron = "0 4 * * *"
+                }"#,
+                &current
+            ),
+            vec!["cron"]
+        );
+    }
+
+    #[test]
+    fn test_changes_env() {
+        let mut current = running(0, "api", "node api.js");
+        current.declared.vars = Env::from([
+            (string!("PORT"), string!("3000")),
+            (string!("DEBUG"), string!("1")),
+        ]);
+
+        let env = |vars: &str| {
+            let contents = format!(
+                r#"process "api" {{
+                    script = "node api.js"
+                    env = {{ {vars} }}
+                }}"#
+            );
+            changes(&contents, &current)
+        };
+
+        assert!(env(r#"PORT = "3000", DEBUG = "1""#).is_empty());
+        assert_eq!(env(r#"PORT = "4000", DEBUG = "1""#), vec!["env"]);
+        assert_eq!(env(r#"PORT = "3000""#), vec!["env"]);
+    }
+
+    #[test]
+    fn test_changes_redacted() {
+        let mut current = running(0, "api", "node api.js");
+        current.declared.vars = Env::from([
+            (string!("PORT"), string!("3000")),
+            (string!("API_TOKEN"), string!(environ::REDACTED)),
+        ]);
+
+        let env = |vars: &str| {
+            let contents = format!(
+                r#"process "api" {{
+                    script = "node api.js"
+                    env = {{ {vars} }}
+                }}"#
+            );
+            changes(&contents, &current)
+        };
+
+        assert!(env(r#"PORT = "3000", API_TOKEN = "abc""#).is_empty());
+        assert_eq!(env(r#"PORT = "4000", API_TOKEN = "abc""#), vec!["env"]);
+        assert_eq!(env(r#"PORT = "3000""#), vec!["env"]);
+    }
+
+    #[test]
+    fn test_change_actions() {
+        let change = |fields: &[&'static str]| Change::Update(fields.to_vec());
+
+        assert!(change(&["script"]).restarts());
+        assert!(change(&["env", "cron"]).restarts());
+        assert!(!change(&["readiness", "cgroup"]).restarts());
+        assert!(change(&["instances", "script"]).recreates());
+        assert!(!change(&["script"]).recreates());
+        assert!(!Change::Create.restarts() && !Change::Delete.recreates());
+    }
+
+    #[test]
+    fn test_steps() {
+        let wrapper = file(
+            r#"
+            process "api" { script = "node api.js" }
+            process "web" {
+                script = "node web.js"
+                depends_on = ["api"]
+            }
+            "#,
+        );
+        let mut api = running(0, "api", "node server.js");
+        api.instance = Some(0);
+        let mut api_next = api.clone();
+        (api_next.id, api_next.instance) = (1, Some(1));
+        let current = runners(vec![api, api_next, running(2, "old", "node old.js")]);
+
+        let plan: Vec<(String, Vec<usize>, Change)> = steps(&wrapper, &current, false)
+            .into_iter()
+            .map(|step| (step.name, step.ids, step.change))
+            .collect();
+
+        assert_eq!(
+            plan,
+            vec![
+                (
+                    string!("api"),
+                    vec![0, 1],
+                    Change::Update(vec!["script", "instances"])
+                ),
+                (string!("web"), vec![], Change::Create),
+            ]
+        );
+
+        let pruned = steps(&wrapper, &current, true);
+        let deleted = pruned.last().unwrap();
+        assert_eq!(pruned.len(), 3);
+        assert_eq!((&*deleted.name, &deleted.ids), ("old", &vec![2]));
+        assert_eq!(deleted.change, Change::Delete);
+    }
+}
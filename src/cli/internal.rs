@@ -1,3 +1,4 @@
+use chrono::Utc;
 use colored::Colorize;
 use futures::{StreamExt, stream::FuturesUnordered};
 use macros_rs::{crashln, string, ternary, then};
@@ -13,9 +14,18 @@ use pmc::{
     config, file,
     helpers::{self, ColoredString},
     log,
-    process::{ItemSingle, Runner, get_process_cpu_usage_percentage, http},
+    process::{
+        ItemSingle, Options, Runner, collect, depends, dump,
+        environ::{self, Declared},
+        exit::Exit,
+        http,
+        ready::Ready,
+        rotate,
+    },
 };
 
+use super::OptionArgs;
+
 use tabled::{
     Table, Tabled,
     settings::{
@@ -26,6 +36,29 @@ use tabled::{
     },
 };
 
+/// Script as it runs on the local runner, bare js and ts files go through node
+pub(crate) fn local_script(script: &str) -> String {
+    let pattern = Regex::new(r"(?m)^[a-zA-Z0-9]+(/[a-zA-Z0-9]+)*(\.js|\.ts)?$").unwrap();
+
+    match pattern.is_match(script) {
+        true => format!("{} {script}", config::read().runner.node),
+        false => script.to_string(),
+    }
+}
+
+/// The first error resolving the declared env with these settings would give
+fn env_error(
+    declared: &Declared,
+    settings: &Option<environ::Settings>,
+    cwd: &std::path::Path,
+) -> Option<String> {
+    let settings = settings.as_ref()?;
+    let mut declared = declared.clone();
+    settings.apply(&mut declared);
+
+    declared.resolve(cwd).1.into_iter().next()
+}
+
 pub struct Internal<'i> {
     pub id: usize,
     pub runner: Runner,
@@ -43,6 +76,34 @@ struct WsFrame {
     message: Option<String>,
 }
 
+/// Status of a process colored for the tables
+fn status(item: &pmc::process::Process) -> colored::ColoredString {
+    let status = format!("{}   ", item.status());
+
+    match item.status() {
+        "online" => status.green().bold(),
+        "unready" => status.yellow().bold(),
+        _ => status.red().bold(),
+    }
+}
+
+/// Resident memory of a process for the tables, with its cgroup charge next to it
+fn format_usage(usage: &MemoryInfo) -> String {
+    match usage.cgroup {
+        Some(cgroup) => format!(
+            "{} (cgroup {})",
+            helpers::format_memory(usage.rss),
+            helpers::format_memory(cgroup)
+        ),
+        None => helpers::format_memory(usage.rss),
+    }
+}
+
+/// A probe of a process for the tables
+fn probe(probe: Option<&pmc::process::health::Probe>) -> String {
+    probe.map_or(string!("none"), |probe| probe.to_string())
+}
+
 fn ws_scheme(address: &str) -> String {
     if address.starts_with("https://") {
         address.replacen("https://", "wss://", 1)
@@ -213,23 +274,38 @@ impl<'i> Internal<'i> {
         script: &String,
         name: &Option<String>,
         watch: &Option<String>,
+        instances: usize,
+        options: &Options,
         silent: bool,
     ) -> Runner {
-        let config = config::read();
         let name = match name {
             Some(name) => string!(name),
             None => string!(script.split_whitespace().next().unwrap_or_default()),
         };
 
+        let path = options.cwd.clone().unwrap_or_else(file::cwd);
+
         if matches!(self.server_name, "internal" | "local") {
-            let pattern = Regex::new(r"(?m)^[a-zA-Z0-9]+(/[a-zA-Z0-9]+)*(\.js|\.ts)?$").unwrap();
+            if let Some(on) = &options.depends_on
+                && let Err(err) = depends::check(&self.runner, &name, on)
+            {
+                crashln!("{} Cannot create {name}.\n{}", *helpers::FAIL, err.white())
+            }
 
-            if pattern.is_match(script) {
-                let script = format!("{} {script}", config.runner.node);
-                self.runner.start(&name, &script, file::cwd(), watch).save();
-            } else {
-                self.runner.start(&name, script, file::cwd(), watch).save();
+            if let Some(err) = env_error(&Declared::default(), &options.env, &path) {
+                crashln!("{} Cannot create {name}.\n{}", *helpers::FAIL, err.white())
             }
+
+            self.runner
+                .start(
+                    &name,
+                    &local_script(script),
+                    path,
+                    watch,
+                    instances,
+                    options,
+                )
+                .save();
         } else {
             let Some(servers) = config::servers().servers else {
                 crashln!("{} Failed to read servers", *helpers::FAIL)
@@ -237,7 +313,9 @@ impl<'i> Internal<'i> {
 
             if let Some(server) = servers.get(self.server_name) {
                 match Runner::connect(self.server_name.into(), server.get(), false) {
-                    Some(mut remote) => remote.start(&name, script, file::cwd(), watch),
+                    Some(mut remote) => {
+                        remote.start(&name, script, path, watch, instances, options)
+                    }
                     None => crashln!(
                         "{} Failed to connect (name={}, address={})",
                         *helpers::FAIL,
@@ -257,9 +335,10 @@ impl<'i> Internal<'i> {
         then!(
             !silent,
             println!(
-                "{} Creating {}process with ({name})",
+                "{} Creating {}process with ({name}){}",
                 *helpers::SUCCESS,
-                self.kind
+                self.kind,
+                ternary!(instances > 1, format!(" x{instances}"), string!())
             )
         );
         then!(
@@ -274,6 +353,7 @@ impl<'i> Internal<'i> {
         mut self,
         name: &Option<String>,
         watch: &Option<String>,
+        options: &OptionArgs,
         reset_env: bool,
         silent: bool,
     ) -> Runner {
@@ -288,6 +368,27 @@ impl<'i> Internal<'i> {
         );
 
         if matches!(self.server_name, "internal" | "local") {
+            let options = options.merge(Some(self.runner.try_info(self.id)));
+
+            if let Some(on) = &options.depends_on {
+                let current = &self.runner.try_info(self.id).name;
+                let name = name.as_ref().unwrap_or(current);
+
+                if let Err(err) = depends::check(&self.runner, name, on) {
+                    crashln!("{} Cannot restart {name}.\n{}", *helpers::FAIL, err.white())
+                }
+            }
+
+            let process = self.runner.try_info(self.id);
+            if let Some(err) = env_error(&process.declared, &options.env, &process.path) {
+                crashln!(
+                    "{} Cannot restart {}.\n{}",
+                    *helpers::FAIL,
+                    process.name,
+                    err.white()
+                )
+            }
+
             let mut item = self.runner.get(self.id);
 
             match watch {
@@ -297,6 +398,8 @@ impl<'i> Internal<'i> {
 
             then!(reset_env, item.clear_env());
 
+            item.set_options(&options);
+
             if let Some(n) = name.as_ref() {
                 item.rename(n.trim().replace("\n", ""))
             }
@@ -311,6 +414,15 @@ impl<'i> Internal<'i> {
             if let Some(server) = servers.get(self.server_name) {
                 match Runner::connect(self.server_name.into(), server.get(), false) {
                     Some(remote) => {
+                        then!(
+                            options.is_set(),
+                            println!(
+                                "{} Process options are not sent to {}servers",
+                                *helpers::WARN,
+                                self.kind
+                            )
+                        );
+
                         let mut item = remote.get(self.id);
 
                         then!(reset_env, item.clear_env());
@@ -349,6 +461,72 @@ impl<'i> Internal<'i> {
         self.runner
     }
 
+    pub fn reload(mut self, ready: &Option<Ready>, silent: bool) -> Runner {
+        then!(
+            !silent,
+            println!(
+                "{} Applying {}action reloadProcess on ({})",
+                *helpers::SUCCESS,
+                self.kind,
+                self.id
+            )
+        );
+
+        if !matches!(self.server_name, "internal" | "local") {
+            let Some(servers) = config::servers().servers else {
+                crashln!("{} Failed to read servers", *helpers::FAIL)
+            };
+
+            if let Some(server) = servers.get(self.server_name) {
+                self.runner = match Runner::connect(self.server_name.into(), server.get(), false) {
+                    Some(remote) => remote,
+                    None => crashln!(
+                        "{} Failed to connect (name={}, address={})",
+                        *helpers::FAIL,
+                        self.server_name,
+                        server.address
+                    ),
+                };
+            } else {
+                crashln!(
+                    "{} Server '{}' does not exist",
+                    *helpers::FAIL,
+                    self.server_name
+                )
+            };
+
+            then!(
+                ready.is_some(),
+                println!(
+                    "{} Readiness options are not sent to {}servers",
+                    *helpers::WARN,
+                    self.kind
+                )
+            );
+        }
+
+        let mut item = self.runner.get(self.id);
+
+        if let Some(ready) = ready {
+            item.set_ready(ready.clone());
+        }
+
+        item.reload();
+        self.runner = item.get_runner().clone();
+
+        if !silent {
+            println!(
+                "{} Reloaded {}({}) ✓",
+                *helpers::SUCCESS,
+                self.kind,
+                self.id
+            );
+            log!("process reloaded {}(id={})", self.kind, self.id);
+        }
+
+        self.runner
+    }
+
     pub fn stop(mut self, silent: bool) -> Runner {
         then!(
             !silent,
@@ -384,12 +562,43 @@ impl<'i> Internal<'i> {
             };
         }
 
+        let exits = self.runner.try_info(self.id).exits.len();
         let mut item = self.runner.get(self.id);
         item.stop();
         self.runner = item.get_runner().clone();
 
         if !silent {
-            println!("{} Stopped {}({}) ✓", *helpers::SUCCESS, self.kind, self.id);
+            // the last exit only belongs to this stop when it was recorded just now
+            let exit = self
+                .runner
+                .info(self.id)
+                .map(|p| &p.exits)
+                .filter(|list| list.len() > exits);
+
+            let stale = self.runner.info(self.id).is_some_and(|p| p.stale);
+
+            match exit.and_then(|list| list.last()) {
+                _ if stale => println!(
+                    "{} Stopped {}({}), pid {} belongs to another process now and was not signalled",
+                    *helpers::WARN,
+                    self.kind,
+                    self.id,
+                    self.runner.pid(self.id)
+                ),
+                Some(exit) if exit.forced => println!(
+                    "{} Stopped {}({}), killed after the kill timeout",
+                    *helpers::WARN,
+                    self.kind,
+                    self.id
+                ),
+                Some(_) => println!(
+                    "{} Stopped {}({}) gracefully ✓",
+                    *helpers::SUCCESS,
+                    self.kind,
+                    self.id
+                ),
+                None => println!("{} Stopped {}({}) ✓", *helpers::SUCCESS, self.kind, self.id),
+            }
             log!("process stopped {}(id={})", self.kind, self.id);
         }
 
@@ -475,7 +684,7 @@ impl<'i> Internal<'i> {
         log!("process logs cleaned (id={})", self.id);
     }
 
-    pub fn info(&self, format: &String) {
+    pub fn info(&self, format: &str) {
         #[derive(Clone, Debug, Tabled)]
         struct Info {
             #[tabled(rename = "error log path ")]
@@ -486,10 +695,29 @@ impl<'i> Internal<'i> {
             cpu_percent: String,
             #[tabled(rename = "memory usage")]
             memory_usage: String,
+            #[tabled(rename = "memory limit")]
+            memory_limit: String,
+            #[tabled(rename = "cpu limit")]
+            cpu_limit: String,
+            readiness: String,
+            liveness: String,
+            #[tabled(rename = "depends on")]
+            depends: String,
+            #[tabled(rename = "stop signal")]
+            stop: String,
+            #[tabled(rename = "cgroup limits")]
+            limits: String,
+            #[tabled(rename = "env mode")]
+            env_mode: String,
+            #[tabled(rename = "declared env")]
+            declared: String,
+            cgroup: String,
             #[tabled(rename = "path hash")]
             hash: String,
             #[tabled(rename = "watching")]
             watch: String,
+            #[tabled(rename = "watch settings")]
+            watch_settings: String,
             children: String,
             #[tabled(rename = "exec cwd")]
             path: String,
@@ -497,11 +725,24 @@ impl<'i> Internal<'i> {
             command: String,
             #[tabled(rename = "script id")]
             id: String,
+            #[tabled(rename = "last exit")]
+            last_exit: String,
             restarts: u64,
             uptime: String,
             pid: String,
+            instance: String,
             name: String,
             status: ColoredString,
+            #[tabled(skip)]
+            exits: Vec<Exit>,
+        }
+
+        #[derive(Tabled)]
+        struct ExitRow {
+            time: String,
+            code: String,
+            signal: String,
+            uptime: String,
         }
 
         impl Serialize for Info {
@@ -510,10 +751,13 @@ impl<'i> Internal<'i> {
                      "id": &self.id.trim(),
                      "pid": &self.pid.trim(),
                      "name": &self.name.trim(),
+                     "instance": &self.instance.trim(),
                      "path": &self.path.trim(),
                      "restarts": &self.restarts,
+                     "exits": &self.exits,
                      "hash": &self.hash.trim(),
                      "watch": &self.watch.trim(),
+                     "watch_settings": &self.watch_settings,
                      "children": &self.children,
                      "uptime": &self.uptime.trim(),
                      "status": &self.status.0.trim(),
@@ -521,6 +765,16 @@ impl<'i> Internal<'i> {
                      "cpu": &self.cpu_percent.trim(),
                      "command": &self.command.trim(),
                      "mem": &self.memory_usage.trim(),
+                     "max_memory": &self.memory_limit.trim(),
+                     "max_cpu": &self.cpu_limit.trim(),
+                     "liveness": &self.liveness,
+                     "readiness": &self.readiness,
+                     "depends_on": &self.depends,
+                     "stop": &self.stop,
+                     "cgroup_limits": &self.limits,
+                     "env_mode": &self.env_mode,
+                     "declared_env": &self.declared,
+                     "cgroup": &self.cgroup,
                      "log_error": &self.log_error.trim(),
                 });
 
@@ -537,7 +791,7 @@ impl<'i> Internal<'i> {
                 .to_string();
 
             if let Ok(json) = serde_json::to_string(&data[0]) {
-                match format.as_str() {
+                match format {
                     "raw" => println!("{:?}", data[0]),
                     "json" => println!("{json}"),
                     _ => {
@@ -547,6 +801,23 @@ impl<'i> Internal<'i> {
                                 .on_bright_white()
                                 .black()
                         );
+
+                        if !data[0].exits.is_empty() {
+                            let rows = data[0].exits.iter().rev().map(|exit| ExitRow {
+                                time: exit.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                                code: exit.code.map_or(string!("-"), |code| code.to_string()),
+                                signal: exit.signal.clone().unwrap_or(string!("-")),
+                                uptime: helpers::format_seconds((exit.uptime / 1000) as i64),
+                            });
+
+                            let history = Table::new(rows)
+                                .with(Style::rounded().remove_horizontals())
+                                .with(BorderColor::filled(Color::FG_BRIGHT_BLACK))
+                                .to_string();
+
+                            println!("{}\n{history}\n", "Exit history".on_bright_white().black());
+                        }
+
                         println!(
                             " {}",
                             format!("Use `pmc logs {} [--lines <num>]` to display logs", self.id)
@@ -583,9 +854,9 @@ impl<'i> Internal<'i> {
                     format!("{:?}", item.children)
                 };
 
-                if let Ok(process) = Process::new(item.pid as u32) {
-                    memory_usage = process.memory_info().ok().map(MemoryInfo::from);
-                    cpu_percent = Some(get_process_cpu_usage_percentage(item.pid));
+                if Process::new(item.pid as u32).is_ok() {
+                    memory_usage = item.memory_usage();
+                    cpu_percent = Some(item.cpu_usage());
                 }
 
                 let cpu_percent = match cpu_percent {
@@ -594,20 +865,11 @@ impl<'i> Internal<'i> {
                 };
 
                 let memory_usage = match memory_usage {
-                    Some(usage) => helpers::format_memory(usage.rss),
+                    Some(usage) => format_usage(&usage),
                     None => string!("0b"),
                 };
 
-                let status = if item.running {
-                    "online   ".green().bold()
-                } else {
-                    match item.crash.crashed {
-                        true => "crashed   ",
-                        false => "stopped   ",
-                    }
-                    .red()
-                    .bold()
-                };
+                let status = status(item);
 
                 let data = vec![Info {
                     children,
@@ -615,7 +877,38 @@ impl<'i> Internal<'i> {
                     memory_usage,
                     id: string!(self.id),
                     restarts: item.restarts,
+                    memory_limit: item
+                        .max_memory
+                        .as_ref()
+                        .map_or(string!("none"), |memory| memory.to_string()),
+                    cpu_limit: item
+                        .max_cpu
+                        .as_ref()
+                        .map_or(string!("none"), |cpu| cpu.to_string()),
+                    liveness: probe(item.health.as_ref().and_then(|h| h.liveness.as_ref())),
+                    readiness: probe(item.health.as_ref().and_then(|h| h.readiness.as_ref())),
+                    depends: (item.depends.as_ref())
+                        .map_or(string!("none"), |depends| depends.to_string()),
+                    stop: item.stop.clone().unwrap_or_default().to_string(),
+                    limits: (item.limits.as_ref())
+                        .map_or(string!("none"), |limits| limits.to_string()),
+                    env_mode: item.env_mode.to_string(),
+                    declared: ternary!(
+                        item.declared.is_empty(),
+                        string!("none"),
+                        item.declared.to_string()
+                    ),
+                    cgroup: (item.cgroup.as_ref())
+                        .map_or(string!("none"), |path| path.display().to_string()),
+                    exits: item.exits.clone(),
+                    last_exit: item
+                        .exits
+                        .last()
+                        .map_or(string!("none"), |exit| exit.to_string()),
                     name: item.name.clone(),
+                    instance: item
+                        .instance
+                        .map_or(string!("none"), |index| index.to_string()),
                     log_out: item.logs().out,
                     path: format!("{} ", path),
                     log_error: item.logs().error,
@@ -634,9 +927,20 @@ impl<'i> Internal<'i> {
                     ),
                     watch: ternary!(
                         item.watch.enabled,
-                        format!("{path}/{}  ", item.watch.path),
+                        format!(
+                            "{}  ",
+                            (item.watch.roots())
+                                .map(|root| format!("{path}/{root}"))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
                         string!("disabled  ")
                     ),
+                    watch_settings: ternary!(
+                        item.watch.enabled,
+                        item.watch.to_string(),
+                        string!("none")
+                    ),
                     uptime: ternary!(
                         item.running,
                         format!("{}", helpers::format_duration(item.started)),
@@ -677,16 +981,7 @@ impl<'i> Internal<'i> {
             let info = http::info(&remote, self.id);
             let path = item.path.to_string_lossy().into_owned();
 
-            let status = if item.running {
-                "online   ".green().bold()
-            } else {
-                match item.crash.crashed {
-                    true => "crashed   ",
-                    false => "stopped   ",
-                }
-                .red()
-                .bold()
-            };
+            let status = status(&item);
 
             if let Ok(info) = info {
                 let stats = info.json::<ItemSingle>().unwrap().stats;
@@ -702,7 +997,7 @@ impl<'i> Internal<'i> {
                 };
 
                 let memory_usage = match stats.memory_usage {
-                    Some(usage) => helpers::format_memory(usage.rss),
+                    Some(usage) => format_usage(&usage),
                     None => string!("0b"),
                 };
 
@@ -714,14 +1009,45 @@ impl<'i> Internal<'i> {
                     path: path.clone(),
                     status: status.into(),
                     restarts: item.restarts,
+                    memory_limit: item
+                        .max_memory
+                        .as_ref()
+                        .map_or(string!("none"), |memory| memory.to_string()),
+                    cpu_limit: item
+                        .max_cpu
+                        .as_ref()
+                        .map_or(string!("none"), |cpu| cpu.to_string()),
+                    liveness: probe(item.health.as_ref().and_then(|h| h.liveness.as_ref())),
+                    readiness: probe(item.health.as_ref().and_then(|h| h.readiness.as_ref())),
+                    depends: (item.depends.as_ref())
+                        .map_or(string!("none"), |depends| depends.to_string()),
+                    stop: item.stop.clone().unwrap_or_default().to_string(),
+                    limits: (item.limits.as_ref())
+                        .map_or(string!("none"), |limits| limits.to_string()),
+                    env_mode: item.env_mode.to_string(),
+                    declared: ternary!(
+                        item.declared.is_empty(),
+                        string!("none"),
+                        item.declared.to_string()
+                    ),
+                    cgroup: (item.cgroup.as_ref())
+                        .map_or(string!("none"), |path| path.display().to_string()),
+                    exits: item.exits.clone(),
+                    last_exit: item
+                        .exits
+                        .last()
+                        .map_or(string!("none"), |exit| exit.to_string()),
                     name: item.name.clone(),
+                    instance: item
+                        .instance
+                        .map_or(string!("none"), |index| index.to_string()),
                     pid: ternary!(
                         item.running,
                         format!("{pid}", pid = item.pid),
                         string!("n/a")
                     ),
-                    log_out: format!("{}/{}-out.log", remote.config.log_path, item.name),
-                    log_error: format!("{}/{}-error.log", remote.config.log_path, item.name),
+                    log_out: format!("{}/{}-out.log", remote.config.log_path, item.log_name()),
+                    log_error: format!("{}/{}-error.log", remote.config.log_path, item.log_name()),
                     hash: ternary!(
                         item.watch.enabled,
                         format!("{}  ", item.watch.hash),
@@ -735,9 +1061,20 @@ impl<'i> Internal<'i> {
                     ),
                     watch: ternary!(
                         item.watch.enabled,
-                        format!("{path}/{}  ", item.watch.path),
+                        format!(
+                            "{}  ",
+                            (item.watch.roots())
+                                .map(|root| format!("{path}/{root}"))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
                         string!("disabled  ")
                     ),
+                    watch_settings: ternary!(
+                        item.watch.enabled,
+                        item.watch.to_string(),
+                        string!("none")
+                    ),
                     uptime: ternary!(
                         item.running,
                         format!("{}", helpers::format_duration(item.started)),
@@ -750,7 +1087,7 @@ impl<'i> Internal<'i> {
         }
     }
 
-    pub fn logs(mut self, lines: &usize) {
+    pub fn logs(mut self, lines: &usize, merged: &bool) {
         let tail = *lines;
         let mut urls: Vec<(String, String)> = Vec::new();
 
@@ -804,6 +1141,34 @@ impl<'i> Internal<'i> {
             Some(item.name.clone())
         };
 
+        if *merged {
+            let item = self
+                .runner
+                .info(self.id)
+                .unwrap_or_else(|| crashln!("{} Process ({}) not found", *helpers::FAIL, self.id));
+
+            let (out, error) = match &self.runner.remote {
+                Some(remote) => {
+                    let lines = |kind| http::logs(remote, self.id, kind).map(|log| log.lines);
+                    (
+                        lines("out").unwrap_or_default(),
+                        lines("error").unwrap_or_default(),
+                    )
+                }
+                None => (
+                    rotate::read_all(&item.logs().out),
+                    rotate::read_all(&item.logs().error),
+                ),
+            };
+
+            println!(
+                "{}",
+                format!("Showing last {lines} merged lines for {}process [{}] (change the value with --lines option)", self.kind, self.id).yellow()
+            );
+
+            return file::logs_merged(collect::merge(out, error), *lines, self.id, &item.name);
+        }
+
         if !urls.is_empty() {
             println!(
                 "{}",
@@ -868,7 +1233,7 @@ impl<'i> Internal<'i> {
         }
     }
 
-    pub fn env(mut self) {
+    pub fn env(mut self, reveal: bool) {
         println!(
             "{}",
             format!("Showing env for {}process {}:\n", self.kind, self.id).bright_yellow()
@@ -898,10 +1263,24 @@ impl<'i> Internal<'i> {
             };
         }
 
+        if reveal && let Err(err) = self.runner.reveal() {
+            crashln!(
+                "{} Failed to reveal secret values\nError: {err}",
+                *helpers::FAIL
+            )
+        }
+
         let item = self.runner.process(self.id);
-        item.env
-            .iter()
-            .for_each(|(key, value)| println!("{}: {}", key, value.green()));
+        then!(!reveal, item.redact(&config::read().runner.redact));
+
+        item.environment().iter().for_each(|(key, var)| {
+            println!(
+                "{}: {} {}",
+                key,
+                var.value.green(),
+                format!("({})", var.source).bright_black()
+            )
+        });
     }
 
     pub fn save(server_name: &String) {
@@ -913,25 +1292,83 @@ impl<'i> Internal<'i> {
         Runner::new().save();
     }
 
-    pub fn restore(server_name: &String) {
-        let mut runner = Runner::new();
+    pub fn restore(from_backup: &Option<usize>, server_name: &String) {
         let (kind, list_name) = super::format(server_name);
 
         if !matches!(&**server_name, "internal" | "local") {
             crashln!("{} Cannot restore on remote servers", *helpers::FAIL)
         }
 
-        Runner::new().list().for_each(|(id, p)| {
-            if p.running {
+        if let Some(index) = *from_backup {
+            let path = dump::backup_path(index);
+            let backup = dump::read_backup(index).unwrap_or_else(|err| {
+                crashln!(
+                    "{} Cannot read backup {path}.\n{}",
+                    *helpers::FAIL,
+                    err.white()
+                )
+            });
+
+            // stopped one at a time first, so the daemon answers in between
+            let mut current = Runner::new();
+            for (id, item) in current.items() {
+                then!(item.running, current.stop(id).save());
+            }
+
+            Runner::new().load(backup.id, backup.list).save();
+            println!("{} Loaded backup {path}", *helpers::SUCCESS);
+        }
+
+        let mut runner = Runner::new();
+        let dump = Runner::new();
+
+        // instances share their name and dependencies, the first one stands for the group
+        let mut groups: Vec<(&str, &[String])> = vec![];
+        for p in dump.list.values().filter(|p| p.running) {
+            if !groups.iter().any(|(name, _)| *name == p.name) {
+                let on = p
+                    .depends
+                    .as_ref()
+                    .map_or(&[][..], |depends| &depends.on[..]);
+                groups.push((&p.name, on));
+            }
+        }
+
+        let order = depends::order(groups).unwrap_or_else(|err| {
+            crashln!(
+                "{} Cannot restore processes.\n{}",
+                *helpers::FAIL,
+                err.white()
+            )
+        });
+
+        for name in order {
+            let group: Vec<_> = (dump.list.iter())
+                .filter(|(_, p)| p.running && p.name == name)
+                .collect();
+
+            if let Some(depends) = group[0].1.depends.as_ref().filter(|depends| depends.wait) {
+                println!(
+                    "{} Waiting for {} before starting {name}",
+                    *helpers::SUCCESS,
+                    depends.on.join(", ")
+                );
+
+                if let Err(err) = depends::wait(&depends.on, Runner::new) {
+                    println!("{} {err}, starting {name} anyway", *helpers::WARN);
+                }
+            }
+
+            for (id, _) in group {
                 runner = Internal {
                     id: *id,
                     server_name,
                     kind: kind.clone(),
                     runner: runner.clone(),
                 }
-                .restart(&None, &None, false, true);
+                .restart(&None, &None, &OptionArgs::default(), false, true);
             }
-        });
+        }
 
         println!(
             "{} Restored process statuses from dumpfile",
@@ -940,7 +1377,7 @@ impl<'i> Internal<'i> {
         Internal::list(&string!("default"), &list_name);
     }
 
-    pub fn list(format: &String, server_name: &String) {
+    pub fn list(format: &str, server_name: &String) {
         let render_list = |runner: &mut Runner, internal: bool| {
             let mut processes: Vec<ProcessItem> = Vec::new();
 
@@ -948,6 +1385,8 @@ impl<'i> Internal<'i> {
             struct ProcessItem {
                 id: ColoredString,
                 name: String,
+                #[tabled(skip)]
+                instance: Option<usize>,
                 pid: String,
                 uptime: String,
                 #[tabled(rename = "↺")]
@@ -957,6 +1396,8 @@ impl<'i> Internal<'i> {
                 mem: String,
                 #[tabled(rename = "watching")]
                 watch: String,
+                #[tabled(rename = "next run")]
+                next_run: String,
             }
 
             impl serde::Serialize for ProcessItem {
@@ -970,7 +1411,9 @@ impl<'i> Internal<'i> {
                         "id": &self.id.0.trim(),
                         "pid": &self.pid.trim(),
                         "name": &self.name.trim(),
+                        "instance": &self.instance,
                         "watch": &self.watch.trim(),
+                        "next_run": &self.next_run.trim(),
                         "uptime": &self.uptime.trim(),
                         "status": &self.status.0.trim(),
                         "restarts": &self.restarts.trim(),
@@ -989,11 +1432,8 @@ impl<'i> Internal<'i> {
                     if internal {
                         let mut usage_internals: (Option<f64>, Option<MemoryInfo>) = (None, None);
 
-                        if let Ok(process) = Process::new(item.pid as u32) {
-                            usage_internals = (
-                                Some(get_process_cpu_usage_percentage(item.pid)),
-                                process.memory_info().ok().map(MemoryInfo::from),
-                            );
+                        if Process::new(item.pid as u32).is_ok() {
+                            usage_internals = (Some(item.cpu_usage()), item.memory_usage());
                         }
 
                         cpu_percent = match usage_internals.0 {
@@ -1023,16 +1463,7 @@ impl<'i> Internal<'i> {
                         }
                     }
 
-                    let status = if item.running {
-                        "online   ".green().bold()
-                    } else {
-                        match item.crash.crashed {
-                            true => "crashed   ",
-                            false => "stopped   ",
-                        }
-                        .red()
-                        .bold()
-                    };
+                    let status = status(&item);
 
                     processes.push(ProcessItem {
                         status: status.into(),
@@ -1040,13 +1471,24 @@ impl<'i> Internal<'i> {
                         mem: format!("{memory_usage}   "),
                         id: id.to_string().cyan().bold().into(),
                         restarts: format!("{}  ", item.restarts),
-                        name: format!("{}   ", item.name.clone()),
+                        instance: item.instance,
+                        name: match item.instance {
+                            Some(index) => format!("{}:{index}   ", item.name),
+                            None => format!("{}   ", item.name),
+                        },
                         pid: ternary!(item.running, format!("{}  ", item.pid), string!("n/a  ")),
                         watch: ternary!(
                             item.watch.enabled,
                             format!("{}  ", item.watch.path),
                             string!("disabled  ")
                         ),
+                        next_run: match item.next_run {
+                            Some(next) => format!(
+                                "in {}  ",
+                                helpers::format_seconds((next - Utc::now()).num_seconds().max(0))
+                            ),
+                            None => string!("none  "),
+                        },
                         uptime: ternary!(
                             item.running,
                             format!("{}  ", helpers::format_duration(item.started)),
@@ -1063,7 +1505,7 @@ impl<'i> Internal<'i> {
                     .to_string();
 
                 if let Ok(json) = serde_json::to_string(&processes) {
-                    match format.as_str() {
+                    match format {
                         "raw" => println!("{:?}", processes),
                         "json" => println!("{json}"),
                         "default" => println!("{table}"),
@@ -1109,7 +1551,7 @@ impl<'i> Internal<'i> {
                     println!(
                         " {} {} {}",
                         "-".yellow(),
-                        format!("{}", server.0),
+                        server.0,
                         format!("[{}]", server.1).white()
                     )
                 });
@@ -1,13 +1,17 @@
 mod args;
 pub use args::*;
 
+pub(crate) mod dump;
 pub(crate) mod import;
 pub(crate) mod internal;
 pub(crate) mod server;
 
 use internal::Internal;
-use macros_rs::{crashln, string, ternary};
-use pmc::{helpers, process::Runner};
+use macros_rs::{crashln, string, ternary, then};
+use pmc::{
+    helpers,
+    process::{Runner, ready::Ready},
+};
 use std::env;
 
 pub(crate) fn format(server_name: &String) -> (String, String) {
@@ -44,6 +48,8 @@ pub fn start(
     name: &Option<String>,
     args: &Args,
     watch: &Option<String>,
+    instances: &Option<usize>,
+    options: &OptionArgs,
     reset_env: &bool,
     server_name: &String,
 ) {
@@ -52,6 +58,15 @@ pub fn start(
 
     let arg = args.get_string().unwrap_or_default();
 
+    // the instance count is only set when a group is created
+    then!(
+        instances.is_some() && (arg == "all" || matches!(args, Args::Id(_))),
+        crashln!(
+            "{} --instances only applies to a process started by name",
+            *helpers::FAIL
+        )
+    );
+
     if arg == "all" {
         println!(
             "{} Applying {kind}action startAllProcess",
@@ -67,7 +82,7 @@ pub fn start(
                     kind: kind.clone(),
                     runner: runner.clone(),
                 }
-                .restart(&None, &None, false, true);
+                .restart(&None, &None, options, false, true);
             }),
             None => println!("{} Cannot start all, no processes found", *helpers::FAIL),
         }
@@ -80,34 +95,96 @@ pub fn start(
                     server_name,
                     kind,
                 }
-                .restart(name, watch, *reset_env, false);
+                .restart(name, watch, options, *reset_env, false);
             }
-            Args::Script(script) => match runner.find(script, server_name) {
-                Some(id) => {
-                    Internal {
-                        id,
-                        runner,
-                        server_name,
-                        kind,
-                    }
-                    .restart(name, watch, *reset_env, false);
-                }
-                None => {
+            Args::Script(script) => {
+                let group = runner.find_all(script, server_name);
+
+                if group.is_empty() {
                     Internal {
                         id: 0,
                         runner,
                         server_name,
                         kind,
                     }
-                    .create(script, name, watch, false);
+                    .create(
+                        script,
+                        name,
+                        watch,
+                        instances.unwrap_or(1),
+                        &options.create(),
+                        false,
+                    );
+                } else {
+                    if let Some(count) = instances
+                        && *count != group.len()
+                    {
+                        crashln!(
+                            "{} Process ({script}) runs {} instance(s), remove it to start {count}",
+                            *helpers::FAIL,
+                            group.len()
+                        );
+                    }
+
+                    for id in group {
+                        runner = Internal {
+                            id,
+                            server_name,
+                            kind: kind.clone(),
+                            runner: runner.clone(),
+                        }
+                        .restart(name, watch, options, *reset_env, false);
+                    }
                 }
-            },
+            }
         }
     }
 
     Internal::list(&string!("default"), &list_name);
 }
 
+pub fn reload(
+    item: &Item,
+    port: &Option<u16>,
+    signal: &Option<String>,
+    grace: &Option<u64>,
+    server_name: &String,
+) {
+    let mut runner: Runner = Runner::new();
+    let (kind, list_name) = format(server_name);
+
+    let ready = match (port, signal, grace) {
+        (Some(port), _, _) => Some(Ready::Port(*port)),
+        (_, Some(signal), _) => Some(Ready::Signal(signal.clone())),
+        (_, _, Some(grace)) => Some(Ready::Grace(*grace)),
+        _ => None,
+    };
+
+    let group = match item {
+        Item::Id(id) => vec![*id],
+        Item::Name(name) => {
+            let group = runner.find_all(name, server_name);
+            then!(
+                group.is_empty(),
+                crashln!("{} Process ({name}) not found", *helpers::FAIL)
+            );
+            group
+        }
+    };
+
+    for id in group {
+        runner = Internal {
+            id,
+            server_name,
+            kind: kind.clone(),
+            runner: runner.clone(),
+        }
+        .reload(&ready, false);
+    }
+
+    Internal::list(&string!("default"), &list_name);
+}
+
 pub fn stop(item: &Item, server_name: &String) {
     let mut runner: Runner = Runner::new();
     let (kind, list_name) = format(server_name);
@@ -141,18 +218,23 @@ pub fn stop(item: &Item, server_name: &String) {
                 }
                 .stop(false);
             }
-            Item::Name(name) => match runner.find(name, server_name) {
-                Some(id) => {
-                    Internal {
+            Item::Name(name) => {
+                let group = runner.find_all(name, server_name);
+                then!(
+                    group.is_empty(),
+                    crashln!("{} Process ({name}) not found", *helpers::FAIL)
+                );
+
+                for id in group {
+                    runner = Internal {
                         id,
-                        runner,
                         server_name,
-                        kind,
+                        kind: kind.clone(),
+                        runner: runner.clone(),
                     }
                     .stop(false);
                 }
-                None => crashln!("{} Process ({name}) not found", *helpers::FAIL),
-            },
+            }
         }
     }
 
@@ -171,22 +253,29 @@ pub fn remove(item: &Item, server_name: &String) {
             kind,
         }
         .remove(),
-        Item::Name(name) => match runner.find(name, server_name) {
-            Some(id) => Internal {
-                id,
-                runner,
-                server_name,
-                kind,
+        Item::Name(name) => {
+            let group = runner.find_all(name, server_name);
+            then!(
+                group.is_empty(),
+                crashln!("{} Process ({name}) not found", *helpers::FAIL)
+            );
+
+            for id in group {
+                Internal {
+                    id,
+                    runner: Runner::new(),
+                    server_name,
+                    kind: kind.clone(),
+                }
+                .remove()
             }
-            .remove(),
-            None => crashln!("{} Process ({name}) not found", *helpers::FAIL),
-        },
+        }
     }
 
     super::daemon::reset();
 }
 
-pub fn info(item: &Item, format: &String, server_name: &String) {
+pub fn info(item: &Item, format: &str, server_name: &String) {
     let runner: Runner = Runner::new();
     let (kind, _) = self::format(server_name);
 
@@ -198,20 +287,27 @@ pub fn info(item: &Item, format: &String, server_name: &String) {
             kind,
         }
         .info(format),
-        Item::Name(name) => match runner.find(name, server_name) {
-            Some(id) => Internal {
-                id,
-                runner,
-                server_name,
-                kind,
+        Item::Name(name) => {
+            let group = runner.find_all(name, server_name);
+            then!(
+                group.is_empty(),
+                crashln!("{} Process ({name}) not found", *helpers::FAIL)
+            );
+
+            for id in group {
+                Internal {
+                    id,
+                    runner: runner.clone(),
+                    server_name,
+                    kind: kind.clone(),
+                }
+                .info(format)
             }
-            .info(format),
-            None => crashln!("{} Process ({name}) not found", *helpers::FAIL),
-        },
+        }
     }
 }
 
-pub fn logs(item: &Item, lines: &usize, server_name: &String) {
+pub fn logs(item: &Item, lines: &usize, merged: &bool, server_name: &String) {
     let runner: Runner = Runner::new();
     let (kind, _) = format(server_name);
 
@@ -222,22 +318,29 @@ pub fn logs(item: &Item, lines: &usize, server_name: &String) {
             server_name,
             kind,
         }
-        .logs(lines),
-        Item::Name(name) => match runner.find(name, server_name) {
-            Some(id) => Internal {
-                id,
-                runner,
-                server_name,
-                kind,
+        .logs(lines, merged),
+        Item::Name(name) => {
+            let group = runner.find_all(name, server_name);
+            then!(
+                group.is_empty(),
+                crashln!("{} Process ({name}) not found", *helpers::FAIL)
+            );
+
+            for id in group {
+                Internal {
+                    id,
+                    runner: runner.clone(),
+                    server_name,
+                    kind: kind.clone(),
+                }
+                .logs(lines, merged)
             }
-            .logs(lines),
-            None => crashln!("{} Process ({name}) not found", *helpers::FAIL),
-        },
+        }
     }
 }
 
 // combine into a single function that handles multiple
-pub fn env(item: &Item, server_name: &String) {
+pub fn env(item: &Item, server_name: &String, reveal: bool) {
     let runner: Runner = Runner::new();
     let (kind, _) = format(server_name);
 
@@ -248,17 +351,24 @@ pub fn env(item: &Item, server_name: &String) {
             server_name,
             kind,
         }
-        .env(),
-        Item::Name(name) => match runner.find(name, server_name) {
-            Some(id) => Internal {
-                id,
-                runner,
-                server_name,
-                kind,
+        .env(reveal),
+        Item::Name(name) => {
+            let group = runner.find_all(name, server_name);
+            then!(
+                group.is_empty(),
+                crashln!("{} Process ({name}) not found", *helpers::FAIL)
+            );
+
+            for id in group {
+                Internal {
+                    id,
+                    runner: runner.clone(),
+                    server_name,
+                    kind: kind.clone(),
+                }
+                .env(reveal)
             }
-            .env(),
-            None => crashln!("{} Process ({name}) not found", *helpers::FAIL),
-        },
+        }
     }
 }
 
@@ -274,15 +384,22 @@ pub fn flush(item: &Item, server_name: &String) {
             kind,
         }
         .flush(),
-        Item::Name(name) => match runner.find(name, server_name) {
-            Some(id) => Internal {
-                id,
-                runner,
-                server_name,
-                kind,
+        Item::Name(name) => {
+            let group = runner.find_all(name, server_name);
+            then!(
+                group.is_empty(),
+                crashln!("{} Process ({name}) not found", *helpers::FAIL)
+            );
+
+            for id in group {
+                Internal {
+                    id,
+                    runner: runner.clone(),
+                    server_name,
+                    kind: kind.clone(),
+                }
+                .flush()
             }
-            .flush(),
-            None => crashln!("{} Process ({name}) not found", *helpers::FAIL),
-        },
+        }
     }
 }
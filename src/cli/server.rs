@@ -53,7 +53,7 @@ impl std::fmt::Display for ServerOption {
     }
 }
 
-pub fn list(format: &String, log_level: Option<log::Level>) {
+pub fn list(format: &str, log_level: Option<log::Level>) {
     let servers = config::servers().servers.take().unwrap_or_default();
 
     let options: Vec<_> = servers
@@ -3,7 +3,7 @@ pub mod structs;
 use crate::{
     file::{self, Exists},
     helpers,
-    process::RemoteConfig,
+    process::{RemoteConfig, environ},
 };
 
 use colored::Colorize;
@@ -53,11 +53,15 @@ pub fn read() -> Config {
                         args: vec![string!("-c")],
                         node: string!("node"),
                         log_path: format!("{path}/.pmc/logs"),
+                        logs: Logs::default(),
+                        redact: environ::default_redact(),
                     },
                     daemon: Daemon {
                         restarts: 10,
                         interval: 1000,
                         kind: string!("default"),
+                        rotate: Rotate::default(),
+                        cgroup: Cgroup::default(),
                         web: Web {
                             ui: false,
                             api: true,
@@ -67,6 +71,7 @@ pub fn read() -> Config {
                             secure: Some(Secure {
                                 enabled: false,
                                 token: string!(""),
+                                reveal: None,
                             }),
                         },
                     },
@@ -1,8 +1,9 @@
+use crate::process::{collect, environ};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 pub mod prelude {
-    pub use super::{Config, Daemon, Runner, Secure, Server, Servers, Web};
+    pub use super::{Cgroup, Config, Daemon, Logs, Rotate, Runner, Secure, Server, Servers, Web};
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -18,6 +19,31 @@ pub struct Runner {
     pub args: Vec<String>,
     pub node: String,
     pub log_path: String,
+    #[serde(default)]
+    pub logs: Logs,
+    /// Variable names whose values are hidden in env output, the api and
+    /// dumps, globs like `*_TOKEN` matched ignoring case
+    #[serde(default = "environ::default_redact")]
+    pub redact: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Logs {
+    /// Pipe output through a collector that timestamps every line
+    pub collect: bool,
+    /// Format the collector writes, "text" or "json"
+    pub format: collect::Format,
+}
+
+impl Logs {
+    pub fn collector(&self, id: usize, name: &str) -> Option<collect::Collector> {
+        self.collect.then(|| collect::Collector {
+            id,
+            name: name.to_string(),
+            format: self.format,
+        })
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -25,9 +51,53 @@ pub struct Daemon {
     pub restarts: u64,
     pub interval: u64,
     pub kind: String,
+    #[serde(default)]
+    pub rotate: Rotate,
+    #[serde(default)]
+    pub cgroup: Cgroup,
     pub web: Web,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Rotate {
+    /// Rotate once a log grows past this size, e.g. "10M"
+    pub max_size: Option<String>,
+    /// Rotate once a log is older than this many seconds
+    pub max_age: Option<u64>,
+    /// Compressed generations to keep
+    pub keep: usize,
+}
+
+impl Default for Rotate {
+    fn default() -> Self {
+        Rotate {
+            max_size: None,
+            max_age: None,
+            keep: 5,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Cgroup {
+    /// Give every process a cgroup v2 of its own when cgroups are available
+    pub enabled: bool,
+    /// Delegated cgroup the processes are placed under, relative to the
+    /// cgroup2 mount. None uses the cgroup the daemon runs in.
+    pub slice: Option<String>,
+}
+
+impl Default for Cgroup {
+    fn default() -> Self {
+        Cgroup {
+            enabled: true,
+            slice: None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Web {
     pub ui: bool,
@@ -42,6 +112,8 @@ pub struct Web {
 pub struct Secure {
     pub enabled: bool,
     pub token: String,
+    /// Token that may also ask for the values of secret variables
+    pub reveal: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -9,9 +9,10 @@ use helpers::{NotFound, create_status};
 use include_dir::{Dir, include_dir};
 use lazy_static::lazy_static;
 use pmc::{config, process};
-use prometheus::{Counter, Gauge, Histogram, HistogramVec};
+use prometheus::{Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramVec};
 use prometheus::{
-    opts, register_counter, register_gauge, register_histogram, register_histogram_vec,
+    opts, register_counter, register_counter_vec, register_gauge, register_gauge_vec,
+    register_histogram, register_histogram_vec,
 };
 use serde_json::{Value, json};
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -52,6 +53,66 @@ lazy_static! {
         "The cpu usage graph of the daemon."
     )
     .unwrap();
+    pub static ref CPU_ALERT_COUNTER: CounterVec = register_counter_vec!(
+        "process_cpu_alerts_total",
+        "Number of times a process stayed over its cpu limit.",
+        &["action"]
+    )
+    .unwrap();
+    pub static ref PROCESS_RSS: GaugeVec = register_gauge_vec!(
+        "process_memory_rss_bytes",
+        "Resident memory of a managed process.",
+        &["id", "name"]
+    )
+    .unwrap();
+    pub static ref PROCESS_VMS: GaugeVec = register_gauge_vec!(
+        "process_memory_vms_bytes",
+        "Virtual memory of a managed process.",
+        &["id", "name"]
+    )
+    .unwrap();
+    pub static ref PROCESS_CGROUP_MEMORY: GaugeVec = register_gauge_vec!(
+        "process_cgroup_memory_bytes",
+        "Memory charged to the cgroup of a managed process, page cache included.",
+        &["id", "name"]
+    )
+    .unwrap();
+    pub static ref PROCESS_CPU: GaugeVec = register_gauge_vec!(
+        "process_cpu_percent",
+        "Cpu usage of a managed process.",
+        &["id", "name"]
+    )
+    .unwrap();
+    pub static ref PROCESS_RESTARTS: CounterVec = register_counter_vec!(
+        "process_restarts_total",
+        "Number of times a managed process was restarted after dying.",
+        &["id", "name"]
+    )
+    .unwrap();
+    pub static ref PROCESS_CRASHES: CounterVec = register_counter_vec!(
+        "process_crashes_total",
+        "Number of crashes the daemon recorded for a managed process.",
+        &["id", "name"]
+    )
+    .unwrap();
+    pub static ref PROCESS_UPTIME: GaugeVec = register_gauge_vec!(
+        "process_uptime_seconds",
+        "Seconds since a managed process was started, 0 while it is stopped.",
+        &["id", "name"]
+    )
+    .unwrap();
+    pub static ref PROCESS_RUNNING: GaugeVec = register_gauge_vec!(
+        "process_running",
+        "Whether a managed process is running.",
+        &["id", "name"]
+    )
+    .unwrap();
+    pub static ref PROCESS_CHILDREN: GaugeVec = register_gauge_vec!(
+        "process_children",
+        "Number of child processes of a managed process.",
+        &["id", "name"]
+    )
+    .unwrap();
     pub static ref HTTP_REQ_HISTOGRAM: HistogramVec = register_histogram_vec!(
         "http_request_duration_seconds",
         "The HTTP request latencies in seconds.",
@@ -82,7 +143,8 @@ lazy_static! {
         routes::metrics_handler,
         routes::prometheus_handler,
         routes::create_handler,
-        routes::rename_handler
+        routes::rename_handler,
+        routes::options_handler
     ),
     components(schemas(
         ErrorMessage,
@@ -91,7 +153,14 @@ lazy_static! {
         process::Info,
         process::Stats,
         process::Watch,
+        process::Options,
         process::ItemSingle,
+        process::exit::Exit,
+        process::policy::Mode,
+        process::policy::Policy,
+        process::limit::Cpu,
+        process::limit::Action,
+        process::limit::Memory,
         process::ProcessItem,
         routes::Stats,
         routes::Daemon,
@@ -105,7 +174,6 @@ lazy_static! {
         routes::ActionResponse,
     ))
 )]
-
 struct ApiDoc;
 struct Logger;
 struct AddCORS;
@@ -128,30 +196,49 @@ impl Modify for SecurityAddon {
 }
 
 #[catch(500)]
-fn internal_error<'m>() -> Json<ErrorMessage> {
+fn internal_error() -> Json<ErrorMessage> {
     create_status(Status::InternalServerError)
 }
 
 #[catch(400)]
-fn bad_request<'m>() -> Json<ErrorMessage> {
+fn bad_request() -> Json<ErrorMessage> {
     create_status(Status::BadRequest)
 }
 
 #[catch(405)]
-fn not_allowed<'m>() -> Json<ErrorMessage> {
+fn not_allowed() -> Json<ErrorMessage> {
     create_status(Status::MethodNotAllowed)
 }
 
 #[catch(404)]
-fn not_found<'m>() -> Json<ErrorMessage> {
+fn not_found() -> Json<ErrorMessage> {
     create_status(Status::NotFound)
 }
 
 #[catch(401)]
-fn unauthorized<'m>() -> Json<ErrorMessage> {
+fn unauthorized() -> Json<ErrorMessage> {
     create_status(Status::Unauthorized)
 }
 
+#[catch(403)]
+fn forbidden() -> Json<ErrorMessage> {
+    create_status(Status::Forbidden)
+}
+
+/// Whether the request carries the token in its header or query
+fn presents(request: &Request<'_>, token: &str) -> bool {
+    let header_valid = request
+        .headers()
+        .get_one("token")
+        .is_some_and(|header_value| header_value == token);
+    let query_valid = match request.query_value::<String>("token") {
+        Some(Ok(query_token)) => query_token == token,
+        _ => false,
+    };
+
+    header_valid || query_valid
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for EnableWebUI {
     type Error = ();
@@ -182,16 +269,10 @@ impl<'r> FromRequest<'r> for routes::Token {
                     return Outcome::Success(routes::Token);
                 }
 
-                let header_valid = request
-                    .headers()
-                    .get_one("token")
-                    .is_some_and(|header_value| header_value == val.token);
-                let query_valid = match request.query_value::<String>("token") {
-                    Some(Ok(query_token)) => query_token == val.token,
-                    _ => false,
-                };
+                let reveal_valid = (val.reveal.as_deref())
+                    .is_some_and(|reveal| !reveal.is_empty() && presents(request, reveal));
 
-                if header_valid || query_valid {
+                if presents(request, &val.token) || reveal_valid {
                     return Outcome::Success(routes::Token);
                 }
 
@@ -202,6 +283,30 @@ impl<'r> FromRequest<'r> for routes::Token {
     }
 }
 
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for routes::Reveal {
+    type Error = ();
+
+    async fn from_request(
+        request: &'r rocket::Request<'_>,
+    ) -> rocket::request::Outcome<Self, Self::Error> {
+        if !matches!(request.query_value::<bool>("reveal"), Some(Ok(true))) {
+            return Outcome::Success(routes::Reveal(false));
+        }
+
+        // without security every request may reveal, with it only the reveal token
+        match config::read().daemon.web.secure {
+            Some(val) if val.enabled => match val.reveal {
+                Some(reveal) if !reveal.is_empty() && presents(request, &reveal) => {
+                    Outcome::Success(routes::Reveal(true))
+                }
+                _ => Outcome::Error((rocket::http::Status::Forbidden, ())),
+            },
+            _ => Outcome::Success(routes::Reveal(true)),
+        }
+    }
+}
+
 static IS_WEBUI: AtomicBool = AtomicBool::new(false);
 
 pub async fn start(webui: bool) {
@@ -244,6 +349,7 @@ pub async fn start(webui: bool) {
         routes::prometheus_handler,
         routes::create_handler,
         routes::rename_handler,
+        routes::options_handler,
         routes::remote_logs_ws,
     ];
 
@@ -262,7 +368,8 @@ pub async fn start(webui: bool) {
                 bad_request,
                 not_allowed,
                 not_found,
-                unauthorized
+                unauthorized,
+                forbidden
             ],
         )
         .launch()
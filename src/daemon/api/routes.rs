@@ -15,6 +15,7 @@ use tera::Context;
 use tokio::{
     fs::File as AsyncFile,
     io::{AsyncReadExt, AsyncSeekExt},
+    task,
     time::{Duration as TokioDuration, sleep as tokio_sleep},
 };
 use tokio_tungstenite::{connect_async, tungstenite::Message as UpstreamMessage};
@@ -38,7 +39,8 @@ use super::{
 use pmc::{
     config, file, helpers,
     process::{
-        ItemSingle, ProcessItem, Runner, dump, get_process_cpu_usage_percentage, http::client,
+        ItemSingle, Options, ProcessItem, Runner, dump, get_process_cpu_usage_percentage,
+        http::client, rotate,
     },
 };
 
@@ -47,17 +49,11 @@ use crate::daemon::{
     pid::{self, Pid},
 };
 
-use std::{
-    collections::BTreeMap,
-    env,
-    fs::{self, File},
-    io::{self, BufRead, BufReader},
-    path::PathBuf,
-    thread::sleep,
-    time::Duration,
-};
+use std::{collections::BTreeMap, env, fs, path::PathBuf, thread::sleep, time::Duration};
 
 pub(crate) struct Token;
+/// Whether the values of secret variables are sent, asked for with `?reveal=true`
+pub(crate) struct Reveal(pub bool);
 type EnvList = Json<BTreeMap<String, String>>;
 const WS_TAIL_DEFAULT: usize = 400;
 
@@ -105,6 +101,10 @@ pub(crate) struct CreateBody {
     path: PathBuf,
     #[schema(example = "src")]
     watch: Option<String>,
+    #[schema(example = 4)]
+    instances: Option<usize>,
+    #[serde(flatten)]
+    options: Options,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -170,6 +170,12 @@ fn attempt(done: bool, method: &str) -> ActionResponse {
     }
 }
 
+/// Run an action that waits on processes, a reload waits for every instance
+/// to get ready and a stop for the kill timeout, away from the async workers
+async fn blocking(action: impl FnOnce() + Send + 'static) -> bool {
+    task::spawn_blocking(action).await.is_ok()
+}
+
 #[get("/")]
 pub async fn dashboard(
     state: &State<TeraState>,
@@ -593,15 +599,20 @@ pub async fn remote_action(
 
 #[get("/daemon/dump")]
 #[utoipa::path(get, tag = "Daemon", path = "/daemon/dump", security((), ("api_key" = [])),
+    params(("reveal" = Option<bool>, Query, description = "Keep the values of secret variables, needs the reveal token", example = false)),
     responses(
         (status = 200, description = "Dump processes successfully", body = [u8]),
         (
             status = UNAUTHORIZED, description = "Authentication failed or not provided", body = ErrorMessage,
             example = json!({"code": 401, "message": "Unauthorized"})
+        ),
+        (
+            status = FORBIDDEN, description = "Token may not reveal secret values", body = ErrorMessage,
+            example = json!({"code": 403, "message": "Forbidden"})
         )
     )
 )]
-pub async fn dump_handler(_t: Token) -> Vec<u8> {
+pub async fn dump_handler(reveal: Reveal, _t: Token) -> Vec<u8> {
     let timer = HTTP_REQ_HISTOGRAM
         .with_label_values(&["dump"])
         .start_timer();
@@ -609,7 +620,10 @@ pub async fn dump_handler(_t: Token) -> Vec<u8> {
     HTTP_COUNTER.inc();
     timer.observe_duration();
 
-    dump::raw()
+    match reveal.0 {
+        true => dump::raw(),
+        false => dump::redacted(&config::read().runner.redact),
+    }
 }
 
 #[get("/daemon/config")]
@@ -692,16 +706,10 @@ pub async fn logs_handler(
                 _ => item.logs().out,
             };
 
-            match File::open(log_file) {
-                Ok(data) => {
-                    let reader = BufReader::new(data);
-                    let logs: Vec<String> = reader.lines().collect::<io::Result<_>>().unwrap();
+            let logs = rotate::read_all(&log_file);
 
-                    timer.observe_duration();
-                    Ok(Json(LogResponse { logs }))
-                }
-                Err(_) => Ok(Json(LogResponse { logs: vec![] })),
-            }
+            timer.observe_duration();
+            Ok(Json(LogResponse { logs }))
         }
         None => {
             timer.observe_duration();
@@ -741,8 +749,11 @@ pub async fn logs_raw_handler(id: usize, kind: String, _t: Token) -> Result<Stri
                 _ => item.logs().out,
             };
 
-            let data = match fs::read_to_string(&log_file) {
-                Ok(data) => format!("# PATH {log_file}\n{data}"),
+            let data = match fs::metadata(&log_file) {
+                Ok(_) => format!(
+                    "# PATH {log_file}\n{}",
+                    rotate::read_all(&log_file).join("\n")
+                ),
                 Err(err) => err.to_string(),
             };
 
@@ -796,13 +807,12 @@ pub async fn logs_ws(
         }
 
         let mut position = buf.len() as u64;
-        let lines: Vec<String> = String::from_utf8_lossy(&buf).lines().map(|line| line.to_string()).collect();
-        let start_index = lines.len().saturating_sub(tail_lines);
+        let lines = rotate::tail(&log_file, tail_lines);
 
         if stream.send(WsOut::Text(json!({
             "type": "snapshot",
             "path": log_file,
-            "lines": lines.iter().skip(start_index).cloned().collect::<Vec<String>>(),
+            "lines": lines,
         }).to_string())).await.is_err() {
             return Ok(());
         }
@@ -810,6 +820,11 @@ pub async fn logs_ws(
         loop {
             tokio_sleep(TokioDuration::from_millis(500)).await;
 
+            // the log was rotated and truncated, follow it from the start
+            if let Ok(metadata) = file.metadata().await && metadata.len() < position {
+                position = 0;
+            }
+
             if file.seek(SeekFrom::Start(position)).await.is_err() {
                 let _ = stream.send(WsOut::Text(json!({"type": "error", "message": "Failed to seek log file"}).to_string())).await;
                 break;
@@ -877,6 +892,7 @@ pub async fn info_handler(id: usize, _t: Token) -> Result<Json<ItemSingle>, NotF
             description = "Create process successful", body = ActionResponse,
             example = json!({"action": "create", "done": true }), status = 200,
         ),
+        (status = BAD_REQUEST, description = "Stop settings are invalid", body = ErrorMessage),
         (status = INTERNAL_SERVER_ERROR, description = "Failed to create process", body = ErrorMessage),
         (
             status = UNAUTHORIZED, description = "Authentication failed or not provided", body = ErrorMessage,
@@ -884,7 +900,10 @@ pub async fn info_handler(id: usize, _t: Token) -> Result<Json<ItemSingle>, NotF
         )
     )
 )]
-pub async fn create_handler(body: Json<CreateBody>, _t: Token) -> Result<Json<ActionResponse>, ()> {
+pub async fn create_handler(
+    body: Json<CreateBody>,
+    _t: Token,
+) -> Result<Json<ActionResponse>, GenericError> {
     let timer = HTTP_REQ_HISTOGRAM
         .with_label_values(&["create"])
         .start_timer();
@@ -892,13 +911,25 @@ pub async fn create_handler(body: Json<CreateBody>, _t: Token) -> Result<Json<Ac
 
     HTTP_COUNTER.inc();
 
+    if let Err(err) = body.options.stop(None) {
+        timer.observe_duration();
+        return Err(generic_error(Status::BadRequest, err));
+    }
+
     let name = match &body.name {
         Some(name) => string!(name),
         None => string!(body.script.split_whitespace().next().unwrap_or_default()),
     };
 
     runner
-        .start(&name, &body.script, body.path.clone(), &body.watch)
+        .start(
+            &name,
+            &body.script,
+            body.path.clone(),
+            &body.watch,
+            body.instances.unwrap_or(1),
+            &body.options,
+        )
         .save();
     timer.observe_duration();
 
@@ -948,29 +979,87 @@ pub async fn rename_handler(
     }
 }
 
+#[post("/process/<id>/options", format = "json", data = "<body>")]
+#[utoipa::path(post, tag = "Process", path = "/process/{id}/options", request_body = Options,
+    security((), ("api_key" = [])),
+    params(("id" = usize, Path, description = "Process id to change options of", example = 0)),
+    responses(
+        (
+            description = "Set options successful", body = ActionResponse,
+            example = json!({"action": "options", "done": true }), status = 200,
+        ),
+        (status = BAD_REQUEST, description = "Stop settings are invalid", body = ErrorMessage),
+        (status = NOT_FOUND, description = "Process was not found", body = ErrorMessage),
+        (
+            status = UNAUTHORIZED, description = "Authentication failed or not provided", body = ErrorMessage,
+            example = json!({"code": 401, "message": "Unauthorized"})
+        )
+    )
+)]
+pub async fn options_handler(
+    id: usize,
+    body: Json<Options>,
+    _t: Token,
+) -> Result<Json<ActionResponse>, GenericError> {
+    let timer = HTTP_REQ_HISTOGRAM
+        .with_label_values(&["options"])
+        .start_timer();
+    let runner = Runner::new();
+
+    match (runner.info(id), body.stop(None)) {
+        (None, _) => {
+            timer.observe_duration();
+            Err(generic_error(
+                Status::NotFound,
+                string!("Process was not found"),
+            ))
+        }
+        (Some(_), Err(err)) => {
+            timer.observe_duration();
+            Err(generic_error(Status::BadRequest, err))
+        }
+        (Some(_), Ok(_)) => {
+            HTTP_COUNTER.inc();
+            runner.get(id).set_options(&body);
+            timer.observe_duration();
+            Ok(Json(attempt(true, "options")))
+        }
+    }
+}
+
 #[get("/process/<id>/env")]
 #[utoipa::path(get, tag = "Process", path = "/process/{id}/env",
-    params(("id" = usize, Path, description = "Process id to fetch env from", example = 0)),
+    params(
+        ("id" = usize, Path, description = "Process id to fetch env from", example = 0),
+        ("reveal" = Option<bool>, Query, description = "Keep the values of secret variables, needs the reveal token", example = false)
+    ),
     responses(
         (
             description = "Current process env", body = HashMap<String, String>,
-            example = json!({"ENV_TEST_VALUE": "example_value"}), status = 200
+            example = json!({"ENV_TEST_VALUE": "example_value", "API_TOKEN": "<redacted>"}), status = 200
         ),
         (status = NOT_FOUND, description = "Process was not found", body = ErrorMessage),
         (
             status = UNAUTHORIZED, description = "Authentication failed or not provided", body = ErrorMessage,
             example = json!({"code": 401, "message": "Unauthorized"})
+        ),
+        (
+            status = FORBIDDEN, description = "Token may not reveal secret values", body = ErrorMessage,
+            example = json!({"code": 403, "message": "Forbidden"})
         )
     )
 )]
-pub async fn env_handler(id: usize, _t: Token) -> Result<EnvList, NotFound> {
+pub async fn env_handler(id: usize, reveal: Reveal, _t: Token) -> Result<EnvList, NotFound> {
     let timer = HTTP_REQ_HISTOGRAM.with_label_values(&["env"]).start_timer();
 
     HTTP_COUNTER.inc();
     match Runner::new().info(id) {
         Some(item) => {
+            let mut item = item.clone();
+            then!(!reveal.0, item.redact(&config::read().runner.redact));
+
             timer.observe_duration();
-            Ok(Json(item.clone().env))
+            Ok(Json(item.env))
         }
         None => {
             timer.observe_duration();
@@ -1007,14 +1096,19 @@ pub async fn action_handler(
         HTTP_COUNTER.inc();
         match method {
             "start" | "restart" => {
-                runner.get(id).restart();
+                let done = blocking(move || runner.get(id).restart()).await;
                 timer.observe_duration();
-                Ok(Json(attempt(true, method)))
+                Ok(Json(attempt(done, method)))
+            }
+            "reload" => {
+                let done = blocking(move || runner.get(id).reload()).await;
+                timer.observe_duration();
+                Ok(Json(attempt(done, method)))
             }
             "stop" | "kill" => {
-                runner.get(id).stop();
+                let done = blocking(move || runner.get(id).stop()).await;
                 timer.observe_duration();
-                Ok(Json(attempt(true, method)))
+                Ok(Json(attempt(done, method)))
             }
             "reset_env" | "clear_env" => {
                 runner.get(id).clear_env();
@@ -1239,6 +1333,8 @@ pub async fn remote_logs_ws(
             match connect_async(url).await {
                 Ok((mut upstream, _)) => {
                     while let Some(msg) = upstream.next().await {
+                        // a guard would hide the send, keep it in the arm
+                        #[allow(clippy::collapsible_match)]
                         match msg {
                             Ok(UpstreamMessage::Text(text)) => {
                                 if stream.send(WsOut::Text(text.to_string())).await.is_err() {
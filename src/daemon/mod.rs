@@ -2,8 +2,15 @@
 mod log;
 mod api;
 mod fork;
-
-use api::{DAEMON_CPU_PERCENTAGE, DAEMON_MEM_USAGE, DAEMON_START_TIME};
+mod supervisor;
+#[cfg(target_os = "linux")]
+mod watch;
+
+use api::{
+    CPU_ALERT_COUNTER, DAEMON_CPU_PERCENTAGE, DAEMON_MEM_USAGE, DAEMON_START_TIME,
+    PROCESS_CGROUP_MEMORY, PROCESS_CHILDREN, PROCESS_CPU, PROCESS_CRASHES, PROCESS_RESTARTS,
+    PROCESS_RSS, PROCESS_RUNNING, PROCESS_UPTIME, PROCESS_VMS,
+};
 use chrono::{DateTime, Utc};
 use colored::Colorize;
 use fork::{Fork, daemon};
@@ -14,14 +21,28 @@ use pmc::process::{MemoryInfo, unix::NativeProcess as Process};
 use serde::Serialize;
 use serde_json::json;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::{process, thread::sleep, time::Duration};
+use std::{
+    collections::BTreeMap,
+    fs, process,
+    str::FromStr,
+    sync::{Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+use supervisor::Supervisor;
 
 use pmc::{
     config, file,
     helpers::{self, ColoredString},
-    process::{Runner, Status, get_process_cpu_usage_percentage, hash, id::Id},
+    process::{
+        REAP_TIMEOUT_MS, Runner, cgroup, control, cron, exit::Exit,
+        get_process_cpu_usage_percentage, id::Id, limit::Action, rotate, stop,
+    },
 };
 
+#[cfg(not(target_os = "linux"))]
+use pmc::process::hash;
+
 use tabled::{
     Table, Tabled,
     settings::{
@@ -34,55 +55,527 @@ use tabled::{
 
 static ENABLE_API: AtomicBool = AtomicBool::new(false);
 static ENABLE_WEBUI: AtomicBool = AtomicBool::new(false);
+static MEMORY_STRIKES: Mutex<BTreeMap<usize, u32>> = Mutex::new(BTreeMap::new());
+static CPU_SINCE: Mutex<BTreeMap<usize, Instant>> = Mutex::new(BTreeMap::new());
+static PROBES: Mutex<BTreeMap<(usize, &str), Probing>> = Mutex::new(BTreeMap::new());
+static EXPORTED: Mutex<BTreeMap<usize, Exported>> = Mutex::new(BTreeMap::new());
+
+/// Name and last seen crash count of a process with prometheus series
+struct Exported {
+    name: String,
+    crashes: u64,
+}
 
 extern "C" fn handle_termination_signal(_: libc::c_int) {
     pid::remove();
+    let _ = fs::remove_file(control::path());
     log!("[daemon] killed", "pid" => process::id());
     unsafe { libc::_exit(0) }
 }
 
-fn restart_process() {
-    for (id, item) in Runner::new().items_mut() {
-        let mut runner = Runner::new();
-        let children = pmc::process::process_find_children(item.pid);
+fn restart_process(supervisor: &mut Supervisor) {
+    for id in supervisor.runner.items().into_keys() {
+        then!(supervisor.busy().contains(&id), continue);
+        check_process(supervisor, id);
+    }
+}
+
+/// Restart a process after its watched files changed
+#[cfg(target_os = "linux")]
+fn watch_reload(supervisor: &mut Supervisor, id: usize) {
+    let Some(item) = supervisor.runner.info(id) else {
+        return;
+    };
+
+    then!(!item.running || !item.watch.enabled, return);
+    let name = item.name.clone();
+
+    supervisor.restart(id, false);
+    log!("[daemon] watch reload", "name" => name, "id" => id);
+}
+
+fn check_process(supervisor: &mut Supervisor, id: usize) {
+    let Some(item) = supervisor.runner.info(id).cloned() else {
+        return;
+    };
+
+    let children = pmc::process::process_find_children(item.pid);
+
+    if !children.is_empty() && children != item.children {
+        log!("[daemon] added", "children" => format!("{children:?}"));
+        supervisor.runner.set_children(id, children.clone());
+    }
+
+    // without inotify changes are found by hashing the first watched path
+    #[cfg(not(target_os = "linux"))]
+    if item.running && item.watch.enabled {
+        let path = item.path.join(item.watch.path.clone());
+        let hash = hash::create(path);
 
-        if !children.is_empty() && children != item.children {
-            log!("[daemon] added", "children" => format!("{children:?}"));
-            runner.set_children(*id, children).save();
+        if hash != item.watch.hash {
+            supervisor.restart(item.id, false);
+            log!("[daemon] watch reload", "name" => item.name, "hash" => "hash");
+            return;
         }
+    }
 
-        if item.running && item.watch.enabled {
-            let path = item.path.join(item.watch.path.clone());
-            let hash = hash::create(path);
+    if !item.running && !item.stale && item.is_alive() {
+        supervisor.runner.set_running(id);
+        log!("[daemon] process fix status", "name" => item.name, "id" => id);
+        return;
+    }
 
-            if hash != item.watch.hash {
-                runner.restart(item.id, false);
-                log!("[daemon] watch reload", "name" => item.name, "hash" => "hash");
-                continue;
+    let uptime = (Utc::now() - item.started).to_std().unwrap_or_default();
+    let reaped = ternary!(item.running, item.reap(Duration::ZERO), None);
+    let stale = item.running && reaped.is_none() && item.is_stale();
+    let alive = reaped.is_none() && !stale && pid::running(item.pid as i32);
+
+    if stale && !item.stale {
+        supervisor.runner.set_stale(id);
+        log!("[daemon] pid reused by another process", "name" => item.name, "id" => id, "pid" => item.pid);
+    }
+
+    if item.running && alive && memory_exceeded(&item, &children) {
+        supervisor.restart(item.id, false);
+        log!("[daemon] restarted over memory limit", "name" => item.name, "id" => id);
+        return;
+    }
+
+    if item.running
+        && alive
+        && let Some(usage) = cpu_exceeded(&item)
+        && cpu_action(supervisor, &item, usage)
+    {
+        return;
+    }
+
+    if item.running && alive && item.crash.value > 0 && item.policy.is_stable(uptime) {
+        supervisor.runner.reset_crashes(id);
+        log!("[daemon] crashes reset", "name" => item.name, "id" => id);
+        return;
+    }
+
+    then!(!item.running || alive, return);
+
+    let since = match item.crash.since {
+        Some(since) => since,
+        None => {
+            let now = Utc::now();
+            let exit = reaped
+                .or_else(|| item.reap(Duration::from_millis(REAP_TIMEOUT_MS)))
+                .unwrap_or_else(|| Exit::unknown(item.started));
+            log!("[daemon] process exited", "name" => item.name, "id" => id, "status" => exit);
+            (supervisor.runner)
+                .record_exit(id, exit)
+                .set_crash_since(id, now);
+            now
+        }
+    };
+
+    let code = supervisor
+        .runner
+        .try_info(id)
+        .exits
+        .last()
+        .and_then(|exit| exit.code);
+
+    if !item.policy.should_restart(code) {
+        log!("[daemon] process not restarted", "name" => item.name, "id" => id, "policy" => item.policy.mode);
+        supervisor.runner.stop(item.id);
+        then!(code != Some(0), supervisor.runner.set_crashed(id));
+        return;
+    }
+
+    if item.crash.value == config::read().daemon.restarts {
+        log!("[daemon] process has crashed", "name" => item.name, "id" => id);
+        supervisor.runner.stop(item.id).set_crashed(id);
+        return;
+    }
+
+    let delay = item.policy.backoff(item.crash.value);
+    let waited = (Utc::now() - since).to_std().unwrap_or_default();
+
+    if waited < delay {
+        then!(
+            item.crash.since.is_none(),
+            log!("[daemon] restart scheduled", "name" => item.name, "id" => id, "delay" => format!("{delay:?}"))
+        );
+        return;
+    }
+
+    supervisor.restart(item.id, true);
+    log!("[daemon] restarted", "name" => item.name, "id" => id, "crashes" => item.crash.value);
+}
+
+/// Whether the process stayed over its memory limit for enough consecutive ticks
+fn memory_exceeded(item: &pmc::process::Process, children: &[i64]) -> bool {
+    let mut strikes = MEMORY_STRIKES.lock().unwrap_or_else(|err| err.into_inner());
+
+    let Some(memory) = &item.max_memory else {
+        strikes.remove(&item.id);
+        return false;
+    };
+
+    // the cgroup charges every descendant, so it stands in for the sum over children
+    let usage = match (
+        memory.children,
+        item.cgroup.as_deref().and_then(cgroup::memory),
+    ) {
+        (true, Some(current)) => current,
+        _ => memory.usage(item.main_pid(), children),
+    };
+    if !memory.is_exceeded(usage) {
+        strikes.remove(&item.id);
+        return false;
+    }
+
+    let count = strikes.entry(item.id).or_default();
+    *count += 1;
+
+    log!("[daemon] memory limit exceeded", "name" => item.name, "id" => item.id, "rss" => helpers::format_memory(usage), "max" => memory.max, "ticks" => format!("{count}/{}", memory.ticks));
+
+    if *count < memory.ticks.max(1) {
+        return false;
+    }
+
+    strikes.remove(&item.id);
+    true
+}
+
+/// Cpu usage of the process once it stayed over its limit for the configured duration
+fn cpu_exceeded(item: &pmc::process::Process) -> Option<f64> {
+    let mut since = CPU_SINCE.lock().unwrap_or_else(|err| err.into_inner());
+
+    let Some(cpu) = &item.max_cpu else {
+        since.remove(&item.id);
+        return None;
+    };
+
+    let usage = item.cpu_usage();
+    if !cpu.is_exceeded(usage) {
+        since.remove(&item.id);
+        return None;
+    }
+
+    let started = *since.entry(item.id).or_insert_with(Instant::now);
+    then!(
+        started.elapsed() < Duration::from_secs(cpu.duration),
+        return None
+    );
+
+    // the next alert needs another full duration over the limit
+    since.remove(&item.id);
+    Some(usage)
+}
+
+/// Run the cpu limit action of a process, returns true when it was restarted
+fn cpu_action(supervisor: &mut Supervisor, item: &pmc::process::Process, usage: f64) -> bool {
+    let Some(cpu) = item.max_cpu.clone() else {
+        return false;
+    };
+
+    CPU_ALERT_COUNTER
+        .with_label_values(&[cpu.action.name()])
+        .inc();
+    log!("[daemon] cpu limit exceeded", "name" => item.name, "id" => item.id, "cpu" => format!("{usage:.2}%"), "max" => format!("{}%", cpu.max), "action" => cpu.action);
+
+    match cpu.action {
+        Action::Log => false,
+        Action::Restart => {
+            supervisor.restart(item.id, false);
+            log!("[daemon] restarted over cpu limit", "name" => item.name, "id" => item.id);
+            true
+        }
+        Action::Signal(name) => {
+            let signal = nix::sys::signal::Signal::from_str(&name);
+
+            // the group gets it like a stop signal, the command may run under a shell
+            if let Err(err) =
+                signal.and_then(|signal| stop::signal_process(item.pid, item.pgid, signal))
+            {
+                log!("[daemon] failed to signal process", "name" => item.name, "id" => item.id, "signal" => name, "error" => err);
             }
+            false
         }
+        Action::Webhook(url) => {
+            let body = json!({
+                "event": "cpu_limit",
+                "id": item.id,
+                "name": item.name,
+                "pid": item.pid,
+                "cpu": usage,
+                "max": cpu.max,
+                "duration": cpu.duration,
+            });
+
+            let (id, name) = (item.id, item.name.clone());
+            thread::spawn(move || {
+                let client = reqwest::blocking::Client::builder()
+                    .timeout(Duration::from_secs(5))
+                    .build();
 
-        if !item.running && pid::running(item.pid as i32) {
-            Runner::new().set_status(*id, Status::Running);
-            log!("[daemon] process fix status", "name" => item.name, "id" => id);
+                if let Err(err) = client.and_then(|client| client.post(&url).json(&body).send()) {
+                    log!("[daemon] failed to call webhook", "name" => name, "id" => id, "url" => url, "error" => err);
+                }
+            });
+            false
+        }
+    }
+}
+
+/// Schedule and failures of a probe, dropped whenever the pid changes
+struct Probing {
+    pid: i64,
+    due: Instant,
+    failures: u32,
+    /// Result of a check still running on its own thread
+    pending: Option<mpsc::Receiver<bool>>,
+}
+
+/// Run due liveness and readiness probes and act on the finished ones
+fn check_health(supervisor: &mut Supervisor) {
+    let mut probes = PROBES.lock().unwrap_or_else(|err| err.into_inner());
+    let items = supervisor.runner.items();
+
+    probes.retain(|(id, _), probing| {
+        items
+            .get(id)
+            .is_some_and(|item| item.running && item.pid == probing.pid)
+    });
+
+    for (id, item) in items {
+        let Some(health) = &item.health else {
             continue;
+        };
+
+        then!(supervisor.busy().contains(&id), continue);
+        then!(!item.running || !item.is_alive(), continue);
+
+        for (kind, probe) in [
+            ("liveness", &health.liveness),
+            ("readiness", &health.readiness),
+        ] {
+            let Some(probe) = probe else {
+                probes.remove(&(id, kind));
+                continue;
+            };
+
+            let probing = probes.entry((id, kind)).or_insert_with(|| Probing {
+                pid: item.pid,
+                due: Instant::now() + probe.interval(),
+                failures: 0,
+                pending: None,
+            });
+
+            let passed = match probing.pending.as_ref().map(|pending| pending.try_recv()) {
+                None => None,
+                Some(Err(mpsc::TryRecvError::Empty)) => continue,
+                Some(result) => {
+                    probing.pending = None;
+                    Some(result.unwrap_or(false))
+                }
+            };
+
+            if probing.pending.is_none() && Instant::now() >= probing.due {
+                let (sender, receiver) = mpsc::channel();
+                let (check, path) = (probe.clone(), item.path.clone());
+                let config = config::read().runner;
+
+                thread::spawn(move || {
+                    let _ = sender.send(check.run(&path, &config.shell, &config.args));
+                });
+
+                probing.due = Instant::now() + probe.interval();
+                probing.pending = Some(receiver);
+            }
+
+            match passed {
+                None => {}
+                Some(true) => {
+                    probing.failures = 0;
+                    if kind == "readiness" && !health.ready {
+                        supervisor.runner.set_readiness(id, true);
+                        log!("[daemon] process ready", "name" => item.name, "id" => id);
+                    }
+                }
+                Some(false) => {
+                    probing.failures += 1;
+                    log!("[daemon] probe failed", "name" => item.name, "id" => id, "probe" => kind, "check" => probe.check, "failures" => format!("{}/{}", probing.failures, probe.threshold));
+
+                    then!(probing.failures < probe.threshold.max(1), continue);
+                    probing.failures = 0;
+
+                    if kind == "liveness" {
+                        supervisor.restart(id, false);
+                        log!("[daemon] restarted after failed liveness probe", "name" => item.name, "id" => id);
+                        break;
+                    }
+
+                    if health.ready {
+                        supervisor.runner.set_readiness(id, false);
+                        log!("[daemon] process not ready", "name" => item.name, "id" => id);
+                    }
+                }
+            }
         }
+    }
+}
+
+fn run_schedules(supervisor: &mut Supervisor) {
+    let now = Utc::now();
+
+    for (id, item) in supervisor.runner.items() {
+        let Some(expression) = &item.cron else {
+            continue;
+        };
 
-        then!(!item.running || pid::running(item.pid as i32), continue);
+        then!(supervisor.busy().contains(&id), continue);
+        let next = cron::next(expression, now);
 
-        if item.running && item.crash.value == config::read().daemon.restarts {
-            log!("[daemon] process has crashed", "name" => item.name, "id" => id);
-            runner.stop(item.id);
-            runner.set_crashed(*id).save();
+        let Some(due) = item.next_run else {
+            match next {
+                Some(_) => {
+                    supervisor.runner.set_next_run(id, next);
+                }
+                None => {
+                    log!("[daemon] invalid cron expression", "name" => item.name, "id" => id, "cron" => expression)
+                }
+            }
             continue;
-        } else {
-            runner.get(item.id).crashed();
-            log!("[daemon] restarted", "name" => item.name, "id" => id, "crashes" => item.crash.value);
+        };
+
+        then!(now < due, continue);
+        supervisor.runner.set_next_run(id, next);
+
+        let alive = item.running && item.is_alive();
+
+        match (item.is_job(), alive) {
+            (true, true) => {
+                log!("[daemon] cron job still running, skipped", "name" => item.name, "id" => id)
+            }
+            (true, false) => {
+                supervisor.restart(id, false);
+                log!("[daemon] cron job started", "name" => item.name, "id" => id);
+            }
+            (false, true) => {
+                supervisor.restart(id, false);
+                log!("[daemon] cron restart", "name" => item.name, "id" => id);
+            }
+            (false, false) => {}
+        }
+    }
+}
+
+fn rotate_logs(runner: &Runner) {
+    let config = config::read().daemon.rotate;
+    then!(
+        config.max_size.is_none() && config.max_age.is_none(),
+        return
+    );
+
+    for item in runner.items().values() {
+        let logs = item.logs();
+
+        for path in [logs.out, logs.error] {
+            then!(!rotate::is_due(&path, &config), continue);
+
+            match rotate::rotate(&path, config.keep) {
+                Ok(_) => log!("[daemon] rotated log", "path" => path, "keep" => config.keep),
+                Err(err) => log!("[daemon] failed to rotate log", "path" => path, "error" => err),
+            }
         }
     }
 }
 
-pub fn health(format: &String) {
+/// Keep the per process prometheus series in line with the process list
+fn update_metrics(runner: &Runner) {
+    let items = runner.items();
+    let mut exported = EXPORTED.lock().unwrap_or_else(|err| err.into_inner());
+
+    exported.retain(|id, seen| {
+        let keep = items.get(id).is_some_and(|item| item.name == seen.name);
+        then!(!keep, remove_metrics(*id, &seen.name));
+        keep
+    });
+
+    for (id, item) in &items {
+        let id_label = id.to_string();
+        let labels = [id_label.as_str(), item.name.as_str()];
+        let alive = item.running && item.is_alive();
+        let memory = match alive {
+            true => item.memory_usage(),
+            false => None,
+        };
+
+        let uptime = (Utc::now() - item.started).num_milliseconds() as f64 / 1000.0;
+        let cpu = ternary!(alive, item.cpu_usage(), 0.0);
+
+        PROCESS_RSS
+            .with_label_values(&labels)
+            .set(memory.as_ref().map_or(0, |memory| memory.rss) as f64);
+        PROCESS_VMS
+            .with_label_values(&labels)
+            .set(memory.as_ref().map_or(0, |memory| memory.vms) as f64);
+        PROCESS_CGROUP_MEMORY.with_label_values(&labels).set(
+            memory
+                .as_ref()
+                .and_then(|memory| memory.cgroup)
+                .unwrap_or(0) as f64,
+        );
+        PROCESS_CPU.with_label_values(&labels).set(cpu);
+        PROCESS_UPTIME
+            .with_label_values(&labels)
+            .set(ternary!(alive, uptime, 0.0));
+        PROCESS_RUNNING
+            .with_label_values(&labels)
+            .set(ternary!(alive, 1.0, 0.0));
+        PROCESS_CHILDREN
+            .with_label_values(&labels)
+            .set(item.children.len() as f64);
+
+        // restarts are kept in the dump, a lower value means the counter was reset
+        let restarts = item.restarts as f64;
+        if restarts < PROCESS_RESTARTS.with_label_values(&labels).get() {
+            let _ = PROCESS_RESTARTS.remove_label_values(&labels);
+        }
+        let counter = PROCESS_RESTARTS.with_label_values(&labels);
+        counter.inc_by(restarts - counter.get());
+
+        // the crash count drops back to zero once a process is stable, only count increases
+        let seen = exported.entry(*id).or_insert(Exported {
+            name: item.name.clone(),
+            crashes: 0,
+        });
+        let counter = PROCESS_CRASHES.with_label_values(&labels);
+        then!(
+            item.crash.value > seen.crashes,
+            counter.inc_by((item.crash.value - seen.crashes) as f64)
+        );
+        seen.crashes = item.crash.value;
+    }
+}
+
+fn remove_metrics(id: usize, name: &str) {
+    let id = id.to_string();
+    let labels = [id.as_str(), name];
+
+    for gauge in [
+        &*PROCESS_RSS,
+        &*PROCESS_VMS,
+        &*PROCESS_CGROUP_MEMORY,
+        &*PROCESS_CPU,
+        &*PROCESS_UPTIME,
+        &*PROCESS_RUNNING,
+        &*PROCESS_CHILDREN,
+    ] {
+        let _ = gauge.remove_label_values(&labels);
+    }
+
+    let _ = PROCESS_RESTARTS.remove_label_values(&labels);
+    let _ = PROCESS_CRASHES.remove_label_values(&labels);
+}
+
+pub fn health(format: &str) {
     let mut pid: Option<i32> = None;
     let mut cpu_percent: Option<f64> = None;
     let mut uptime: Option<DateTime<Utc>> = None;
@@ -179,7 +672,7 @@ pub fn health(format: &String) {
         .to_string();
 
     if let Ok(json) = serde_json::to_string(&data[0]) {
-        match format.as_str() {
+        match format {
             "raw" => println!("{:?}", data[0]),
             "json" => println!("{json}"),
             "default" => {
@@ -214,7 +707,7 @@ pub fn stop() {
 
         match pid::read() {
             Ok(pid) => {
-                if let Err(err) = pmc::process::process_stop(pid.get()) {
+                if let Err(err) = pmc::process::process_stop(pid.get(), &Default::default()) {
                     log!("[daemon] failed to stop", "error" => err);
                 }
                 pid::remove();
@@ -262,26 +755,68 @@ pub fn start(verbose: bool) {
         let api_enabled = ENABLE_API.load(Ordering::Acquire);
         let ui_enabled = ENABLE_WEBUI.load(Ordering::Acquire);
 
-        unsafe { libc::signal(libc::SIGTERM, handle_termination_signal as usize) };
+        unsafe {
+            libc::signal(
+                libc::SIGTERM,
+                handle_termination_signal as *const () as libc::sighandler_t,
+            )
+        };
         DAEMON_START_TIME.set(Utc::now().timestamp_millis() as f64);
 
         pid::write(process::id());
         log!("[daemon] new fork", "pid" => process::id());
 
+        match cgroup::init() {
+            Ok(slice) => log!("[daemon] cgroups enabled", "slice" => slice.display()),
+            Err(reason) => log!("[daemon] cgroups unavailable", "reason" => reason),
+        }
+
         if api_enabled {
             log!("[api] server queued", "address" => config::read().fmt_address());
             tokio::spawn(async move { api::start(ui_enabled).await });
         }
 
+        control::own();
+        let mut supervisor = Supervisor::new();
+        let interval = Duration::from_millis(config.interval);
+        let mut tick = Instant::now();
+
         loop {
-            if api_enabled && let Ok(process) = Process::new(process::id()) {
-                DAEMON_CPU_PERCENTAGE
-                    .observe(get_process_cpu_usage_percentage(process.pid() as i64));
-                DAEMON_MEM_USAGE.observe(process.memory_info().ok().unwrap().rss() as f64);
+            let exited = supervisor.wait(tick.saturating_duration_since(Instant::now()));
+            supervisor.refresh();
+
+            supervisor.serve();
+
+            // exits are handled once they settle, everything else on the interval
+            for id in exited {
+                check_process(&mut supervisor, id);
+            }
+
+            #[cfg(target_os = "linux")]
+            for id in supervisor.changed() {
+                watch_reload(&mut supervisor, id);
+            }
+
+            if Instant::now() >= tick {
+                tick = Instant::now() + interval;
+
+                if api_enabled && let Ok(process) = Process::new(process::id()) {
+                    DAEMON_CPU_PERCENTAGE
+                        .observe(get_process_cpu_usage_percentage(process.pid() as i64));
+                    DAEMON_MEM_USAGE.observe(process.memory_info().ok().unwrap().rss() as f64);
+                }
+
+                if !supervisor.runner.is_empty() {
+                    restart_process(&mut supervisor);
+                    check_health(&mut supervisor);
+                    run_schedules(&mut supervisor);
+                    rotate_logs(&supervisor.runner);
+                }
+
+                then!(api_enabled, update_metrics(&supervisor.runner));
             }
 
-            then!(!Runner::new().is_empty(), restart_process());
-            sleep(Duration::from_millis(config.interval));
+            supervisor.save();
         }
     }
 
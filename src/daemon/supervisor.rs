@@ -0,0 +1,589 @@
+use global_placeholders::global;
+use macros_rs::then;
+
+#[cfg(target_os = "linux")]
+use super::watch::Watcher;
+
+use pmc::process::{
+    Env, Process, Runner, Source,
+    control::{self, Action, Request, Response},
+    dump,
+};
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::net::{UnixListener, UnixStream},
+    },
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(target_os = "linux")]
+use std::{collections::BTreeMap, time::Instant};
+
+/// How long an exit is left for a cli or api action that caused it to save
+/// the new state, before the daemon treats the process as dead
+#[cfg(target_os = "linux")]
+const EXIT_SETTLE_MS: u64 = 500;
+
+/// How long a connected client gets to send its request
+const REQUEST_TIMEOUT_MS: u64 = 5000;
+
+/// Write end of the pipe the SIGCHLD handler wakes the supervisor through
+static CHILD_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// Write end of the pipe control threads wake the supervisor through
+static WAKE_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+fn notify(pipe: &AtomicI32) {
+    let fd = pipe.load(Ordering::Relaxed);
+    then!(fd >= 0, unsafe {
+        libc::write(fd, [1u8].as_ptr() as *const libc::c_void, 1);
+    });
+}
+
+extern "C" fn handle_child_signal(_: libc::c_int) {
+    notify(&CHILD_PIPE);
+}
+
+/// Something a control thread hands back to the supervisor
+enum Event {
+    /// A request read from a connection, still to be answered on it
+    Request(Result<Box<Request>, String>, UnixStream),
+    /// An action that ran off the loop on a copy of the list, with what
+    /// became of its process and the connection it came from, none when the
+    /// daemon started it itself
+    Finished(usize, Option<Box<Process>>, Option<UnixStream>),
+}
+
+/// Keeps the process list in memory, reloading the dump only when another
+/// process wrote it and writing it only when the daemon changed something
+pub struct Supervisor {
+    pub runner: Runner,
+    /// Modification time and size of the dump as last read or written
+    stamp: Option<(SystemTime, u64)>,
+    /// Encoded list as last read or written
+    written: String,
+    /// Read end of the SIGCHLD pipe
+    child_pipe: Option<OwnedFd>,
+    /// Control socket the cli and api send changes through
+    listener: Option<UnixListener>,
+    /// Read end of the pipe control threads wake the supervisor through
+    wake_pipe: Option<OwnedFd>,
+    /// Requests read and actions finished by control threads
+    events: (mpsc::Sender<Event>, mpsc::Receiver<Event>),
+    /// Ids of processes with an action running off the loop
+    busy: BTreeSet<usize>,
+    /// Requests for busy processes, applied in order once they are done
+    waiting: VecDeque<(Request, UnixStream)>,
+    /// Pidfds of running processes by id
+    #[cfg(target_os = "linux")]
+    pidfds: BTreeMap<usize, Watched>,
+    /// Exits seen on a pidfd and when they are handed out
+    #[cfg(target_os = "linux")]
+    settling: BTreeMap<usize, Instant>,
+    /// Inotify watches of processes with watching enabled
+    #[cfg(target_os = "linux")]
+    watcher: Watcher,
+}
+
+/// Pidfd of a running process, readable once the pid exited
+#[cfg(target_os = "linux")]
+struct Watched {
+    pid: i64,
+    fd: OwnedFd,
+    exited: bool,
+}
+
+fn stamp() -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(global!("pmc.dump")).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn encode(runner: &Runner) -> String {
+    ron::ser::to_string(runner).unwrap_or_default()
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        let runner = dump::read();
+
+        Supervisor {
+            stamp: stamp(),
+            written: encode(&runner),
+            child_pipe: child_pipe(),
+            listener: listen(),
+            wake_pipe: pipe(&WAKE_PIPE),
+            events: mpsc::channel(),
+            busy: BTreeSet::new(),
+            waiting: VecDeque::new(),
+            #[cfg(target_os = "linux")]
+            pidfds: BTreeMap::new(),
+            #[cfg(target_os = "linux")]
+            settling: BTreeMap::new(),
+            #[cfg(target_os = "linux")]
+            watcher: Watcher::default(),
+            runner,
+        }
+    }
+
+    /// Pick up changes the cli or api wrote to the dump
+    pub fn refresh(&mut self) {
+        let current = stamp();
+        then!(current.is_some() && current == self.stamp, return);
+
+        self.runner = dump::read();
+        self.written = encode(&self.runner);
+        self.stamp = stamp();
+    }
+
+    /// Write the dump when the in memory list differs from it
+    pub fn save(&mut self) {
+        let encoded = encode(&self.runner);
+        then!(encoded == self.written, return);
+
+        dump::write(&self.runner);
+        self.written = encoded;
+        self.stamp = stamp();
+    }
+
+    /// Sleep until a watched process exits, a child changes state, a watched
+    /// file changes or the timeout passes, returns the ids of processes whose
+    /// exit has settled
+    pub fn wait(&mut self, timeout: Duration) -> BTreeSet<usize> {
+        let mut fds = vec![];
+
+        #[cfg(target_os = "linux")]
+        self.watcher.sync(&self.runner);
+
+        #[cfg(target_os = "linux")]
+        let timeout = match self.settling.values().chain(&self.watcher.next()).min() {
+            Some(due) => timeout.min(due.saturating_duration_since(Instant::now())),
+            None => timeout,
+        };
+
+        let sources = [
+            self.child_pipe.as_ref().map(|pipe| pipe.as_raw_fd()),
+            self.wake_pipe.as_ref().map(|pipe| pipe.as_raw_fd()),
+            self.listener.as_ref().map(|listener| listener.as_raw_fd()),
+        ];
+
+        fds.extend(sources.into_iter().flatten().map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        }));
+
+        #[cfg(target_os = "linux")]
+        let watched = self.watcher.fds();
+
+        #[cfg(target_os = "linux")]
+        fds.extend(watched.iter().map(|(_, fd)| libc::pollfd {
+            fd: *fd,
+            events: libc::POLLIN,
+            revents: 0,
+        }));
+
+        #[cfg(target_os = "linux")]
+        let ids = self.watch_exits();
+
+        #[cfg(target_os = "linux")]
+        fds.extend(ids.iter().map(|id| libc::pollfd {
+            fd: self.pidfds[id].fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }));
+
+        let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+        unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+
+        drain(&self.wake_pipe);
+        let signalled = drain(&self.child_pipe);
+
+        #[cfg(target_os = "linux")]
+        {
+            let offset = fds.len() - ids.len();
+            let now = Instant::now();
+
+            for ((id, _), fd) in watched.iter().zip(&fds[offset - watched.len()..offset]) {
+                then!(fd.revents != 0, self.watcher.read(*id));
+            }
+
+            // an exited pid stays readable, it is only polled again once it changes
+            for (id, fd) in ids.into_iter().zip(&fds[offset..]) {
+                then!(fd.revents == 0, continue);
+                self.pidfds
+                    .entry(id)
+                    .and_modify(|watched| watched.exited = true);
+                self.settling
+                    .insert(id, now + Duration::from_millis(EXIT_SETTLE_MS));
+            }
+
+            // processes that died before a pidfd could be opened only show up as a child exiting
+            if signalled {
+                for (id, item) in &self.runner.list {
+                    if item.running && !self.pidfds.contains_key(id) {
+                        self.settling
+                            .entry(*id)
+                            .or_insert(now + Duration::from_millis(EXIT_SETTLE_MS));
+                    }
+                }
+            }
+
+            let settled: BTreeSet<usize> = (self.settling.iter())
+                .filter(|(_, due)| **due <= now)
+                .map(|(id, _)| *id)
+                .collect();
+
+            // exits of busy processes are the action stopping them, not a crash
+            self.settling.retain(|id, _| !settled.contains(id));
+            &settled - &self.busy
+        }
+
+        // without pidfds a child exiting is the only event, check every running process
+        #[cfg(not(target_os = "linux"))]
+        match signalled {
+            true => (self.runner.list.iter())
+                .filter(|(id, item)| item.running && !self.busy.contains(id))
+                .map(|(id, _)| *id)
+                .collect(),
+            false => BTreeSet::new(),
+        }
+    }
+
+    /// Ids of watched processes whose files changed and settled
+    #[cfg(target_os = "linux")]
+    pub fn changed(&mut self) -> Vec<usize> {
+        let due = self.watcher.due();
+        due.into_iter()
+            .filter(|id| !self.busy.contains(id))
+            .collect()
+    }
+
+    /// Ids of processes the daemon must leave alone until their action is done
+    pub fn busy(&self) -> &BTreeSet<usize> {
+        &self.busy
+    }
+
+    /// Read new connections on their own threads, apply the requests read so
+    /// far and answer the ones whose action finished
+    pub fn serve(&mut self) {
+        if let Some(listener) = &self.listener {
+            for stream in listener.incoming().map_while(Result::ok) {
+                let sender = self.events.0.clone();
+                thread::spawn(move || receive(stream, sender));
+            }
+        }
+
+        while let Ok(event) = self.events.1.try_recv() {
+            match event {
+                Event::Request(Ok(request), stream) => self.apply(*request, stream),
+                Event::Request(Err(err), stream) => answer(stream, Response::error(err)),
+                Event::Finished(id, process, stream) => {
+                    match process {
+                        Some(process) => self.runner.list.insert(id, *process),
+                        None => self.runner.list.remove(&id),
+                    };
+
+                    self.busy.remove(&id);
+                    self.save();
+
+                    if let Some(stream) = stream {
+                        answer(stream, Response::ok(self.runner.clone()));
+                    }
+
+                    for (request, stream) in std::mem::take(&mut self.waiting) {
+                        self.apply(request, stream);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply a request, handing actions that wait on a process to a thread
+    /// and answering every other one right away
+    fn apply(&mut self, request: Request, stream: UnixStream) {
+        let response = match request.action.id() {
+            Some(id) if !self.runner.exists(id) => {
+                Response::error(format!("Process ({id}) not found"))
+            }
+            Some(id) if self.busy.contains(&id) => {
+                self.waiting.push_back((request, stream));
+                return;
+            }
+            Some(_) if request.action.waits() => {
+                let Request { caller, action, .. } = request;
+                self.dispatch(Some(caller), action, Some(stream));
+                return;
+            }
+            // the list is replaced as a whole, every running action finishes first
+            None if matches!(request.action, Action::Load { .. }) && !self.busy.is_empty() => {
+                self.waiting.push_back((request, stream));
+                return;
+            }
+            _ => self.change(request),
+        };
+
+        answer(stream, response);
+    }
+
+    /// Restart a process off the loop, for restarts the daemon decides on
+    /// itself, a stop that has to wait for its kill timeout never holds up
+    /// the other processes
+    pub fn restart(&mut self, id: usize, dead: bool) {
+        then!(self.busy.contains(&id), return);
+        self.dispatch(None, Action::Restart { id, dead }, None);
+    }
+
+    /// Run an action on a copy of the list, the process stays busy until the
+    /// thread hands back what became of it
+    fn dispatch(&mut self, caller: Option<Env>, action: Action, stream: Option<UnixStream>) {
+        let Some(id) = action.id() else {
+            return;
+        };
+
+        let mut runner = self.runner.clone();
+        let sender = self.events.0.clone();
+
+        runner.source = Source::Copy;
+        runner.caller = caller;
+        self.busy.insert(id);
+
+        thread::spawn(move || {
+            control::own();
+
+            match action {
+                Action::Restart { id, dead } => {
+                    runner.restart(id, dead);
+                }
+                Action::Reload { id } => {
+                    runner.reload(id);
+                }
+                Action::Stop { id } => {
+                    runner.stop(id);
+                }
+                Action::Remove { id } => runner.remove(id),
+                _ => {}
+            }
+
+            let _ = sender.send(Event::Finished(
+                id,
+                runner.list.remove(&id).map(Box::new),
+                stream,
+            ));
+            notify(&WAKE_PIPE);
+        });
+    }
+
+    fn change(&mut self, Request { caller, action, .. }: Request) -> Response {
+        if let Action::Start { path, .. } = &action
+            && !path.is_dir()
+        {
+            return Response::error(format!("Working directory {path:?} does not exist"));
+        }
+
+        if let Action::Start { options, .. } | Action::SetOptions { options, .. } = &action
+            && let Err(err) = options.stop(None)
+        {
+            return Response::error(err);
+        }
+
+        let runner = &mut self.runner;
+        runner.caller = Some(caller);
+
+        match action {
+            Action::Dump
+            | Action::Restart { .. }
+            | Action::Reload { .. }
+            | Action::Stop { .. }
+            | Action::Remove { .. } => {}
+            Action::Start {
+                name,
+                command,
+                path,
+                watch,
+                instances,
+                options,
+            } => {
+                runner.start(&name, &command, path, &watch, instances, &options);
+            }
+            Action::Flush { id } => {
+                runner.flush(id);
+            }
+            Action::Rename { id, name } => {
+                runner.rename(id, name);
+            }
+            Action::Watch { id, path, enabled } => {
+                runner.watch(id, &path, enabled);
+            }
+            Action::SetEnv { id, env } => {
+                runner.set_env(id, env);
+            }
+            Action::ClearEnv { id } => {
+                runner.clear_env(id);
+            }
+            Action::SetReady { id, ready } => {
+                runner.set_ready(id, ready);
+            }
+            Action::SetOptions { id, options } => {
+                runner.set_options(id, &options);
+            }
+            Action::SetId { id } => runner.set_id(id),
+            Action::Load { id, list } => {
+                let list = list.into_iter().map(|item| (item.id, item)).collect();
+                runner.load(id, list);
+            }
+        }
+
+        runner.caller = None;
+        self.save();
+
+        Response::ok(self.runner.clone())
+    }
+
+    /// Open pidfds for new or restarted processes and close the ones no longer
+    /// needed, returns the ids still to be polled
+    #[cfg(target_os = "linux")]
+    fn watch_exits(&mut self) -> Vec<usize> {
+        let list = &self.runner.list;
+
+        self.pidfds.retain(|id, watched| {
+            list.get(id)
+                .is_some_and(|item| item.running && item.pid == watched.pid)
+        });
+
+        for (id, item) in list {
+            if !item.running || item.pid <= 0 || self.pidfds.contains_key(id) {
+                continue;
+            }
+
+            let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, item.pid as libc::pid_t, 0) };
+            if fd >= 0 {
+                let watched = Watched {
+                    pid: item.pid,
+                    fd: unsafe { OwnedFd::from_raw_fd(fd as i32) },
+                    exited: false,
+                };
+                self.pidfds.insert(*id, watched);
+            }
+        }
+
+        self.pidfds
+            .iter()
+            .filter(|(_, watched)| !watched.exited)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
+
+/// Bind the control socket, replacing one left behind by a daemon that was killed
+fn listen() -> Option<UnixListener> {
+    let path = control::path();
+    let _ = fs::remove_file(&path);
+
+    // the socket is created owner only, there is no window where others can connect
+    let umask = unsafe { libc::umask(0o177) };
+    let bound = UnixListener::bind(&path);
+    unsafe { libc::umask(umask) };
+
+    let listener = match bound {
+        Ok(listener) => listener,
+        Err(err) => {
+            log!("[control] failed to bind socket", "path" => path, "error" => err);
+            return None;
+        }
+    };
+
+    listener.set_nonblocking(true).ok()?;
+
+    log!("[control] listening", "path" => path);
+    Some(listener)
+}
+
+/// Read a request on its own thread, so a slow client never holds up the loop
+fn receive(stream: UnixStream, sender: mpsc::Sender<Event>) {
+    let timeout = Some(Duration::from_millis(REQUEST_TIMEOUT_MS));
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(timeout);
+
+    let request = match control::read::<Request>(&stream) {
+        Ok(request) if request.version != control::VERSION => {
+            Err(format!("Unsupported protocol v{}", request.version))
+        }
+        Ok(request) => Ok(Box::new(request)),
+        Err(err) => Err(format!("Invalid request: {err}")),
+    };
+
+    let _ = sender.send(Event::Request(request, stream));
+    notify(&WAKE_PIPE);
+}
+
+/// Write a response on its own thread, so a client that stopped reading
+/// never holds up the loop
+fn answer(stream: UnixStream, response: Response) {
+    thread::spawn(move || {
+        if let Err(err) = control::write(&stream, &response) {
+            log!("[control] failed to answer", "error" => err);
+        }
+    });
+}
+
+/// Empty a non blocking pipe, returns whether anything was written to it
+fn drain(pipe: &Option<OwnedFd>) -> bool {
+    let Some(pipe) = pipe else {
+        return false;
+    };
+
+    let mut buffer = [0u8; 64];
+    let mut written = false;
+
+    while unsafe {
+        libc::read(
+            pipe.as_raw_fd(),
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            buffer.len(),
+        )
+    } > 0
+    {
+        written = true;
+    }
+
+    written
+}
+
+/// Open a non blocking pipe, storing its write end and returning its read end
+fn pipe(write: &AtomicI32) -> Option<OwnedFd> {
+    let mut fds = [0; 2];
+    then!(unsafe { libc::pipe(fds.as_mut_ptr()) } != 0, return None);
+
+    for fd in fds {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+
+    write.store(fds[1], Ordering::Relaxed);
+    Some(unsafe { OwnedFd::from_raw_fd(fds[0]) })
+}
+
+/// Route SIGCHLD into a non blocking pipe the supervisor polls on
+fn child_pipe() -> Option<OwnedFd> {
+    let read = pipe(&CHILD_PIPE)?;
+
+    unsafe {
+        libc::signal(
+            libc::SIGCHLD,
+            handle_child_signal as *const () as libc::sighandler_t,
+        )
+    };
+
+    Some(read)
+}
@@ -0,0 +1,277 @@
+use macros_rs::then;
+use pmc::process::{Runner, watch::ignored};
+
+use std::{
+    collections::BTreeMap,
+    ffi::{CString, OsStr, OsString},
+    fs, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+const MASK: u32 = libc::IN_MODIFY
+    | libc::IN_ATTRIB
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF;
+
+/// Inotify watches of every running process with watching enabled
+#[derive(Default)]
+pub struct Watcher {
+    entries: BTreeMap<usize, Entry>,
+}
+
+/// Inotify instance of a single process
+struct Entry {
+    fd: OwnedFd,
+    /// Roots and ignore patterns the watches were added for
+    roots: Vec<PathBuf>,
+    ignore: Vec<String>,
+    /// Directories behind every watch descriptor
+    dirs: BTreeMap<i32, Vec<Dir>>,
+    wait: Duration,
+    changed: Option<Instant>,
+}
+
+/// Watched directory, relative paths below it are matched against the ignore
+/// patterns from its root. A file root watches its parent for that name only.
+struct Dir {
+    path: PathBuf,
+    root: PathBuf,
+    file: Option<OsString>,
+}
+
+impl Watcher {
+    /// Add watches for processes that started watching and drop the ones no
+    /// longer running or watching
+    pub fn sync(&mut self, runner: &Runner) {
+        self.entries.retain(|id, _| {
+            runner
+                .list
+                .get(id)
+                .is_some_and(|item| item.running && item.watch.enabled)
+        });
+
+        for (id, item) in &runner.list {
+            if !item.running || !item.watch.enabled {
+                continue;
+            }
+
+            let roots: Vec<PathBuf> = (item.watch.roots())
+                .map(|root| item.path.join(root))
+                .collect();
+            let wait = Duration::from_millis(item.watch.debounce + item.watch.delay);
+
+            if let Some(entry) = self.entries.get_mut(id) {
+                entry.wait = wait;
+                if entry.roots == roots && entry.ignore == item.watch.ignore {
+                    continue;
+                }
+            }
+
+            match Entry::new(roots, item.watch.ignore.clone(), wait) {
+                Some(entry) => self.entries.insert(*id, entry),
+                None => self.entries.remove(id),
+            };
+        }
+    }
+
+    /// Inotify fds to poll with the id they belong to
+    pub fn fds(&self) -> Vec<(usize, RawFd)> {
+        (self.entries.iter())
+            .map(|(id, entry)| (*id, entry.fd.as_raw_fd()))
+            .collect()
+    }
+
+    /// Read the pending events of a process
+    pub fn read(&mut self, id: usize) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.read();
+        }
+    }
+
+    /// When the next change settles
+    pub fn next(&self) -> Option<Instant> {
+        (self.entries.values())
+            .filter_map(|entry| Some(entry.changed? + entry.wait))
+            .min()
+    }
+
+    /// Ids of processes whose changes settled, each change is handed out once
+    pub fn due(&mut self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut due = vec![];
+
+        for (id, entry) in &mut self.entries {
+            if entry
+                .changed
+                .is_some_and(|changed| changed + entry.wait <= now)
+            {
+                entry.changed = None;
+                due.push(*id);
+            }
+        }
+
+        due
+    }
+}
+
+impl Entry {
+    fn new(roots: Vec<PathBuf>, ignore: Vec<String>, wait: Duration) -> Option<Entry> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            log!("[daemon] watch failed", "error" => std::io::Error::last_os_error());
+            return None;
+        }
+
+        let mut entry = Entry {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            roots: roots.clone(),
+            ignore,
+            dirs: BTreeMap::new(),
+            wait,
+            changed: None,
+        };
+
+        for root in roots {
+            match root.is_dir() {
+                true => entry.add_tree(&root, &root),
+                false => {
+                    let (Some(parent), Some(file)) = (root.parent(), root.file_name()) else {
+                        continue;
+                    };
+                    let dir = Dir {
+                        path: parent.to_path_buf(),
+                        root: parent.to_path_buf(),
+                        file: Some(file.to_os_string()),
+                    };
+                    entry.add(dir);
+                }
+            }
+        }
+
+        Some(entry)
+    }
+
+    fn add(&mut self, dir: Dir) -> bool {
+        let Ok(path) = CString::new(dir.path.as_os_str().as_bytes()) else {
+            return false;
+        };
+
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), MASK) };
+        if wd < 0 {
+            log!("[daemon] watch failed", "path" => dir.path.display(), "error" => std::io::Error::last_os_error());
+            return false;
+        }
+
+        self.dirs.entry(wd).or_default().push(dir);
+        true
+    }
+
+    /// Watch a directory and every directory below it that is not ignored
+    fn add_tree(&mut self, path: &Path, root: &Path) {
+        let dir = Dir {
+            path: path.to_path_buf(),
+            root: root.to_path_buf(),
+            file: None,
+        };
+        then!(!self.add(dir), return);
+
+        let Ok(children) = fs::read_dir(path) else {
+            return;
+        };
+
+        for child in children.flatten() {
+            let path = child.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+
+            if child.file_type().is_ok_and(|kind| kind.is_dir()) && !ignored(&self.ignore, relative)
+            {
+                self.add_tree(&path, root);
+            }
+        }
+    }
+
+    fn read(&mut self) {
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                )
+            };
+            then!(read <= 0, return);
+
+            let mut offset = 0;
+            while offset + mem::size_of::<libc::inotify_event>() <= read as usize {
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const _) };
+                let start = offset + mem::size_of::<libc::inotify_event>();
+                let name = &buffer[start..start + event.len as usize];
+                let name = OsStr::from_bytes(
+                    &name[..name.iter().position(|c| *c == 0).unwrap_or(name.len())],
+                );
+
+                self.handle(&event, name);
+                offset = start + event.len as usize;
+            }
+        }
+    }
+
+    fn handle(&mut self, event: &libc::inotify_event, name: &OsStr) {
+        let now = Instant::now();
+
+        // the queue overflowed and events were lost, assume something changed
+        if event.mask & libc::IN_Q_OVERFLOW != 0 {
+            self.changed = Some(now);
+            return;
+        }
+
+        if event.mask & libc::IN_IGNORED != 0 {
+            self.dirs.remove(&event.wd);
+            return;
+        }
+
+        let Some(dirs) = self.dirs.get(&event.wd) else {
+            return;
+        };
+
+        let mut created = vec![];
+
+        for dir in dirs {
+            if let Some(file) = &dir.file
+                && file != name
+            {
+                continue;
+            }
+
+            let path = dir.path.join(name);
+            let relative = path.strip_prefix(&dir.root).unwrap_or(&path);
+            then!(ignored(&self.ignore, relative), continue);
+
+            self.changed = Some(now);
+
+            if dir.file.is_none()
+                && event.mask & libc::IN_ISDIR != 0
+                && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0
+            {
+                created.push((path, dir.root.clone()));
+            }
+        }
+
+        for (path, root) in created {
+            self.add_tree(&path, &root);
+        }
+    }
+}
@@ -61,6 +61,19 @@ pub fn logs_internal(
     }
 }
 
+pub fn logs_merged(lines: Vec<(&str, String)>, lines_to_tail: usize, id: usize, item_name: &str) {
+    let start_index = lines.len().saturating_sub(lines_to_tail);
+
+    for (kind, line) in lines.iter().skip(start_index) {
+        let color = ternary!(*kind == "out", "green", "red");
+        println!(
+            "{} {}",
+            format!("{}|{}|{kind} |", id, item_name).color(color),
+            line
+        );
+    }
+}
+
 pub fn cwd() -> PathBuf {
     match env::current_dir() {
         Ok(path) => path,
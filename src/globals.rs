@@ -57,6 +57,7 @@ pub(crate) fn init() {
             init!("pmc.log", format!("{path}/.pmc/pmc.log"));
             init!("pmc.pid", format!("{path}/.pmc/daemon.pid"));
             init!("pmc.dump", format!("{path}/.pmc/process.dump"));
+            init!("pmc.socket", format!("{path}/.pmc/daemon.sock"));
 
             init!("pmc.daemon.kind", config.daemon.kind);
             init!("pmc.daemon.log", format!("{path}/.pmc/daemon.log"));
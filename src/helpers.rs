@@ -36,8 +36,11 @@ impl fmt::Display for ColoredString {
 pub fn format_duration(datetime: DateTime<Utc>) -> String {
     let current_time = Utc::now();
     let duration = current_time.signed_duration_since(datetime);
+    format_seconds(duration.num_seconds())
+}
 
-    match duration.num_seconds() {
+pub fn format_seconds(seconds: i64) -> String {
+    match seconds {
         s if s >= 86400 => format!("{}d", s / 86400),
         s if s >= 3600 => format!("{}h", s / 3600),
         s if s >= 60 => format!("{}m", s / 60),
@@ -63,3 +66,38 @@ pub fn format_memory(bytes: u64) -> String {
 
     [result, SUFFIX[base.floor() as usize]].join("")
 }
+
+/// Parse a human readable size like "512M" or "1gb" into bytes
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_lowercase();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(split);
+    let value: f64 = value.parse().ok()?;
+
+    let multiplier: u64 = match unit.trim() {
+        "" | "b" => 1,
+        "k" | "kb" => 1024,
+        "m" | "mb" => 1024 * 1024,
+        "g" | "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    Some((value * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("10k"), Some(10 * 1024));
+        assert_eq!(parse_size("512M"), Some(512 * 1024 * 1024));
+        assert_eq!(parse_size("1.5gb"), Some(1536 * 1024 * 1024));
+        assert_eq!(parse_size("lots"), None);
+        assert_eq!(parse_size("5x"), None);
+    }
+}
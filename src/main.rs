@@ -6,10 +6,11 @@ mod webui;
 use clap::{Parser, Subcommand};
 use clap_verbosity_flag::{LogLevel, Verbosity};
 use macros_rs::{str, string, then};
+use pmc::process::collect::{Collector, Format};
 use update_informer::{Check, registry};
 
 use crate::{
-    cli::{Args, Item, internal::Internal},
+    cli::{Args, Item, OptionArgs, internal::Internal},
     globals::defaults,
 };
 
@@ -57,6 +58,16 @@ enum Daemon {
     },
 }
 
+#[derive(Subcommand)]
+enum Dump {
+    /// Upgrade the dumpfile to the current schema
+    Migrate {
+        /// Only show what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum Server {
     /// Add new server
@@ -91,6 +102,31 @@ enum Commands {
     Import {
         /// Path of file to import
         path: String,
+        /// Env profile to use for the processes that declare it
+        #[arg(long = "env", value_name = "PROFILE")]
+        profile: Option<String>,
+    },
+    /// Create, replace and optionally delete processes to match a file
+    Apply {
+        /// Path of file to apply
+        path: String,
+        /// Delete processes that are missing from the file
+        #[arg(long)]
+        prune: bool,
+        /// Env profile to use for the processes that declare it
+        #[arg(long = "env", value_name = "PROFILE")]
+        profile: Option<String>,
+    },
+    /// Show the changes apply would make
+    Diff {
+        /// Path of file to compare
+        path: String,
+        /// Include processes that are missing from the file
+        #[arg(long)]
+        prune: bool,
+        /// Env profile to use for the processes that declare it
+        #[arg(long = "env", value_name = "PROFILE")]
+        profile: Option<String>,
     },
     /// Export environment file from process
     #[command(visible_alias = "get")]
@@ -99,6 +135,9 @@ enum Commands {
         item: Item,
         /// Path to export file
         path: Option<String>,
+        /// Write placeholders in place of the values of secret variables
+        #[arg(long)]
+        redact: bool,
     },
     /// Start/Restart a process
     #[command(visible_alias = "restart")]
@@ -111,6 +150,11 @@ enum Commands {
         /// Watch to reload path
         #[arg(long)]
         watch: Option<String>,
+        /// Number of instances to run under the same name, one by default
+        #[arg(short, long)]
+        instances: Option<usize>,
+        #[command(flatten)]
+        options: Box<OptionArgs>,
         /// Server
         #[arg(short, long)]
         server: Option<String>,
@@ -118,6 +162,23 @@ enum Commands {
         #[arg(short, long)]
         reset_env: bool,
     },
+    /// Reload a process without downtime
+    Reload {
+        #[clap(value_parser = cli::validate::<Item>)]
+        item: Item,
+        /// Wait until the process listens on this port
+        #[arg(long, conflicts_with_all = ["signal", "grace"])]
+        port: Option<u16>,
+        /// Wait until this text is written to stdout
+        #[arg(long, conflicts_with = "grace")]
+        signal: Option<String>,
+        /// Wait a fixed amount of milliseconds
+        #[arg(long)]
+        grace: Option<u64>,
+        /// Server
+        #[arg(short, long)]
+        server: Option<String>,
+    },
     /// Stop/Kill a process
     #[command(visible_alias = "kill")]
     Stop {
@@ -144,6 +205,9 @@ enum Commands {
         /// Server
         #[arg(short, long)]
         server: Option<String>,
+        /// Show the values of secret variables
+        #[arg(long)]
+        reveal: bool,
     },
     /// Get information of a process
     #[command(visible_alias = "info")]
@@ -170,6 +234,9 @@ enum Commands {
     /// Restore all processes
     #[command(visible_alias = "resurrect")]
     Restore {
+        /// Load a rolling backup of the dumpfile first, 1 being the newest
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+        from_backup: Option<usize>,
         /// Server
         #[arg(short, long)]
         server: Option<String>,
@@ -187,6 +254,9 @@ enum Commands {
         item: Item,
         #[arg(long, default_value_t = 15, help = "")]
         lines: usize,
+        /// Interleave out and error logs by timestamp
+        #[arg(long)]
+        merged: bool,
         /// Server
         #[arg(short, long)]
         server: Option<String>,
@@ -207,45 +277,105 @@ enum Commands {
         command: Daemon,
     },
 
+    /// Dumpfile management
+    Dump {
+        #[command(subcommand)]
+        command: Dump,
+    },
+
     /// Server management
     #[command(visible_alias = "remote", visible_alias = "srv")]
     Server {
         #[command(subcommand)]
         command: Server,
     },
+
+    /// Collect the output of a process into its logs
+    #[command(hide = true)]
+    Collect {
+        #[arg(long)]
+        id: usize,
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        format: Format,
+        #[arg(long)]
+        out: String,
+        #[arg(long)]
+        error: String,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
+
     let mut env = env_logger::Builder::new();
     let level = cli.verbose.log_level_filter();
-    let informer = update_informer::new(registry::Crates, "pmc", env!("CARGO_PKG_VERSION"));
 
-    if let Some(version) = informer.check_version().ok().flatten() {
-        println!(
-            "{} New version is available: {version}",
-            *pmc::helpers::WARN
-        );
-    }
+    // the collector runs inside every spawned process, it stays quiet and skips the setup
+    if !matches!(&cli.command, Commands::Collect { .. }) {
+        let informer = update_informer::new(registry::Crates, "pmc", env!("CARGO_PKG_VERSION"));
 
-    globals::init();
-    env.filter_level(level).init();
+        if let Some(version) = informer.check_version().ok().flatten() {
+            println!(
+                "{} New version is available: {version}",
+                *pmc::helpers::WARN
+            );
+        }
+
+        globals::init();
+        env.filter_level(level).init();
+    }
 
     match &cli.command {
-        Commands::Import { path } => cli::import::read_hcl(path),
-        Commands::Export { item, path } => cli::import::export_hcl(item, path),
+        Commands::Import { path, profile } => cli::import::read_hcl(path, profile),
+        Commands::Export { item, path, redact } => cli::import::export_hcl(item, path, *redact),
+        Commands::Apply {
+            path,
+            prune,
+            profile,
+        } => cli::import::apply(path, prune, profile),
+        Commands::Diff {
+            path,
+            prune,
+            profile,
+        } => cli::import::diff(path, prune, profile),
         Commands::Start {
             name,
             args,
             watch,
+            instances,
+            options,
             server,
             reset_env,
-        } => cli::start(name, args, watch, reset_env, &defaults(server)),
+        } => cli::start(
+            name,
+            args,
+            watch,
+            instances,
+            options,
+            reset_env,
+            &defaults(server),
+        ),
+        Commands::Reload {
+            item,
+            port,
+            signal,
+            grace,
+            server,
+        } => cli::reload(item, port, signal, grace, &defaults(server)),
         Commands::Stop { item, server } => cli::stop(item, &defaults(server)),
         Commands::Remove { item, server } => cli::remove(item, &defaults(server)),
-        Commands::Restore { server } => Internal::restore(&defaults(server)),
+        Commands::Restore {
+            from_backup,
+            server,
+        } => Internal::restore(from_backup, &defaults(server)),
         Commands::Save { server } => Internal::save(&defaults(server)),
-        Commands::Env { item, server } => cli::env(item, &defaults(server)),
+        Commands::Env {
+            item,
+            server,
+            reveal,
+        } => cli::env(item, &defaults(server), *reveal),
         Commands::Details {
             item,
             format,
@@ -255,8 +385,9 @@ fn main() {
         Commands::Logs {
             item,
             lines,
+            merged,
             server,
-        } => cli::logs(item, lines, &defaults(server)),
+        } => cli::logs(item, lines, merged, &defaults(server)),
         Commands::Flush { item, server } => cli::flush(item, &defaults(server)),
 
         Commands::Daemon { command } => match command {
@@ -266,6 +397,23 @@ fn main() {
             Daemon::Restore { api, webui } => daemon::restart(api, webui, level.as_str() != "OFF"),
         },
 
+        Commands::Dump { command } => match command {
+            Dump::Migrate { dry_run } => cli::dump::migrate(dry_run),
+        },
+
+        Commands::Collect {
+            id,
+            name,
+            format,
+            out,
+            error,
+        } => Collector {
+            id: *id,
+            name: name.clone(),
+            format: *format,
+        }
+        .run(out, error),
+
         Commands::Server { command } => match command {
             Server::New => cli::server::new(),
             Server::Remove { name } => cli::server::remove(name),
@@ -276,9 +424,11 @@ fn main() {
 
     if !matches!(&cli.command, Commands::Daemon { .. })
         && !matches!(&cli.command, Commands::Server { .. })
+        && !matches!(&cli.command, Commands::Dump { .. })
         && !matches!(&cli.command, Commands::Save { .. })
         && !matches!(&cli.command, Commands::Env { .. })
         && !matches!(&cli.command, Commands::Export { .. })
+        && !matches!(&cli.command, Commands::Collect { .. })
     {
         then!(
             !daemon::pid::exists(),
@@ -0,0 +1,373 @@
+use crate::{config, helpers, log};
+
+use core::fmt;
+use macros_rs::then;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Period the cpu quota is measured over, in microseconds
+const CPU_PERIOD_US: u64 = 100_000;
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "pids"];
+
+/// Leaf the daemon moves into, a cgroup with controllers enabled for its
+/// children can not hold processes itself
+const DAEMON_LEAF: &str = "daemon";
+
+static SLICE: OnceLock<Result<Slice, String>> = OnceLock::new();
+
+/// Kernel limits applied to the cgroup of a process
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Limits {
+    /// Cpus the process may use, 0.5 is half a cpu
+    #[schema(example = 0.5)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<f64>,
+    /// Memory the kernel keeps the process under, like "512M"
+    #[schema(example = "512M")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// Tasks the process may run at once
+    #[schema(example = 100)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pids: Option<u64>,
+}
+
+impl Limits {
+    /// Limits from these over a base, a zero value removes a limit.
+    /// None when nothing is limited.
+    pub fn merge(&self, base: Option<&Limits>) -> Option<Limits> {
+        let base = base.cloned().unwrap_or_default();
+        let limits = Limits {
+            cpu: self.cpu.or(base.cpu).filter(|cpu| *cpu > 0.0),
+            memory: (self.memory.clone().or(base.memory))
+                .filter(|memory| helpers::parse_size(memory).is_some_and(|bytes| bytes > 0)),
+            pids: self.pids.or(base.pids).filter(|pids| *pids > 0),
+        };
+
+        (limits != Limits::default()).then_some(limits)
+    }
+
+    fn value(&self, controller: &str) -> Option<(&'static str, String)> {
+        let max = |limit: Option<String>| limit.unwrap_or_else(|| "max".to_string());
+
+        match controller {
+            "cpu" => Some((
+                "cpu.max",
+                format!(
+                    "{} {CPU_PERIOD_US}",
+                    max(self
+                        .cpu
+                        .map(|cpu| ((cpu * CPU_PERIOD_US as f64) as u64).to_string()))
+                ),
+            )),
+            "memory" => Some((
+                "memory.max",
+                max(self
+                    .memory
+                    .as_deref()
+                    .and_then(helpers::parse_size)
+                    .map(|bytes| bytes.to_string())),
+            )),
+            "pids" => Some(("pids.max", max(self.pids.map(|pids| pids.to_string())))),
+            _ => None,
+        }
+    }
+
+    fn is_set(&self, controller: &str) -> bool {
+        match controller {
+            "cpu" => self.cpu.is_some(),
+            "memory" => self.memory.is_some(),
+            "pids" => self.pids.is_some(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+
+        if let Some(cpu) = self.cpu {
+            parts.push(format!("{cpu} cpu"));
+        }
+        if let Some(memory) = &self.memory {
+            parts.push(format!("{memory} memory"));
+        }
+        if let Some(pids) = self.pids {
+            parts.push(format!("{pids} pids"));
+        }
+
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Delegated cgroup processes get their own cgroup under
+#[derive(Debug)]
+struct Slice {
+    path: PathBuf,
+    controllers: Vec<String>,
+}
+
+/// Set up the slice for the daemon. Without a configured slice the cgroup the
+/// daemon runs in is used, which is delegated when the unit sets Delegate=yes.
+pub fn init() -> Result<PathBuf, String> {
+    match SLICE.get_or_init(|| find(true)) {
+        Ok(slice) => Ok(slice.path.clone()),
+        Err(err) => Err(err.clone()),
+    }
+}
+
+fn slice() -> Option<&'static Slice> {
+    SLICE.get_or_init(|| find(false)).as_ref().ok()
+}
+
+fn find(daemon: bool) -> Result<Slice, String> {
+    let config = config::read().daemon.cgroup;
+    then!(
+        !config.enabled,
+        return Err("disabled in the config".to_string())
+    );
+
+    let mount = mount().ok_or("no cgroup2 hierarchy is mounted")?;
+    let path = match (&config.slice, daemon) {
+        (Some(slice), _) => mount.join(slice.trim_start_matches('/')),
+        (None, true) => match own() {
+            Some(own) if own != "/" => mount.join(own.trim_start_matches('/')),
+            Some(_) => {
+                return Err(
+                    "the daemon runs in the root cgroup, set daemon.cgroup.slice".to_string(),
+                );
+            }
+            None => return Err("the daemon is not in a cgroup2 hierarchy".to_string()),
+        },
+        (None, false) => return Err("no slice is configured".to_string()),
+    };
+
+    let available = fs::read_to_string(path.join("cgroup.controllers"))
+        .map_err(|err| format!("cannot read the controllers of {}: {err}", path.display()))?;
+
+    let controllers: Vec<String> = CONTROLLERS
+        .iter()
+        .filter(|controller| {
+            available
+                .split_whitespace()
+                .any(|name| name == **controller)
+        })
+        .map(|controller| controller.to_string())
+        .collect();
+
+    if controllers.is_empty() {
+        return Err(format!(
+            "no cpu, memory or pids controller is delegated to {}",
+            path.display()
+        ));
+    }
+
+    let procs = fs::read_to_string(path.join("cgroup.procs")).unwrap_or_default();
+    if !procs.trim().is_empty() {
+        let leaf = path.join(DAEMON_LEAF);
+        fs::create_dir_all(&leaf)
+            .map_err(|err| format!("cannot create {}: {err}", leaf.display()))?;
+
+        for pid in procs.lines() {
+            fs::write(leaf.join("cgroup.procs"), pid)
+                .map_err(|err| format!("cannot move {pid} into {}: {err}", leaf.display()))?;
+        }
+    }
+
+    let enable: Vec<String> = controllers
+        .iter()
+        .map(|controller| format!("+{controller}"))
+        .collect();
+    fs::write(path.join("cgroup.subtree_control"), enable.join(" ")).map_err(|err| {
+        format!(
+            "cannot enable {} in {}: {err}",
+            enable.join(" "),
+            path.display()
+        )
+    })?;
+
+    Ok(Slice { path, controllers })
+}
+
+/// Mount point of the cgroup2 hierarchy
+fn mount() -> Option<PathBuf> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+
+    mountinfo.lines().find_map(|line| {
+        let (mount, fs) = line.split_once(" - ")?;
+        then!(fs.split_whitespace().next() != Some("cgroup2"), return None);
+        mount.split_whitespace().nth(4).map(PathBuf::from)
+    })
+}
+
+/// Cgroup2 path of our own process
+fn own() -> Option<String> {
+    let cgroup = fs::read_to_string("/proc/self/cgroup").ok()?;
+    cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::").map(str::to_string))
+}
+
+/// Create the cgroup of a process and apply its limits, none when cgroups
+/// are unavailable
+pub fn prepare(id: usize, limits: Option<&Limits>) -> Option<PathBuf> {
+    let slice = slice()?;
+    let path = slice.path.join(format!("process-{id}"));
+
+    if let Err(err) = fs::create_dir_all(&path) {
+        log!("[cgroup] cannot create {}: {err}", path.display());
+        return None;
+    }
+
+    if let Err(err) = write(&path, limits) {
+        log!("[cgroup] {err}");
+        remove(&path);
+        return None;
+    }
+
+    Some(path)
+}
+
+/// Update the limits of a running process, unset limits are lifted
+pub fn apply(path: &Path, limits: Option<&Limits>) {
+    if let Err(err) = write(path, limits) {
+        log!("[cgroup] {err}");
+    }
+}
+
+fn write(path: &Path, limits: Option<&Limits>) -> Result<(), String> {
+    let Some(slice) = slice() else {
+        return Ok(());
+    };
+
+    let limits = limits.cloned().unwrap_or_default();
+
+    for controller in CONTROLLERS {
+        if !slice
+            .controllers
+            .iter()
+            .any(|enabled| enabled == controller)
+        {
+            then!(
+                limits.is_set(controller),
+                log!("[cgroup] the {controller} controller is unavailable, ignoring its limit")
+            );
+            continue;
+        }
+
+        if let Some((file, value)) = limits.value(controller) {
+            fs::write(path.join(file), &value).map_err(|err| {
+                format!(
+                    "cannot write {value} to {}: {err}",
+                    path.join(file).display()
+                )
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the cgroup of a process, it is left alone while processes remain in it
+pub fn remove(path: &Path) {
+    let _ = fs::remove_dir(path);
+}
+
+/// Whether the pid was moved into the cgroup
+pub fn contains(path: &Path, pid: i64) -> bool {
+    fs::read_to_string(path.join("cgroup.procs"))
+        .is_ok_and(|procs| procs.lines().any(|line| line.trim() == pid.to_string()))
+}
+
+/// Memory charged to the cgroup, every descendant of the process included
+pub fn memory(path: &Path) -> Option<u64> {
+    fs::read_to_string(path.join("memory.current"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Cpu usage of the cgroup over a short sample, in percent of every cpu like
+/// the /proc sampling in `process::unix`
+pub fn cpu_percent(path: &Path) -> Option<f64> {
+    let usage = || -> Option<u64> {
+        let stat = fs::read_to_string(path.join("cpu.stat")).ok()?;
+        stat.lines()
+            .find_map(|line| line.strip_prefix("usage_usec ")?.trim().parse().ok())
+    };
+
+    let start = usage()?;
+    let started = Instant::now();
+    thread::sleep(Duration::from_millis(
+        super::unix::PROCESS_OPERATION_DELAY_MS,
+    ));
+    let end = usage()?;
+
+    let available = started.elapsed().as_micros() as f64 * num_cpus::get() as f64;
+    Some((end.saturating_sub(start) as f64 / available * 100.0).min(100.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_merge() {
+        let base = Limits {
+            cpu: Some(0.5),
+            memory: Some("512M".to_string()),
+            pids: None,
+        };
+
+        let update = Limits {
+            memory: Some("0".to_string()),
+            pids: Some(64),
+            ..Default::default()
+        };
+
+        let merged = update.merge(Some(&base)).unwrap();
+        assert_eq!(merged.cpu, Some(0.5));
+        assert_eq!(merged.memory, None);
+        assert_eq!(merged.pids, Some(64));
+
+        let cleared = Limits {
+            cpu: Some(0.0),
+            pids: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(cleared.merge(Some(&merged)), None);
+    }
+
+    #[test]
+    fn test_limits_value() {
+        let limits = Limits {
+            cpu: Some(1.5),
+            memory: Some("1K".to_string()),
+            pids: None,
+        };
+
+        assert_eq!(
+            limits.value("cpu").unwrap(),
+            ("cpu.max", "150000 100000".to_string())
+        );
+        assert_eq!(
+            limits.value("memory").unwrap(),
+            ("memory.max", "1024".to_string())
+        );
+        assert_eq!(
+            limits.value("pids").unwrap(),
+            ("pids.max", "max".to_string())
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        io::{AsRawFd, FromRawFd, OwnedFd},
+        process::CommandExt,
+    },
+    process::{ChildStderr, ChildStdout, Command, Stdio},
+    thread,
+};
+
+/// Hidden subcommand running the collector
+pub const COMMAND: &str = "collect";
+/// Descriptor the collector reads stderr from, stdout arrives on stdin
+const ERROR_FD: i32 = 3;
+
+/// How collected lines are written to the log files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// `<rfc3339> [stream] line`
+    #[default]
+    Text,
+    /// One json object per line
+    Json,
+}
+
+impl Format {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Json => "json",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown log format '{s}', expected text or json")),
+        }
+    }
+}
+
+/// A single collected log line
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Entry {
+    pub ts: String,
+    pub stream: String,
+    #[serde(default)]
+    pub id: usize,
+    #[serde(default)]
+    pub name: String,
+    pub line: String,
+}
+
+impl Entry {
+    /// Render the entry as it is written to the log file
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => format!("{} [{}] {}", self.ts, self.stream, self.line),
+            Format::Json => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+
+    /// Parse a collected line in either format, none for plain lines
+    pub fn parse(line: &str) -> Option<Self> {
+        if line.starts_with('{') {
+            return serde_json::from_str(line).ok();
+        }
+
+        let (ts, rest) = line.split_once(' ')?;
+        DateTime::parse_from_rfc3339(ts).ok()?;
+
+        let (stream, line) = rest.strip_prefix('[')?.split_once("] ")?;
+
+        Some(Entry {
+            ts: ts.to_string(),
+            stream: stream.to_string(),
+            id: 0,
+            name: String::new(),
+            line: line.to_string(),
+        })
+    }
+
+    pub fn time(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.ts).ok()
+    }
+}
+
+/// Settings passed down to `process_run` when the collector is enabled
+#[derive(Clone, Debug)]
+pub struct Collector {
+    pub id: usize,
+    pub name: String,
+    pub format: Format,
+}
+
+impl Collector {
+    /// Start a detached collector draining the pipes of a freshly spawned process
+    pub fn spawn(
+        &self,
+        stdout: ChildStdout,
+        stderr: ChildStderr,
+        out_path: &str,
+        error_path: &str,
+    ) -> io::Result<()> {
+        // the daemon runs with its standard descriptors closed, so the pipe is moved
+        // out of their way before it is handed to the collector on a fixed descriptor
+        let error = match unsafe { libc::fcntl(stderr.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 10) } {
+            -1 => return Err(io::Error::last_os_error()),
+            fd => unsafe { OwnedFd::from_raw_fd(fd) },
+        };
+
+        let error_fd = error.as_raw_fd();
+        let mut cmd = Command::new(env::current_exe()?);
+
+        cmd.arg(COMMAND)
+            .args(["--id", &self.id.to_string(), "--name", &self.name])
+            .args(["--format", self.format.as_str()])
+            .args(["--out", out_path, "--error", error_path])
+            .stdin(Stdio::from(stdout))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+
+        // the collector has to outlive short lived spawners like the cli, so it
+        // is double forked into its own session and never left behind as a zombie
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::dup2(error_fd, ERROR_FD) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                match libc::fork() {
+                    -1 => Err(io::Error::last_os_error()),
+                    0 => {
+                        libc::setsid();
+                        Ok(())
+                    }
+                    _ => libc::_exit(0),
+                }
+            })
+        };
+
+        cmd.spawn()?.wait()?;
+        drop((stderr, error));
+
+        Ok(())
+    }
+
+    /// Entry point of the collector, returns once both streams are closed
+    pub fn run(&self, out_path: &str, error_path: &str) {
+        let stdin = BufReader::new(io::stdin());
+        let stderr = BufReader::new(unsafe { File::from_raw_fd(ERROR_FD) });
+
+        thread::scope(|scope| {
+            scope.spawn(|| self.drain(stdin, "out", out_path));
+            scope.spawn(|| self.drain(stderr, "error", error_path));
+        });
+    }
+
+    fn drain(&self, mut reader: impl BufRead, stream: &str, path: &str) {
+        let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) else {
+            return;
+        };
+
+        let mut buffer = Vec::new();
+        while let Ok(read) = reader.read_until(b'\n', &mut buffer) {
+            if read == 0 {
+                break;
+            }
+
+            let line = String::from_utf8_lossy(&buffer);
+            let entry = Entry {
+                ts: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                stream: stream.to_string(),
+                id: self.id,
+                name: self.name.clone(),
+                line: line.trim_end_matches(['\n', '\r']).to_string(),
+            };
+
+            let _ = writeln!(file, "{}", entry.render(self.format));
+            buffer.clear();
+        }
+    }
+}
+
+/// Interleave the out and error logs by timestamp, keeping the order within each log.
+/// Lines without a timestamp inherit the one of the line before them.
+pub fn merge(out: Vec<String>, error: Vec<String>) -> Vec<(&'static str, String)> {
+    let stamp = |lines: Vec<String>| {
+        let mut last = None;
+        lines
+            .into_iter()
+            .map(|line| match Entry::parse(&line) {
+                Some(entry) => {
+                    last = entry.time().or(last);
+                    (last, format!("{} {}", entry.ts, entry.line))
+                }
+                None => (last, line),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let (out, error) = (stamp(out), stamp(error));
+    let mut merged = Vec::with_capacity(out.len() + error.len());
+    let (mut out, mut error) = (out.into_iter().peekable(), error.into_iter().peekable());
+
+    loop {
+        let take_out = match (out.peek(), error.peek()) {
+            (Some((out_ts, _)), Some((error_ts, _))) => out_ts <= error_ts,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break,
+        };
+
+        match take_out {
+            true => merged.extend(out.next().map(|(_, line)| ("out", line))),
+            false => merged.extend(error.next().map(|(_, line)| ("error", line))),
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ts: &str, stream: &str, line: &str) -> Entry {
+        Entry {
+            ts: ts.to_string(),
+            stream: stream.to_string(),
+            id: 1,
+            name: String::from("app"),
+            line: line.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_entry_formats() {
+        let entry = entry("2024-01-01T00:00:00.000Z", "out", "hello [world]");
+
+        let text = entry.render(Format::Text);
+        assert_eq!(text, "2024-01-01T00:00:00.000Z [out] hello [world]");
+        assert_eq!(Entry::parse(&text).unwrap().line, "hello [world]");
+
+        let json = entry.render(Format::Json);
+        assert!(json.contains(r#""stream":"out""#));
+        assert_eq!(Entry::parse(&json), Some(entry));
+
+        assert_eq!(Entry::parse("plain line"), None);
+    }
+
+    #[test]
+    fn test_merge_interleaves() {
+        let out = vec![
+            entry("2024-01-01T00:00:01.000Z", "out", "a").render(Format::Text),
+            String::from("continued"),
+            entry("2024-01-01T00:00:03.000Z", "out", "c").render(Format::Json),
+        ];
+        let error = vec![entry("2024-01-01T00:00:02.000Z", "error", "b").render(Format::Text)];
+
+        let streams: Vec<&str> = merge(out, error).iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(streams, vec!["out", "out", "error", "out"]);
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{
+    log,
+    process::{Env, Options, Process, Runner, id::Id, ready::Ready},
+};
+
+use global_placeholders::global;
+use macros_rs::then;
+use serde::{Deserialize, Serialize};
+
+use std::{
+    cell::Cell,
+    env,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    time::Duration,
+};
+
+/// Protocol version, bumped whenever a request or response changes shape
+pub const VERSION: u32 = 2;
+
+/// How long the cli waits for the daemon to apply a change, on top of the
+/// time the action may block on its processes
+const TIMEOUT_SECS: u64 = 60;
+
+thread_local! {
+    static OWNER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Mark the current thread as the owner of the process list, it never talks to the socket
+pub fn own() {
+    OWNER.with(|owner| owner.set(true));
+}
+
+/// Whether the current thread owns the process list
+fn owned() -> bool {
+    OWNER.with(Cell::get)
+}
+
+/// Path of the socket the daemon listens on
+pub fn path() -> String {
+    global!("pmc.socket")
+}
+
+/// Change the daemon applies to its process list
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Only return the current list
+    Dump,
+    Start {
+        name: String,
+        command: String,
+        path: PathBuf,
+        watch: Option<String>,
+        instances: usize,
+        options: Options,
+    },
+    Restart {
+        id: usize,
+        dead: bool,
+    },
+    Reload {
+        id: usize,
+    },
+    Stop {
+        id: usize,
+    },
+    Remove {
+        id: usize,
+    },
+    Flush {
+        id: usize,
+    },
+    Rename {
+        id: usize,
+        name: String,
+    },
+    Watch {
+        id: usize,
+        path: String,
+        enabled: bool,
+    },
+    SetEnv {
+        id: usize,
+        env: Env,
+    },
+    ClearEnv {
+        id: usize,
+    },
+    SetReady {
+        id: usize,
+        ready: Ready,
+    },
+    SetOptions {
+        id: usize,
+        options: Options,
+    },
+    SetId {
+        id: Id,
+    },
+    /// Replace the whole list, stopping everything started from the current one,
+    /// a sequence since integer map keys do not survive the flattened request
+    Load {
+        id: Id,
+        list: Vec<Process>,
+    },
+}
+
+impl Action {
+    /// Process the action applies to
+    pub fn id(&self) -> Option<usize> {
+        match self {
+            Action::Dump | Action::Start { .. } | Action::SetId { .. } | Action::Load { .. } => {
+                None
+            }
+            Action::Restart { id, .. }
+            | Action::Reload { id }
+            | Action::Stop { id }
+            | Action::Remove { id }
+            | Action::Flush { id }
+            | Action::Rename { id, .. }
+            | Action::Watch { id, .. }
+            | Action::SetEnv { id, .. }
+            | Action::ClearEnv { id }
+            | Action::SetReady { id, .. }
+            | Action::SetOptions { id, .. } => Some(*id),
+        }
+    }
+
+    /// Whether the action waits on its process stopping or becoming ready
+    pub fn waits(&self) -> bool {
+        matches!(
+            self,
+            Action::Restart { .. }
+                | Action::Reload { .. }
+                | Action::Stop { .. }
+                | Action::Remove { .. }
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Request {
+    pub version: u32,
+    /// Environment of the cli, used in place of the daemon environment,
+    /// defaulted so requests of older versions still decode and get refused
+    #[serde(default)]
+    pub caller: Env,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Response {
+    pub version: u32,
+    /// Process list after the change was applied
+    pub runner: Option<Runner>,
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn ok(runner: Runner) -> Self {
+        Response {
+            version: VERSION,
+            runner: Some(runner),
+            error: None,
+        }
+    }
+
+    pub fn error(error: impl Into<String>) -> Self {
+        Response {
+            version: VERSION,
+            runner: None,
+            error: Some(error.into()),
+        }
+    }
+}
+
+/// Hand an action to the daemon, none when no daemon is listening. `wait` is
+/// how long the action may block on its processes, it is waited for twice
+/// since a request can queue behind a daemon action on the same process
+pub fn send(action: Action, wait: Duration) -> Option<Result<Runner, String>> {
+    then!(owned(), return None);
+
+    let stream = UnixStream::connect(path()).ok()?;
+    let timeout = Some(Duration::from_secs(TIMEOUT_SECS) + wait * 2);
+
+    let request = Request {
+        version: VERSION,
+        caller: env::vars().collect(),
+        action,
+    };
+
+    let exchange = || -> io::Result<Response> {
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+
+        write(&stream, &request)?;
+        read(&stream)
+    };
+
+    match exchange() {
+        Ok(response) if response.version != VERSION => {
+            log!(
+                "[control] daemon protocol v{} does not match v{VERSION}, using the dump",
+                response.version
+            );
+            None
+        }
+        Ok(Response {
+            error: Some(err), ..
+        }) => Some(Err(err)),
+        Ok(Response {
+            runner: Some(runner),
+            ..
+        }) => Some(Ok(runner)),
+        Ok(_) => Some(Err(String::from("empty response from daemon"))),
+        Err(err) => Some(Err(format!("daemon did not answer: {err}"))),
+    }
+}
+
+/// Write a single message as one line of json
+pub fn write<T: Serialize>(mut stream: &UnixStream, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    stream.write_all(&line)
+}
+
+/// Read a single message written by `write`
+pub fn read<T: serde::de::DeserializeOwned>(stream: &UnixStream) -> io::Result<T> {
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_format() {
+        let request = Request {
+            version: VERSION,
+            caller: Env::from([(String::from("PORT"), String::from("80"))]),
+            action: Action::Rename {
+                id: 4,
+                name: String::from("api"),
+            },
+        };
+
+        let encoded = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            encoded,
+            r#"{"version":2,"caller":{"PORT":"80"},"action":"rename","id":4,"name":"api"}"#
+        );
+
+        let decoded: Request = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded.action.id(), Some(4));
+        assert!(matches!(decoded.action, Action::Rename { name, .. } if name == "api"));
+    }
+
+    #[test]
+    fn test_older_request() {
+        let encoded = r#"{"version":1,"env":{"PORT":"80"},"action":"stop","id":4}"#;
+        let decoded: Request = serde_json::from_str(encoded).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert_ne!(decoded.version, VERSION);
+    }
+
+    #[test]
+    fn test_set_env_request() {
+        let request = Request {
+            version: VERSION,
+            caller: Env::new(),
+            action: Action::SetEnv {
+                id: 1,
+                env: Env::from([(String::from("PORT"), String::from("80"))]),
+            },
+        };
+
+        let encoded = serde_json::to_string(&request).unwrap();
+        let decoded: Request = serde_json::from_str(&encoded).unwrap();
+        assert!(matches!(decoded.action, Action::SetEnv { env, .. } if env["PORT"] == "80"));
+    }
+
+    #[test]
+    fn test_exchange() {
+        let (client, server) = UnixStream::pair().unwrap();
+
+        write(&client, &Response::error("Process (9) not found")).unwrap();
+        let response: Response = read(&server).unwrap();
+
+        assert_eq!(response.version, VERSION);
+        assert!(response.runner.is_none());
+        assert_eq!(response.error.as_deref(), Some("Process (9) not found"));
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::{DateTime, Local, TimeZone, Timelike, Utc};
+use croner::Cron;
+use std::str::FromStr;
+
+/// Parse a standard cron expression, 5 fields or 6 with leading seconds
+pub fn parse(expression: &str) -> Result<Cron, String> {
+    let fields = expression.split_whitespace().count();
+
+    if !expression.trim_start().starts_with('@') && !(5..=6).contains(&fields) {
+        return Err(format!(
+            "invalid cron expression '{expression}', expected 5 or 6 fields"
+        ));
+    }
+
+    Cron::from_str(expression)
+        .map_err(|err| format!("invalid cron expression '{expression}': {err}"))
+}
+
+/// Next time the expression fires after the given time. Like cron it is
+/// evaluated in local time, so `0 4 * * *` fires at 04:00 on the host clock.
+pub fn next(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    next_in(expression, after.with_timezone(&Local)).map(|next| next.with_timezone(&Utc))
+}
+
+fn next_in<Tz: TimeZone>(expression: &str, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let after = after.with_nanosecond(0)?;
+
+    parse(expression)
+        .ok()?
+        .find_next_occurrence(&after, false)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cron_next() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 10, 7, 30).unwrap()
+            + chrono::Duration::milliseconds(250);
+
+        assert_eq!(
+            next_in("*/15 * * * *", now),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 10, 15, 0).unwrap())
+        );
+        assert_eq!(
+            next_in("10 0 12 * * *", now),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 10).unwrap())
+        );
+        assert_eq!(
+            next_in("@daily", now),
+            Some(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_cron_local() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 10, 7, 30).unwrap();
+        let fired = next("0 4 * * *", now).unwrap().with_timezone(&Local);
+
+        assert_eq!((fired.hour(), fired.minute()), (4, 0));
+    }
+
+    #[test]
+    fn test_cron_invalid() {
+        assert!(parse("* * *").is_err());
+        assert!(parse("* * * * * * 2030").is_err());
+        assert!(parse("61 * * * *").is_err());
+        assert_eq!(next("nope", Utc::now()), None);
+    }
+}
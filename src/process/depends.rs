@@ -0,0 +1,191 @@
+use crate::process::Runner;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::{
+    collections::BTreeMap,
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+/// How long a dependency may take to become ready
+pub const WAIT_TIMEOUT_SECS: u64 = 60;
+const WAIT_POLL_MS: u64 = 500;
+
+/// Processes that have to be started before a process
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct Depends {
+    /// Names of the processes started first
+    pub on: Vec<String>,
+    /// Wait until every dependency is online and ready before starting
+    #[serde(default)]
+    pub wait: bool,
+}
+
+impl Depends {
+    /// Dependencies of a process, none without any name
+    pub fn new(on: Vec<String>, wait: bool) -> Option<Self> {
+        let on: Vec<String> = on.into_iter().filter(|name| !name.is_empty()).collect();
+        (!on.is_empty()).then_some(Depends { on, wait })
+    }
+}
+
+impl fmt::Display for Depends {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.on.join(", "))?;
+        match self.wait {
+            true => write!(f, ", waits until ready"),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Order names so every name comes after its dependencies, keeping the given
+/// order otherwise. Dependencies outside of the given names are left out.
+pub fn order<'a, I>(items: I) -> Result<Vec<&'a str>, String>
+where
+    I: IntoIterator<Item = (&'a str, &'a [String])>,
+{
+    let items: Vec<(&str, &[String])> = items.into_iter().collect();
+    let graph: BTreeMap<&str, &[String]> = items.iter().copied().collect();
+
+    let mut ordered = vec![];
+    let mut path = vec![];
+
+    fn visit<'a>(
+        name: &'a str,
+        graph: &BTreeMap<&'a str, &'a [String]>,
+        path: &mut Vec<&'a str>,
+        ordered: &mut Vec<&'a str>,
+    ) -> Result<(), String> {
+        if ordered.contains(&name) {
+            return Ok(());
+        }
+
+        if let Some(start) = path.iter().position(|visited| *visited == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name);
+            return Err(format!("dependency cycle {}", cycle.join(" -> ")));
+        }
+
+        path.push(name);
+        for dependency in graph[name] {
+            if let Some((dependency, _)) = graph.get_key_value(dependency.as_str()) {
+                visit(dependency, graph, path, ordered)?;
+            }
+        }
+        path.pop();
+
+        ordered.push(name);
+        Ok(())
+    }
+
+    for &(name, _) in &items {
+        visit(name, &graph, &mut path, &mut ordered)?;
+    }
+
+    Ok(ordered)
+}
+
+/// Check that giving a process these dependencies keeps the list free of cycles
+pub fn check(runner: &Runner, name: &str, on: &[String]) -> Result<(), String> {
+    let mut groups: Vec<(&str, &[String])> = vec![(name, on)];
+
+    for process in runner.list.values() {
+        if !groups.iter().any(|(group, _)| *group == process.name) {
+            let on = (process.depends.as_ref()).map_or(&[][..], |depends| &depends.on[..]);
+            groups.push((&process.name, on));
+        }
+    }
+
+    order(groups).map(|_| ())
+}
+
+/// Block until every process with one of the names is online and ready,
+/// fetching the list again on every poll
+pub fn wait(names: &[String], mut fetch: impl FnMut() -> Runner) -> Result<(), String> {
+    let started = Instant::now();
+
+    loop {
+        let runner = fetch();
+        let mut pending = None;
+
+        for name in names {
+            let statuses: Vec<&str> = (runner.list.values())
+                .filter(|process| &process.name == name)
+                .map(|process| process.status())
+                .collect();
+
+            if statuses.is_empty() {
+                return Err(format!("dependency {name} does not exist"));
+            }
+
+            if statuses.contains(&"stopped") {
+                return Err(format!("dependency {name} is stopped"));
+            }
+
+            if statuses.iter().any(|status| *status != "online") {
+                pending = Some(name);
+            }
+        }
+
+        let Some(name) = pending else {
+            return Ok(());
+        };
+
+        if started.elapsed() >= Duration::from_secs(WAIT_TIMEOUT_SECS) {
+            return Err(format!(
+                "dependency {name} was not ready after {WAIT_TIMEOUT_SECS}s"
+            ));
+        }
+
+        thread::sleep(Duration::from_millis(WAIT_POLL_MS));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_order() {
+        let (api, worker, proxy) = (names(&["worker", "proxy"]), names(&["proxy"]), names(&[]));
+        let items = [
+            ("api", &api[..]),
+            ("worker", &worker[..]),
+            ("proxy", &proxy[..]),
+        ];
+
+        assert_eq!(order(items).unwrap(), vec!["proxy", "worker", "api"]);
+    }
+
+    #[test]
+    fn test_order_ignores_unknown() {
+        let api = names(&["db"]);
+        let items = [("web", &[][..]), ("api", &api[..])];
+
+        assert_eq!(order(items).unwrap(), vec!["web", "api"]);
+    }
+
+    #[test]
+    fn test_order_cycle() {
+        let (a, b, c) = (names(&["b"]), names(&["c"]), names(&["a"]));
+        let items = [("a", &a[..]), ("b", &b[..]), ("c", &c[..])];
+
+        assert_eq!(
+            order(items).unwrap_err(),
+            "dependency cycle a -> b -> c -> a"
+        );
+
+        let own = names(&["a"]);
+        assert_eq!(
+            order([("a", &own[..])]).unwrap_err(),
+            "dependency cycle a -> a"
+        );
+    }
+}
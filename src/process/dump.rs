@@ -1,17 +1,100 @@
 use crate::{
     file::{self, Exists},
     helpers, log,
-    process::{Runner, id::Id},
+    process::{Runner, Source, id::Id, schema},
 };
 
 use colored::Colorize;
 use global_placeholders::global;
-use macros_rs::{crashln, fmtstr, string};
+use macros_rs::{crashln, fmtstr, string, then};
 use reqwest::blocking::Client;
 use reqwest::header::{HeaderMap, HeaderValue};
-use std::{collections::BTreeMap, fs};
 
-pub fn from(address: &str, token: Option<&str>) -> Result<Runner, anyhow::Error> {
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::{fd::AsRawFd, unix::fs::MetadataExt},
+    path::Path,
+    sync::{Arc, Weak},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Number of rolling backups kept next to the dump
+pub const BACKUPS: usize = 5;
+
+/// Minimum age of the newest backup before another one is taken
+const BACKUP_INTERVAL_SECS: u64 = 60;
+
+thread_local! {
+    /// Lock shared by everything on this thread that reads or writes the dump,
+    /// other threads open their own and wait on it like another process would
+    static HELD: RefCell<Weak<Lock>> = const { RefCell::new(Weak::new()) };
+}
+
+/// Exclusive advisory lock on the dump, released once every holder dropped it
+#[derive(Debug)]
+pub struct Lock {
+    file: File,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+/// Take the dump lock, or share it when this thread already holds it
+pub fn lock() -> Arc<Lock> {
+    if let Some(lock) = HELD.with_borrow(Weak::upgrade) {
+        return lock;
+    }
+
+    let path = format!("{}.lock", global!("pmc.dump"));
+    let file = match OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(err) => crashln!(
+            "{} Cannot open {path}.\n{}",
+            *helpers::FAIL,
+            string!(err).white()
+        ),
+    };
+
+    while unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            crashln!(
+                "{} Cannot lock {path}.\n{}",
+                *helpers::FAIL,
+                string!(err).white()
+            )
+        }
+    }
+
+    let lock = Arc::new(Lock { file });
+    HELD.set(Arc::downgrade(&lock));
+    lock
+}
+
+/// Path of a rolling backup, 1 being the newest
+pub fn backup_path(index: usize) -> String {
+    format!("{}.{index}", global!("pmc.dump"))
+}
+
+/// Read a backup without touching the dump, upgrading it to the current schema
+pub fn read_backup(index: usize) -> Result<Runner, String> {
+    let bytes = fs::read(backup_path(index)).map_err(|err| string!(err))?;
+    schema::migrate(&bytes).map(|migration| migration.runner)
+}
+
+/// Fetch the dump of a remote, `reveal` asks for the values of secret variables
+pub fn from(address: &str, token: Option<&str>, reveal: bool) -> Result<Runner, anyhow::Error> {
     let client = Client::new();
     let mut headers = HeaderMap::new();
 
@@ -24,34 +107,62 @@ pub fn from(address: &str, token: Option<&str>) -> Result<Runner, anyhow::Error>
 
     let response = client
         .get(fmtstr!("{address}/daemon/dump"))
+        .query(&[("reveal", reveal)])
         .headers(headers)
-        .send()?;
+        .send()?
+        .error_for_status()?;
     let bytes = response.bytes()?;
 
-    Ok(file::from_object(&bytes))
+    let migration = schema::migrate(&bytes).map_err(anyhow::Error::msg)?;
+    Ok(migration.runner)
 }
 
 pub fn read() -> Runner {
+    let _lock = lock();
+
     if !Exists::check(&global!("pmc.dump")).file() {
         let runner = Runner {
+            version: schema::VERSION,
             id: Id::new(0),
             list: BTreeMap::new(),
             remote: None,
+            source: Source::Memory,
+            caller: None,
+            guard: None,
         };
 
         write(&runner);
         log!("created dump file");
     }
 
-    file::read_object(global!("pmc.dump"))
+    let migration = match schema::migrate(&file::raw(global!("pmc.dump"))) {
+        Ok(migration) => migration,
+        Err(err) => crashln!("{} Cannot parse dumpfile.\n{}", *helpers::FAIL, err.white()),
+    };
+
+    if !migration.is_current() {
+        log!(
+            "[dump::read] Upgraded dump from schema v{} to v{}",
+            migration.from,
+            schema::VERSION
+        );
+    }
+
+    migration.runner
 }
 
 pub fn raw() -> Vec<u8> {
+    let _lock = lock();
+
     if !Exists::check(&global!("pmc.dump")).file() {
         let runner = Runner {
+            version: schema::VERSION,
             id: Id::new(0),
             list: BTreeMap::new(),
             remote: None,
+            source: Source::Memory,
+            caller: None,
+            guard: None,
         };
 
         write(&runner);
@@ -61,6 +172,20 @@ pub fn raw() -> Vec<u8> {
     file::raw(global!("pmc.dump"))
 }
 
+/// The dump with the values of secret variables replaced
+pub fn redacted(patterns: &[String]) -> Vec<u8> {
+    let mut runner = read();
+    runner.redact(patterns);
+
+    match ron::ser::to_string(&runner) {
+        Ok(contents) => contents.into_bytes(),
+        Err(err) => {
+            log!("[dump::redacted] Cannot encode dump: {err}");
+            vec![]
+        }
+    }
+}
+
 pub fn write(dump: &Runner) {
     let encoded = match ron::ser::to_string(&dump) {
         Ok(contents) => contents,
@@ -71,7 +196,11 @@ pub fn write(dump: &Runner) {
         ),
     };
 
-    if let Err(err) = fs::write(global!("pmc.dump"), encoded) {
+    let _lock = lock();
+    let path = global!("pmc.dump");
+
+    backup(&path);
+    if let Err(err) = replace(&path, encoded.as_bytes()) {
         crashln!(
             "{} Error writing dumpfile.\n{}",
             *helpers::FAIL,
@@ -79,3 +208,68 @@ pub fn write(dump: &Runner) {
         )
     }
 }
+
+/// Write to a temporary file and rename it over the dump, so a crash never leaves it truncated
+fn replace(path: &str, contents: &[u8]) -> io::Result<()> {
+    let temp = format!("{path}.tmp");
+    let mut file = File::create(&temp)?;
+
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temp, path)?;
+
+    if let Some(parent) = Path::new(path).parent() {
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Keep the dump about to be replaced as the newest backup, at most once per interval
+fn backup(path: &str) {
+    // a hard link keeps the mtime of the old dump, its ctime is when the backup was taken
+    let taken = fs::metadata(backup_path(1)).map(|metadata| metadata.ctime());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    let recent = taken.is_ok_and(|taken| now - taken < BACKUP_INTERVAL_SECS as i64);
+
+    then!(recent || !Exists::check(path).file(), return);
+
+    for index in (1..BACKUPS).rev() {
+        let _ = fs::rename(backup_path(index), backup_path(index + 1));
+    }
+
+    // the dump is only ever renamed over, so a link keeps the old contents
+    if fs::hard_link(path, backup_path(1)).is_err()
+        && let Err(err) = fs::copy(path, backup_path(1))
+    {
+        log!("[dump::backup] Cannot back up dump: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_leaves_no_temp_file() {
+        let dir = std::env::temp_dir().join(format!("pmc-dump-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("process.dump").to_string_lossy().to_string();
+
+        fs::write(&path, "(id: (counter: 1), list: {})").unwrap();
+        let before = fs::metadata(&path).unwrap().ino();
+
+        replace(&path, b"(id: (counter: 2), list: {})").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "(id: (counter: 2), list: {})"
+        );
+        assert_ne!(fs::metadata(&path).unwrap().ino(), before);
+        assert!(!Path::new(&format!("{path}.tmp")).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
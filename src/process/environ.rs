@@ -0,0 +1,443 @@
+use crate::process::{Env, watch::matches};
+
+use macros_rs::string;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Search path of processes in clean mode
+pub const CLEAN_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Shown in place of the value of a secret variable
+pub const REDACTED: &str = "<redacted>";
+
+/// Variable names whose values are redacted unless configured otherwise
+pub fn default_redact() -> Vec<String> {
+    vec![
+        string!("*_TOKEN"),
+        string!("*SECRET*"),
+        string!("*PASSWORD*"),
+    ]
+}
+
+/// Where the environment of a process comes from besides its declared variables
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// The shell that started it, merged again on every restart
+    #[default]
+    Inherit,
+    /// The shell that created it, frozen at creation
+    Snapshot,
+    /// Only a minimal PATH and HOME
+    Clean,
+}
+
+impl Mode {
+    /// Environment a process starts from, `caller` is the shell starting it
+    pub fn base(&self, caller: Env) -> Env {
+        match self {
+            Mode::Inherit | Mode::Snapshot => caller,
+            Mode::Clean => {
+                let mut env = Env::from([("PATH".to_string(), CLEAN_PATH.to_string())]);
+                if let Some(home) = home::home_dir() {
+                    env.insert("HOME".to_string(), home.to_string_lossy().into_owned());
+                }
+                env
+            }
+        }
+    }
+
+    /// Source shown for the variables that do not come from a declaration
+    pub fn source(&self) -> &'static str {
+        match self {
+            Mode::Inherit => "inherited",
+            Mode::Snapshot => "snapshot",
+            Mode::Clean => "clean",
+        }
+    }
+
+    /// Whether a restart merges the environment of the shell restarting it
+    pub fn inherits(&self) -> bool {
+        *self == Mode::Inherit
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inherit" => Ok(Mode::Inherit),
+            "snapshot" => Ok(Mode::Snapshot),
+            "clean" => Ok(Mode::Clean),
+            _ => Err(format!(
+                "invalid env mode '{s}' (expected inherit, snapshot or clean)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Inherit => write!(f, "inherit"),
+            Mode::Snapshot => write!(f, "snapshot"),
+            Mode::Clean => write!(f, "clean"),
+        }
+    }
+}
+
+/// A variable and where its value came from
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct Var {
+    #[schema(example = "production")]
+    pub value: String,
+    /// Env file, env block or profile, like `env_file .env`
+    #[schema(example = "env \"production\"")]
+    pub source: String,
+}
+
+/// Variables declared for a process on top of the environment it inherits.
+/// Env files are read on every start, so edits apply with the next restart.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Declared {
+    /// Dotenv files relative to the working directory, later files win
+    #[schema(value_type = Vec<String>, example = json!([".env", ".env.local"]))]
+    pub files: Vec<PathBuf>,
+    /// Variables of the env block, they win over the files
+    pub vars: Env,
+    /// Named sets of variables like production, applied over everything else
+    pub profiles: BTreeMap<String, Env>,
+    /// Profile in use
+    #[schema(example = "production")]
+    pub profile: Option<String>,
+}
+
+impl Declared {
+    pub fn is_empty(&self) -> bool {
+        self == &Declared::default()
+    }
+
+    /// Hide the values another copy has redacted, so the two only differ
+    /// where something that can be read differs
+    pub fn mask(&mut self, redacted: &Declared) {
+        let hide = |env: &mut Env, other: &Env| {
+            for (key, value) in env.iter_mut() {
+                if other.get(key).is_some_and(|other| other == REDACTED) {
+                    *value = string!(REDACTED);
+                }
+            }
+        };
+
+        hide(&mut self.vars, &redacted.vars);
+        for (name, env) in self.profiles.iter_mut() {
+            if let Some(other) = redacted.profiles.get(name) {
+                hide(env, other);
+            }
+        }
+    }
+
+    /// Every declared variable with its source, sources that cannot be read
+    /// are left out and returned as errors
+    pub fn resolve(&self, cwd: &Path) -> (BTreeMap<String, Var>, Vec<String>) {
+        let mut vars = BTreeMap::new();
+        let mut errors = vec![];
+
+        let mut add = |env: &Env, source: String| {
+            for (key, value) in env {
+                let var = Var {
+                    value: value.clone(),
+                    source: source.clone(),
+                };
+                vars.insert(key.clone(), var);
+            }
+        };
+
+        for file in &self.files {
+            match read(&cwd.join(file)) {
+                Ok(env) => add(&env, format!("env_file {}", file.display())),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        add(&self.vars, "env".to_string());
+
+        if let Some(profile) = &self.profile {
+            match self.profiles.get(profile) {
+                Some(env) => add(env, format!("env \"{profile}\"")),
+                None => errors.push(format!("env profile '{profile}' does not exist")),
+            }
+        }
+
+        (vars, errors)
+    }
+}
+
+impl fmt::Display for Declared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+
+        if !self.files.is_empty() {
+            let files: Vec<String> = (self.files.iter())
+                .map(|file| file.display().to_string())
+                .collect();
+            parts.push(format!("files {}", files.join(", ")));
+        }
+        match self.vars.len() {
+            0 => {}
+            1 => parts.push(string!("1 var")),
+            count => parts.push(format!("{count} vars")),
+        }
+        if let Some(profile) = &self.profile {
+            parts.push(format!("profile {profile}"));
+        }
+
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Declared env settings, unset fields keep the current value
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Settings {
+    /// Dotenv files, an empty list removes them
+    #[schema(value_type = Option<Vec<String>>, example = json!([".env"]))]
+    pub files: Option<Vec<PathBuf>>,
+    /// Variables of the env block, replacing the current ones
+    pub vars: Option<Env>,
+    /// Named profiles, replacing the current ones
+    pub profiles: Option<BTreeMap<String, Env>>,
+    /// Profile to use, an empty name removes it
+    #[schema(example = "production")]
+    pub profile: Option<String>,
+}
+
+impl Settings {
+    pub fn apply(&self, declared: &mut Declared) {
+        if let Some(files) = &self.files {
+            declared.files = files.clone();
+        }
+        if let Some(vars) = &self.vars {
+            declared.vars = vars.clone();
+        }
+        if let Some(profiles) = &self.profiles {
+            declared.profiles = profiles.clone();
+        }
+        if let Some(profile) = &self.profile {
+            declared.profile = (!profile.is_empty()).then(|| profile.clone());
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self != &Settings::default()
+    }
+}
+
+/// Whether a variable name matches one of the redaction patterns, ignoring case
+pub fn secret(patterns: &[String], key: &str) -> bool {
+    let key = key.to_uppercase();
+    (patterns.iter()).any(|pattern| matches(pattern.to_uppercase().as_bytes(), key.as_bytes()))
+}
+
+/// Replace the values of secret variables with the placeholder
+pub fn redact<'a>(
+    patterns: &[String],
+    vars: impl IntoIterator<Item = (&'a String, &'a mut String)>,
+) {
+    for (key, value) in vars {
+        if secret(patterns, key) {
+            *value = string!(REDACTED);
+        }
+    }
+}
+
+/// Read a dotenv file
+pub fn read(path: &Path) -> Result<Env, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+
+    parse(&contents).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// Parse dotenv contents, `KEY=value` lines optionally prefixed with export
+/// and # comments. Single quoted values are taken as they are, double quoted
+/// ones unescape \n, \t, \" and \\.
+pub fn parse(contents: &str) -> Result<Env, String> {
+    let mut env = Env::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let error = |message: &str| format!("line {}: {message}", index + 1);
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error("expected KEY=value"));
+        };
+
+        let key = key.trim();
+        let valid = key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && key.chars().next().is_some_and(|c| !c.is_ascii_digit());
+
+        if !valid {
+            return Err(error(&format!("invalid variable name '{key}'")));
+        }
+
+        let value = value.trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                unquote(&value[1..], quote).ok_or_else(|| error("unterminated quote"))?
+            }
+            _ => match value.find(" #") {
+                Some(comment) => value[..comment].trim_end(),
+                None => value.trim_end(),
+            }
+            .to_string(),
+        };
+
+        env.insert(key.to_string(), value);
+    }
+
+    Ok(env)
+}
+
+fn unquote(value: &str, quote: char) -> Option<String> {
+    let mut unquoted = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c == quote => return Some(unquoted),
+            '\\' if quote == '"' => match chars.next()? {
+                'n' => unquoted.push('\n'),
+                't' => unquoted.push('\t'),
+                escaped => unquoted.push(escaped),
+            },
+            c => unquoted.push(c),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let contents = r#"
+            # database
+            DATABASE_URL=postgres://localhost/app
+            export NODE_ENV=production
+            GREETING="hello\n\"world\""
+            RAW='a\nb' # kept as is
+            PORT=3000 # inline comment
+            EMPTY=
+        "#;
+
+        let env = parse(contents).unwrap();
+        assert_eq!(env["DATABASE_URL"], "postgres://localhost/app");
+        assert_eq!(env["NODE_ENV"], "production");
+        assert_eq!(env["GREETING"], "hello\n\"world\"");
+        assert_eq!(env["RAW"], "a\\nb");
+        assert_eq!(env["PORT"], "3000");
+        assert_eq!(env["EMPTY"], "");
+
+        assert_eq!(
+            parse("A=1\nnot a line").unwrap_err(),
+            "line 2: expected KEY=value"
+        );
+        assert!(parse("1A=1").is_err());
+        assert!(parse("A=\"open").is_err());
+    }
+
+    #[test]
+    fn test_mode_base() {
+        let caller = Env::from([("SHELL_ONLY".to_string(), "1".to_string())]);
+
+        assert_eq!(Mode::Snapshot.base(caller.clone()), caller);
+        assert!(!Mode::Clean.base(caller.clone()).contains_key("SHELL_ONLY"));
+        assert_eq!(Mode::Clean.base(caller)["PATH"], CLEAN_PATH);
+        assert_eq!("clean".parse::<Mode>(), Ok(Mode::Clean));
+        assert!("isolated".parse::<Mode>().is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let patterns = default_redact();
+        let mut env = Env::from([
+            ("GITHUB_TOKEN".to_string(), "ghp_1".to_string()),
+            ("db_password".to_string(), "hunter2".to_string()),
+            ("CLIENT_SECRET_ID".to_string(), "s".to_string()),
+            ("TOKENIZER".to_string(), "bpe".to_string()),
+        ]);
+
+        redact(&patterns, &mut env);
+        assert_eq!(env["GITHUB_TOKEN"], REDACTED);
+        assert_eq!(env["db_password"], REDACTED);
+        assert_eq!(env["CLIENT_SECRET_ID"], REDACTED);
+        assert_eq!(env["TOKENIZER"], "bpe");
+        assert!(!secret(&[], "GITHUB_TOKEN"));
+    }
+
+    #[test]
+    fn test_mask() {
+        let redacted = Declared {
+            vars: Env::from([
+                ("API_TOKEN".to_string(), REDACTED.to_string()),
+                ("PORT".to_string(), "80".to_string()),
+            ]),
+            ..Declared::default()
+        };
+
+        let mut declared = Declared {
+            vars: Env::from([
+                ("API_TOKEN".to_string(), "abc".to_string()),
+                ("PORT".to_string(), "81".to_string()),
+            ]),
+            ..Declared::default()
+        };
+
+        declared.mask(&redacted);
+        assert_eq!(declared.vars["API_TOKEN"], REDACTED);
+        assert_eq!(declared.vars["PORT"], "81");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = std::env::temp_dir().join(format!("pmc-environ-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(".env"), "A=file\nB=file\nC=file").unwrap();
+
+        let declared = Declared {
+            files: vec![PathBuf::from(".env"), PathBuf::from(".env.missing")],
+            vars: Env::from([("B".to_string(), "block".to_string())]),
+            profiles: BTreeMap::from([(
+                "production".to_string(),
+                Env::from([("C".to_string(), "profile".to_string())]),
+            )]),
+            profile: Some("production".to_string()),
+        };
+
+        let (vars, errors) = declared.resolve(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(vars["A"].source, "env_file .env");
+        assert_eq!((&*vars["B"].value, &*vars["B"].source), ("block", "env"));
+        assert_eq!(vars["C"].source, "env \"production\"");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains(".env.missing"));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::helpers;
+use chrono::serde::ts_milliseconds;
+use chrono::{DateTime, Utc};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    sys::wait::{WaitPidFlag, WaitStatus, waitpid},
+    unistd::Pid,
+};
+
+/// Amount of exit records kept per process
+pub const MAX_EXITS: usize = 10;
+const WAIT_POLL_MS: u64 = 25;
+
+/// Why and when a process died
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct Exit {
+    /// Exit code, set when the process exited on its own
+    #[schema(example = 1)]
+    pub code: Option<i32>,
+    /// Signal name, set when the process was killed by a signal
+    #[schema(example = "SIGKILL")]
+    pub signal: Option<String>,
+    #[serde(with = "ts_milliseconds")]
+    #[schema(value_type = i64, example = 946688400000i64)]
+    pub time: DateTime<Utc>,
+    /// Milliseconds the process was up before it died
+    #[schema(example = 60000)]
+    pub uptime: u64,
+    /// Whether it outlived its kill timeout and was sent SIGKILL
+    #[serde(default)]
+    pub forced: bool,
+}
+
+impl Exit {
+    /// Record a death whose status could not be collected
+    pub fn unknown(started: DateTime<Utc>) -> Self {
+        let time = Utc::now();
+
+        Exit {
+            time,
+            code: None,
+            signal: None,
+            uptime: (time - started).num_milliseconds().max(0) as u64,
+            forced: false,
+        }
+    }
+
+    /// Collect the status of a dead child of this process without blocking,
+    /// none when it is still running or was spawned by another process
+    pub fn reap(pid: i64, started: DateTime<Utc>) -> Option<Self> {
+        Exit::wait(pid, started, Duration::ZERO)
+    }
+
+    /// Like `reap`, but keeps polling a running child until the timeout passes
+    pub fn wait(pid: i64, started: DateTime<Utc>, timeout: Duration) -> Option<Self> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let mut exit = Exit::unknown(started);
+
+            match waitpid(Pid::from_raw(pid as i32), Some(WaitPidFlag::WNOHANG)).ok()? {
+                WaitStatus::Exited(_, code) => exit.code = Some(code),
+                WaitStatus::Signaled(_, signal, _) => {
+                    exit.signal = Some(signal.as_str().to_string())
+                }
+                _ if Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(WAIT_POLL_MS));
+                    continue;
+                }
+                _ => return None,
+            }
+
+            return Some(exit);
+        }
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let uptime = helpers::format_seconds((self.uptime / 1000) as i64);
+
+        match (&self.code, &self.signal) {
+            (Some(code), _) => write!(f, "code {code} after {uptime}"),
+            (_, Some(signal)) => write!(f, "{signal} after {uptime}"),
+            _ => write!(f, "unknown after {uptime}"),
+        }?;
+
+        match self.forced {
+            true => write!(f, ", forced"),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{process::Command, thread, time::Duration};
+
+    #[test]
+    fn test_reap_exit_code() {
+        let pid = Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap()
+            .id() as i64;
+
+        let exit = loop {
+            if let Some(exit) = Exit::reap(pid, Utc::now()) {
+                break exit;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        assert_eq!(exit.code, Some(3));
+        assert_eq!(exit.signal, None);
+        assert!(Exit::reap(pid, Utc::now()).is_none());
+    }
+
+    #[test]
+    fn test_reap_signal() {
+        let pid = Command::new("sleep").arg("30").spawn().unwrap().id() as i64;
+
+        assert!(Exit::reap(pid, Utc::now()).is_none());
+        nix::sys::signal::kill(Pid::from_raw(pid as i32), nix::sys::signal::Signal::SIGKILL)
+            .unwrap();
+
+        let exit = Exit::wait(pid, Utc::now(), Duration::from_secs(5)).unwrap();
+
+        assert_eq!(exit.code, None);
+        assert_eq!(exit.signal.as_deref(), Some("SIGKILL"));
+        assert!(exit.to_string().starts_with("SIGKILL after"));
+    }
+}
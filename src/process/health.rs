@@ -0,0 +1,280 @@
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+const COMMAND_POLL_MS: u64 = 50;
+
+/// What a probe checks
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// No check, removes the probe when passed as an option
+    #[default]
+    Off,
+    /// GET request that has to answer with a 2xx status
+    Http(String),
+    /// Connect to a port on localhost, or to host:port
+    Tcp(String),
+    /// Shell command run in the process directory that has to exit with 0
+    Command(String),
+}
+
+impl FromStr for Check {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Ok(Check::Http(s.to_string()));
+        }
+
+        match s.split_once(':') {
+            Some(("http", url)) => Ok(Check::Http(url.to_string())),
+            Some(("tcp", target)) => match target.rsplit(':').next().map(u16::from_str) {
+                Some(Ok(_)) => Ok(Check::Tcp(target.to_string())),
+                _ => Err(format!("invalid port in '{target}'")),
+            },
+            Some(("cmd" | "command", command)) => Ok(Check::Command(command.to_string())),
+            None if s == "off" => Ok(Check::Off),
+            _ => Err(format!(
+                "unknown check '{s}', expected http:<URL>, tcp:<PORT>, cmd:<COMMAND> or off"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Check::Off => write!(f, "off"),
+            Check::Http(url) => write!(f, "http:{url}"),
+            Check::Tcp(target) => write!(f, "tcp:{target}"),
+            Check::Command(command) => write!(f, "cmd:{command}"),
+        }
+    }
+}
+
+/// Check run on an interval, failing after enough consecutive failures
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Probe {
+    pub check: Check,
+    /// Seconds between checks
+    #[schema(example = 10)]
+    pub interval: u64,
+    /// Seconds a single check may take
+    #[schema(example = 5)]
+    pub timeout: u64,
+    /// Consecutive failures before the probe fails
+    #[schema(example = 3)]
+    pub threshold: u32,
+}
+
+impl Default for Probe {
+    fn default() -> Self {
+        Probe {
+            check: Check::Off,
+            interval: 10,
+            timeout: 5,
+            threshold: 3,
+        }
+    }
+}
+
+impl Probe {
+    pub fn is_enabled(&self) -> bool {
+        self.check != Check::Off
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval.max(1))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.max(1))
+    }
+
+    /// Run the check once, true when it passed
+    pub fn run(&self, path: &Path, shell: &str, args: &[String]) -> bool {
+        match &self.check {
+            Check::Off => true,
+            Check::Http(url) => http(url, self.timeout()),
+            Check::Tcp(target) => tcp(target, self.timeout()),
+            Check::Command(command) => {
+                let mut process = Command::new(shell);
+                process.args(args).arg(command).current_dir(path);
+                exits_ok(process, self.timeout())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} every {}s, timeout {}s, {} failures",
+            self.check, self.interval, self.timeout, self.threshold
+        )
+    }
+}
+
+/// Probes of a process together with its last readiness result
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct Health {
+    /// Restarts the process once it fails
+    pub liveness: Option<Probe>,
+    /// Marks the process unready while it fails
+    pub readiness: Option<Probe>,
+    /// Whether the readiness probe passed since the process was started
+    #[serde(default)]
+    pub ready: bool,
+}
+
+impl Health {
+    /// Health of a process with the given probes, none without any enabled probe
+    pub fn new(liveness: Option<Probe>, readiness: Option<Probe>) -> Option<Self> {
+        let liveness = liveness.filter(Probe::is_enabled);
+        let readiness = readiness.filter(Probe::is_enabled);
+
+        (liveness.is_some() || readiness.is_some()).then_some(Health {
+            liveness,
+            readiness,
+            ready: false,
+        })
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.readiness.is_none() || self.ready
+    }
+}
+
+fn http(url: &str, timeout: Duration) -> bool {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(timeout)
+        .build();
+
+    client
+        .and_then(|client| client.get(url).send())
+        .is_ok_and(|response| response.status().is_success())
+}
+
+fn tcp(target: &str, timeout: Duration) -> bool {
+    let target = match target.contains(':') {
+        true => target.to_string(),
+        false => format!("127.0.0.1:{target}"),
+    };
+
+    let Ok(addresses) = target.to_socket_addrs() else {
+        return false;
+    };
+
+    addresses
+        .into_iter()
+        .any(|address| TcpStream::connect_timeout(&address, timeout).is_ok())
+}
+
+fn exits_ok(mut command: Command, timeout: Duration) -> bool {
+    let spawned = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+
+    let Ok(mut child) = spawned else {
+        return false;
+    };
+
+    let started = Instant::now();
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return status.success(),
+            Ok(None) if started.elapsed() < timeout => {
+                thread::sleep(Duration::from_millis(COMMAND_POLL_MS))
+            }
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_check() {
+        assert_eq!(
+            "http://localhost:3000/health".parse::<Check>().unwrap(),
+            Check::Http(String::from("http://localhost:3000/health"))
+        );
+        assert_eq!(
+            "tcp:3000".parse::<Check>().unwrap(),
+            Check::Tcp(String::from("3000"))
+        );
+        assert_eq!(
+            "cmd:test -f ready".parse::<Check>().unwrap(),
+            Check::Command(String::from("test -f ready"))
+        );
+        assert_eq!("off".parse::<Check>().unwrap(), Check::Off);
+        assert!("tcp:web".parse::<Check>().is_err());
+        assert!("ping:host".parse::<Check>().is_err());
+    }
+
+    #[test]
+    fn test_tcp_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let probe = Probe {
+            check: Check::Tcp(port.to_string()),
+            timeout: 1,
+            ..Probe::default()
+        };
+
+        assert!(probe.run(Path::new("/"), "/bin/sh", &[]));
+        drop(listener);
+        assert!(!probe.run(Path::new("/"), "/bin/sh", &[]));
+    }
+
+    #[test]
+    fn test_command_probe() {
+        let args = [String::from("-c")];
+        let probe = |command: &str| Probe {
+            check: Check::Command(command.to_string()),
+            timeout: 1,
+            ..Probe::default()
+        };
+
+        assert!(probe("exit 0").run(Path::new("/"), "/bin/sh", &args));
+        assert!(!probe("exit 3").run(Path::new("/"), "/bin/sh", &args));
+        assert!(!probe("sleep 5").run(Path::new("/"), "/bin/sh", &args));
+    }
+
+    #[test]
+    fn test_health_new() {
+        let tcp = Probe {
+            check: Check::Tcp(String::from("80")),
+            ..Probe::default()
+        };
+
+        assert!(Health::new(None, Some(Probe::default())).is_none());
+
+        let health = Health::new(None, Some(tcp)).unwrap();
+        assert!(health.liveness.is_none());
+        assert!(!health.is_ready());
+    }
+}
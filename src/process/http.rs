@@ -1,4 +1,4 @@
-use crate::process::Remote;
+use crate::process::{Options, Remote};
 use macros_rs::{fmtstr, string};
 use reqwest::Client;
 use reqwest::header::{HeaderMap, HeaderValue};
@@ -21,6 +21,9 @@ struct CreateBody<'c> {
     pub script: &'c String,
     pub path: PathBuf,
     pub watch: &'c Option<String>,
+    pub instances: usize,
+    #[serde(flatten)]
+    pub options: &'c Options,
 }
 
 pub mod sync {
@@ -97,6 +100,8 @@ pub fn create(
     script: &String,
     path: PathBuf,
     watch: &Option<String>,
+    instances: usize,
+    options: &Options,
 ) -> Result<sync::Response, anyhow::Error> {
     let (client, headers) = sync::client(token);
     let content = CreateBody {
@@ -104,6 +109,8 @@ pub fn create(
         script,
         path,
         watch,
+        instances,
+        options,
     };
 
     Ok(client
@@ -129,6 +136,22 @@ pub fn restart(
         .send()?)
 }
 
+pub fn reload(
+    Remote { address, token, .. }: &Remote,
+    id: usize,
+) -> Result<sync::Response, anyhow::Error> {
+    let (client, headers) = sync::client(token);
+    let content = ActionBody {
+        method: string!("reload"),
+    };
+
+    Ok(client
+        .post(fmtstr!("{address}/process/{id}/action"))
+        .json(&content)
+        .headers(headers)
+        .send()?)
+}
+
 pub fn rename(
     Remote { address, token, .. }: &Remote,
     id: usize,
@@ -142,6 +165,20 @@ pub fn rename(
         .send()?)
 }
 
+pub fn set_options(
+    Remote { address, token, .. }: &Remote,
+    id: usize,
+    options: &Options,
+) -> Result<sync::Response, anyhow::Error> {
+    let (client, headers) = sync::client(token);
+    Ok(client
+        .post(fmtstr!("{address}/process/{id}/options"))
+        .json(options)
+        .headers(headers)
+        .send()?
+        .error_for_status()?)
+}
+
 // merge into one function
 pub fn stop(
     Remote { address, token, .. }: &Remote,
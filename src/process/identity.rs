@@ -0,0 +1,103 @@
+use nix::{sys::signal::kill, unistd::Pid};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+/// What a pid pointed at when the process was spawned. The start time
+/// survives exec, so it still identifies the process once the shell exec'd
+/// the command; the executable is only compared where no start time is known.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Identity {
+    /// Start time in milliseconds, counted from boot on linux
+    pub started: Option<u64>,
+    /// Boot the start time was taken in, a reboot restarts the count
+    pub boot: Option<String>,
+    /// Executable the process ran
+    pub exe: Option<PathBuf>,
+}
+
+impl Identity {
+    /// Identity of a running pid, none when it does not exist
+    pub fn of(pid: i64) -> Option<Identity> {
+        if pid <= 0 || kill(Pid::from_raw(pid as i32), None).is_err() {
+            return None;
+        }
+
+        let identity = Identity {
+            started: started(pid),
+            boot: boot(),
+            exe: fs::read_link(format!("/proc/{pid}/exe")).ok(),
+        };
+
+        (identity.started.is_some() || identity.exe.is_some()).then_some(identity)
+    }
+
+    /// Whether the pid still belongs to the process this identity was taken from
+    pub fn matches(&self, pid: i64) -> bool {
+        let Some(current) = Identity::of(pid) else {
+            return false;
+        };
+
+        if let (Some(boot), Some(current)) = (&self.boot, &current.boot)
+            && boot != current
+        {
+            return false;
+        }
+
+        match (self.started, current.started) {
+            (Some(started), Some(current)) => started == current,
+            _ => self.exe.is_some() && self.exe == current.exe,
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn started(pid: i64) -> Option<u64> {
+    let started = super::unix::get_process_start_time(pid as u32).ok()?;
+    Some(
+        started
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .as_millis() as u64,
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn started(_pid: i64) -> Option<u64> {
+    None
+}
+
+fn boot() -> Option<String> {
+    let boot = fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+    Some(boot.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_identity_matches() {
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        let pid = child.id() as i64;
+        let identity = Identity::of(pid).unwrap();
+
+        assert!(identity.matches(pid));
+
+        let reused = Identity {
+            started: identity.started.map(|started| started + 1000),
+            ..identity.clone()
+        };
+        assert!(!reused.matches(pid));
+
+        let rebooted = Identity {
+            boot: Some("another boot".to_string()),
+            ..identity.clone()
+        };
+        assert!(!rebooted.matches(pid) || identity.boot.is_none());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(!identity.matches(pid));
+    }
+}
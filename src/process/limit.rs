@@ -0,0 +1,233 @@
+use crate::{helpers, process::unix};
+use core::fmt;
+use nix::sys::signal::Signal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// Memory ceiling a process gets restarted at
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Memory {
+    /// Resident memory limit, "off" or "0" removes it
+    #[schema(example = "512M")]
+    pub max: String,
+    /// Count the resident memory of child processes as well
+    pub children: bool,
+    /// Consecutive daemon ticks the limit has to be exceeded for
+    #[schema(example = 3)]
+    pub ticks: u32,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory {
+            max: String::new(),
+            children: false,
+            ticks: 3,
+        }
+    }
+}
+
+impl Memory {
+    /// Limit in bytes, none when the limit is disabled
+    pub fn bytes(&self) -> Option<u64> {
+        helpers::parse_size(&self.max).filter(|bytes| *bytes > 0)
+    }
+
+    /// Resident memory of the process, together with its children when enabled,
+    /// the process itself is only counted once when it is one of the children
+    pub fn usage(&self, pid: i64, children: &[i64]) -> u64 {
+        let rss = |pid: i64| unix::get_memory_info(pid as u32).map_or(0, |info| info.rss());
+
+        match self.children {
+            true => {
+                rss(pid)
+                    + (children.iter())
+                        .filter(|child| **child != pid)
+                        .map(|child| rss(*child))
+                        .sum::<u64>()
+            }
+            false => rss(pid),
+        }
+    }
+
+    pub fn is_exceeded(&self, usage: u64) -> bool {
+        self.bytes().is_some_and(|bytes| usage > bytes)
+    }
+}
+
+impl fmt::Display for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} for {} ticks", self.max, self.ticks)?;
+        match self.children {
+            true => write!(f, " (with children)"),
+            false => Ok(()),
+        }
+    }
+}
+
+/// What happens once a process stays over its cpu limit
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Only write a warning to the daemon log
+    #[default]
+    Log,
+    /// Post the event as json to an url
+    Webhook(String),
+    /// Send a signal like SIGUSR1 to the process
+    Signal(String),
+    /// Restart the process
+    Restart,
+}
+
+impl Action {
+    /// Name used for the action in logs and metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Log => "log",
+            Action::Webhook(_) => "webhook",
+            Action::Signal(_) => "signal",
+            Action::Restart => "restart",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("webhook", url)) => Ok(Action::Webhook(url.to_string())),
+            Some(("signal", signal)) => Signal::from_str(&signal.to_uppercase())
+                .map(|signal| Action::Signal(signal.as_str().to_string()))
+                .map_err(|_| format!("unknown signal '{signal}'")),
+            None if s == "log" => Ok(Action::Log),
+            None if s == "restart" => Ok(Action::Restart),
+            _ => Err(format!(
+                "unknown action '{s}', expected log, restart, signal:<SIGNAL> or webhook:<URL>"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Webhook(url) => write!(f, "webhook:{url}"),
+            Action::Signal(signal) => write!(f, "signal:{signal}"),
+            action => write!(f, "{}", action.name()),
+        }
+    }
+}
+
+/// Cpu ceiling a process has to stay under
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Cpu {
+    /// Cpu percentage, 0 removes the limit
+    #[schema(example = 90.0)]
+    pub max: f64,
+    /// Seconds the usage has to stay over the limit
+    #[schema(example = 30)]
+    pub duration: u64,
+    pub action: Action,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu {
+            max: 0.0,
+            duration: 30,
+            action: Action::Log,
+        }
+    }
+}
+
+impl Cpu {
+    pub fn is_enabled(&self) -> bool {
+        self.max > 0.0
+    }
+
+    pub fn is_exceeded(&self, usage: f64) -> bool {
+        self.is_enabled() && usage > self.max
+    }
+}
+
+impl fmt::Display for Cpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}% for {}s, then {}",
+            self.max, self.duration, self.action
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_limit() {
+        let memory = Memory {
+            max: String::from("1M"),
+            ..Memory::default()
+        };
+
+        assert_eq!(memory.bytes(), Some(1024 * 1024));
+        assert!(memory.is_exceeded(2 * 1024 * 1024));
+        assert!(!memory.is_exceeded(1024));
+        assert_eq!(memory.to_string(), "1M for 3 ticks");
+
+        for max in ["off", "0", ""] {
+            let disabled = Memory {
+                max: String::from(max),
+                ..Memory::default()
+            };
+            assert_eq!(disabled.bytes(), None);
+            assert!(!disabled.is_exceeded(u64::MAX));
+        }
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let pid = std::process::id() as i64;
+        let memory = Memory {
+            max: String::from("1G"),
+            children: true,
+            ..Memory::default()
+        };
+
+        assert!(memory.usage(pid, &[]) > 0);
+        assert_eq!(memory.usage(999999999, &[]), 0);
+    }
+
+    #[test]
+    fn test_cpu_action() {
+        assert_eq!("log".parse(), Ok(Action::Log));
+        assert_eq!("restart".parse(), Ok(Action::Restart));
+        assert_eq!(
+            "signal:sigusr1".parse(),
+            Ok(Action::Signal(String::from("SIGUSR1")))
+        );
+        assert_eq!(
+            "webhook:http://localhost/hook".parse(),
+            Ok(Action::Webhook(String::from("http://localhost/hook")))
+        );
+        assert!("signal:SIGNOPE".parse::<Action>().is_err());
+        assert!("explode".parse::<Action>().is_err());
+
+        let cpu = Cpu {
+            max: 90.0,
+            action: Action::Signal(String::from("SIGUSR2")),
+            ..Cpu::default()
+        };
+
+        assert!(cpu.is_exceeded(95.0));
+        assert!(!cpu.is_exceeded(50.0));
+        assert!(!Cpu::default().is_exceeded(100.0));
+        assert_eq!(cpu.to_string(), "90% for 30s, then signal:SIGUSR2");
+    }
+}
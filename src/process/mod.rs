@@ -1,27 +1,38 @@
+pub mod cgroup;
+pub mod collect;
+pub mod control;
+pub mod cron;
+pub mod depends;
 pub mod dump;
+pub mod environ;
+pub mod exit;
 pub mod hash;
+pub mod health;
 pub mod http;
 pub mod id;
+pub mod identity;
+pub mod limit;
+pub mod policy;
+pub mod ready;
+pub mod rotate;
+pub mod schema;
+pub mod stop;
 pub mod unix;
+pub mod watch;
 
-use crate::{config, config::structs::Server, file, helpers};
+use crate::{config, config::structs::Server, helpers};
 
 use std::{
     collections::HashSet,
     env,
-    fs::File,
-    path::PathBuf,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    thread,
     time::Duration,
 };
 
-use nix::{
-    sys::signal::{Signal, kill},
-    unistd::Pid,
-};
-
-use chrono::serde::ts_milliseconds;
+use chrono::serde::{ts_milliseconds, ts_milliseconds_option};
 use chrono::{DateTime, Utc};
 use global_placeholders::global;
 use macros_rs::{crashln, string, ternary, then};
@@ -36,6 +47,8 @@ pub struct ItemSingle {
     pub watch: Watch,
     pub log: Log,
     pub raw: Raw,
+    #[serde(default)]
+    pub exits: Vec<exit::Exit>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -49,6 +62,17 @@ pub struct Info {
     pub uptime: String,
     pub command: String,
     pub children: Vec<i64>,
+    pub instance: Option<usize>,
+    #[serde(default)]
+    #[schema(example = "0 4 * * *")]
+    pub cron: Option<String>,
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "2000-01-01T04:00:00.000Z")]
+    pub next_run: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub health: Option<health::Health>,
+    #[serde(default)]
+    pub depends: Option<depends::Depends>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -63,6 +87,10 @@ pub struct Stats {
 pub struct MemoryInfo {
     pub rss: u64,
     pub vms: u64,
+    /// Memory charged to the cgroup, page cache and descendants included,
+    /// none outside a cgroup
+    #[serde(default)]
+    pub cgroup: Option<u64>,
 }
 
 impl From<unix::NativeMemoryInfo> for MemoryInfo {
@@ -70,6 +98,7 @@ impl From<unix::NativeMemoryInfo> for MemoryInfo {
         MemoryInfo {
             rss: native.rss(),
             vms: native.vms(),
+            cgroup: None,
         }
     }
 }
@@ -107,6 +136,11 @@ pub struct ProcessItem {
     watch_path: String,
     #[schema(value_type = String, example = "2000-01-01T01:00:00.000Z")]
     start_time: DateTime<Utc>,
+    #[schema(example = 0)]
+    instance: Option<usize>,
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "2000-01-01T04:00:00.000Z")]
+    next_run: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone)]
@@ -117,11 +151,29 @@ pub struct ProcessWrapper {
 
 pub type Env = BTreeMap<String, String>;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Process {
     pub id: usize,
     pub pid: i64,
+    /// Process group led by the process, none for processes started before groups
+    pub pgid: Option<i64>,
+    /// Start time and executable of the pid, none for processes started before identities
+    pub identity: Option<identity::Identity>,
+    /// Set once the pid turned out to belong to another process
+    #[serde(default)]
+    pub stale: bool,
+    /// Cgroup the process runs in, none when cgroups are unavailable
+    pub cgroup: Option<PathBuf>,
     pub env: Env,
+    /// Whether `env` follows the shell restarting the process, is frozen or minimal
+    #[serde(default)]
+    pub env_mode: environ::Mode,
+    /// Env files, env block and profiles declared on top of `env`
+    #[serde(default)]
+    pub declared: environ::Declared,
+    /// Declared variables with their source, as resolved on the last start
+    #[serde(default)]
+    pub resolved: BTreeMap<String, environ::Var>,
     pub name: String,
     pub path: PathBuf,
     pub script: String,
@@ -130,32 +182,180 @@ pub struct Process {
     pub crash: Crash,
     pub watch: Watch,
     pub children: Vec<i64>,
+    pub instance: Option<usize>,
+    pub ready: ready::Ready,
+    pub policy: policy::Policy,
+    pub max_memory: Option<limit::Memory>,
+    pub max_cpu: Option<limit::Cpu>,
+    /// Cron expression the process is restarted, or run as a job, on
+    pub cron: Option<String>,
+    #[serde(with = "ts_milliseconds_option")]
+    pub next_run: Option<DateTime<Utc>>,
+    /// Custom stdout log file, none writes into the log path
+    pub out_file: Option<String>,
+    /// Custom stderr log file, none writes into the log path
+    pub error_file: Option<String>,
+    /// Liveness and readiness probes
+    pub health: Option<health::Health>,
+    /// Processes started before this one
+    pub depends: Option<depends::Depends>,
+    /// Stop signal and kill timeout, none for SIGTERM and the default timeout
+    pub stop: Option<stop::Stop>,
+    /// Cpu, memory and pids limits of the cgroup
+    pub limits: Option<cgroup::Limits>,
+    pub exits: Vec<exit::Exit>,
     #[serde(with = "ts_milliseconds")]
     pub started: DateTime<Utc>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Crash {
     pub crashed: bool,
     pub value: u64,
+    /// When the daemon noticed the process died, cleared once it is restarted
+    #[serde(with = "ts_milliseconds_option")]
+    pub since: Option<DateTime<Utc>>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+/// Optional settings applied to a process when it is created or restarted
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct Options {
+    #[serde(default)]
+    pub policy: Option<policy::Policy>,
+    #[serde(default)]
+    pub max_memory: Option<limit::Memory>,
+    #[serde(default)]
+    pub max_cpu: Option<limit::Cpu>,
+    /// Cron expression, "off" removes it
+    #[serde(default)]
+    #[schema(example = "0 4 * * *")]
+    pub cron: Option<String>,
+    /// Command to run, replacing the current one
+    #[serde(default)]
+    #[schema(example = "node index.js")]
+    pub script: Option<String>,
+    /// Working directory
+    #[serde(default)]
+    #[schema(value_type = Option<String>, example = "/projects/app")]
+    pub cwd: Option<PathBuf>,
+    /// Stdout log file, an empty path resets it to the log path
+    #[serde(default)]
+    #[schema(example = "/var/log/app.out.log")]
+    pub out_file: Option<String>,
+    /// Stderr log file, an empty path resets it to the log path
+    #[serde(default)]
+    #[schema(example = "/var/log/app.error.log")]
+    pub error_file: Option<String>,
+    /// Probe the process is restarted after failing, check "off" removes it
+    #[serde(default)]
+    pub liveness: Option<health::Probe>,
+    /// Probe the process is shown unready while failing, check "off" removes it
+    #[serde(default)]
+    pub readiness: Option<health::Probe>,
+    /// Names of processes started before this one, an empty list removes them
+    #[serde(default)]
+    #[schema(example = json!(["db"]))]
+    pub depends_on: Option<Vec<String>>,
+    /// Wait until the dependencies are ready before starting
+    #[serde(default)]
+    pub wait_ready: Option<bool>,
+    /// Signal sent to stop the process, like SIGINT
+    #[serde(default)]
+    #[schema(example = "SIGTERM")]
+    pub stop_signal: Option<String>,
+    /// Milliseconds to wait after the stop signal before SIGKILL
+    #[serde(default)]
+    #[schema(example = 5000)]
+    pub kill_timeout: Option<u64>,
+    /// Limits of the process cgroup, a zero value removes a limit
+    #[serde(default)]
+    pub cgroup: Option<cgroup::Limits>,
+    /// Extra watch paths, ignore patterns, debounce and delay
+    #[serde(default)]
+    pub watch: Option<watch::Settings>,
+    /// Env files, env block and profiles declared for the process
+    #[serde(default)]
+    pub env: Option<environ::Settings>,
+    /// Where the environment comes from besides the declared variables
+    #[serde(default)]
+    pub env_mode: Option<environ::Mode>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
 pub struct Watch {
     pub enabled: bool,
     #[schema(example = "/path")]
     pub path: String,
     pub hash: String,
+    /// Paths watched besides `path`
+    #[serde(default)]
+    #[schema(example = json!(["config"]))]
+    pub paths: Vec<String>,
+    /// Glob patterns changes are ignored in
+    #[serde(default)]
+    #[schema(example = json!(["node_modules", ".git", "*.log"]))]
+    pub ignore: Vec<String>,
+    /// Milliseconds without changes before restarting
+    #[serde(default = "watch::default_debounce")]
+    pub debounce: u64,
+    /// Milliseconds to wait after the debounce before restarting
+    #[serde(default)]
+    pub delay: u64,
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Watch {
+            enabled: false,
+            path: string!(""),
+            hash: string!(""),
+            paths: vec![],
+            ignore: vec![],
+            debounce: watch::DEFAULT_DEBOUNCE_MS,
+            delay: 0,
+        }
+    }
+}
+
+impl Watch {
+    /// Every watched path, relative to the working directory of the process
+    pub fn roots(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.path).chain(&self.paths)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Runner {
+    /// Schema of the dump, see `schema::migrate`
+    pub version: u32,
     pub id: id::Id,
     #[serde(skip)]
     pub remote: Option<Remote>,
+    #[serde(skip)]
+    pub source: Source,
+    /// Environment of the cli a change is applied for, none to use our own
+    #[serde(skip)]
+    pub caller: Option<Env>,
+    /// Dump lock held from the first local change until it is saved
+    #[serde(skip)]
+    pub guard: Option<Arc<dump::Lock>>,
     pub list: BTreeMap<usize, Process>,
 }
 
+/// Where the list of a runner was read from, and where changes to it go
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Source {
+    /// Only kept in memory, saving writes the dump as is
+    #[default]
+    Memory,
+    /// Read from the dump, changes are made under the dump lock
+    Dump,
+    /// Changes are applied by the daemon over its socket
+    Daemon,
+    /// Copy the daemon changes off its loop, saving is left to the daemon
+    Copy,
+}
+
 #[derive(Clone, Debug)]
 pub struct Remote {
     address: String,
@@ -193,10 +393,20 @@ impl Status {
     }
 }
 
+/// A freshly spawned process
+pub struct Spawned {
+    /// Shell running the command, a direct child of the spawner and leader of its own process group
+    pub pid: i64,
+    /// Cgroup the shell joined before running the command
+    pub cgroup: Option<PathBuf>,
+}
+
 /// Process metadata
 pub struct ProcessMetadata {
     /// Process name
     pub name: String,
+    /// Working directory
+    pub path: PathBuf,
     /// Shell command
     pub shell: String,
     /// Command
@@ -207,8 +417,21 @@ pub struct ProcessMetadata {
     pub args: Vec<String>,
     /// Environment variables
     pub env: Vec<String>,
+    /// Timestamping collector, none to write output straight into the logs
+    pub collector: Option<collect::Collector>,
+    /// Stdout log file, none to use the log path
+    pub out_file: Option<String>,
+    /// Stderr log file, none to use the log path
+    pub error_file: Option<String>,
+    /// Cgroup to run in, none to stay in the cgroup of the spawner
+    pub cgroup: Option<PathBuf>,
+    /// Start from an empty environment instead of the one of the spawner
+    pub clear_env: bool,
 }
 
+/// How long to wait for a dying process to be collected
+pub const REAP_TIMEOUT_MS: u64 = 500;
+
 macro_rules! lock {
     ($runner:expr) => {{
         match $runner.lock() {
@@ -218,29 +441,103 @@ macro_rules! lock {
     }};
 }
 
-fn kill_children(children: Vec<i64>) {
-    for pid in children {
-        match kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
-            Ok(_) => {}
-            Err(nix::errno::Errno::ESRCH) => {
-                // Process already terminated
-            }
-            Err(err) => {
-                log::error!("Failed to stop pid {}: {err:?}", pid);
-            }
-        }
-    }
-}
-
 impl Default for Runner {
     fn default() -> Self {
         Self::new()
     }
 }
 
+impl Options {
+    /// Stop settings from the options over a base, none when they match the
+    /// defaults, the signal is normalized and both are checked
+    pub fn stop(&self, base: Option<&stop::Stop>) -> Result<Option<stop::Stop>, String> {
+        let base = base.cloned().unwrap_or_default();
+        let stop = stop::Stop {
+            signal: match &self.stop_signal {
+                Some(signal) => stop::parse_signal(signal)?,
+                None => base.signal,
+            },
+            kill_timeout: match self.kill_timeout {
+                Some(ms) => stop::check_kill_timeout(ms)?,
+                None => base.kill_timeout,
+            },
+        };
+
+        Ok((!stop.is_default()).then_some(stop))
+    }
+}
+
 impl Runner {
     pub fn new() -> Self {
-        dump::read()
+        match control::send(control::Action::Dump, Duration::ZERO) {
+            Some(Ok(runner)) => Runner {
+                source: Source::Daemon,
+                ..runner
+            },
+            _ => Runner {
+                source: Source::Dump,
+                ..dump::read()
+            },
+        }
+    }
+
+    /// Hand a change to the daemon, false when it has to be applied locally
+    fn control(&mut self, action: control::Action) -> bool {
+        then!(self.source != Source::Daemon, return false);
+
+        // the answer comes once the processes the action stops or waits on are done
+        let wait = match &action {
+            control::Action::Load { .. } => (self.list.values())
+                .filter(|item| item.running)
+                .map(Process::limit)
+                .sum(),
+            action if action.waits() => (action.id())
+                .and_then(|id| self.list.get(&id))
+                .map_or(Duration::ZERO, Process::limit),
+            _ => Duration::ZERO,
+        };
+
+        match control::send(action, wait) {
+            Some(Ok(runner)) => {
+                self.id = runner.id;
+                self.list = runner.list;
+                true
+            }
+            Some(Err(err)) => crashln!("{} {err}", *helpers::FAIL),
+            None => {
+                self.source = Source::Dump;
+                false
+            }
+        }
+    }
+
+    /// Take the dump lock before a local change and reload the list, so
+    /// nothing written since this runner was read gets overwritten
+    fn lock(&mut self) {
+        then!(self.source != Source::Dump || self.guard.is_some(), return);
+
+        let guard = dump::lock();
+        let current = dump::read();
+
+        self.id = current.id;
+        self.list = current.list;
+        self.guard = Some(guard);
+    }
+
+    /// Environment of the caller as key value pairs
+    fn caller_vars(&self) -> Env {
+        self.caller.clone().unwrap_or_else(|| env::vars().collect())
+    }
+
+    /// Environment of the caller formatted for `process_run`
+    fn caller_env(&self) -> Vec<String> {
+        match &self.caller {
+            Some(env) => env
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect(),
+            None => unix::env(),
+        }
     }
 
     pub fn refresh(&self) -> Self {
@@ -256,7 +553,7 @@ impl Runner {
             }
         };
 
-        if let Ok(dump) = dump::from(&address, token.as_deref()) {
+        if let Ok(dump) = dump::from(&address, token.as_deref(), false) {
             then!(
                 verbose,
                 println!(
@@ -283,63 +580,151 @@ impl Runner {
         command: &String,
         path: PathBuf,
         watch: &Option<String>,
+        instances: usize,
+        options: &Options,
     ) -> &mut Self {
         if let Some(remote) = &self.remote {
-            if let Err(err) = http::create(remote, name, command, path, watch) {
+            if let Err(err) = http::create(remote, name, command, path, watch, instances, options) {
                 crashln!(
                     "{} Failed to start create {name}\nError: {:#?}",
                     *helpers::FAIL,
                     err
                 );
             };
-        } else {
+
+            return self;
+        }
+
+        let action = control::Action::Start {
+            name: name.clone(),
+            command: command.clone(),
+            path: path.clone(),
+            watch: watch.clone(),
+            instances,
+            options: options.clone(),
+        };
+        then!(self.control(action), return self);
+        self.lock();
+
+        if let Err(err) = working_dir(&path) {
+            crashln!(
+                "{} Failed to set working directory {:?}\nError: {:#?}",
+                *helpers::FAIL,
+                path,
+                err
+            );
+        }
+
+        let mut watch = match watch {
+            Some(watch) => Watch {
+                enabled: true,
+                path: string!(watch),
+                hash: hash::create(path.join(watch)),
+                ..Watch::default()
+            },
+            None => Watch::default(),
+        };
+
+        if let Some(settings) = &options.watch {
+            settings.apply(&mut watch);
+        }
+
+        let mut declared = environ::Declared::default();
+        if let Some(settings) = &options.env {
+            settings.apply(&mut declared);
+        }
+
+        let env_mode = options.env_mode.unwrap_or_default();
+        let stop = options.stop(None).unwrap_or_else(|err| {
+            crashln!("{} Failed to start {name}\nError: {err}", *helpers::FAIL)
+        });
+
+        for index in 0..instances.max(1) {
             let id = self.id.next();
             let config = config::read().runner;
+            let instance = (instances > 1).then_some(index);
+
             let crash = Crash {
                 crashed: false,
                 value: 0,
+                since: None,
             };
 
-            let watch = match watch {
-                Some(watch) => Watch {
-                    enabled: true,
-                    path: string!(watch),
-                    hash: hash::create(file::cwd().join(watch)),
-                },
-                None => Watch {
-                    enabled: false,
-                    path: string!(""),
-                    hash: string!(""),
-                },
+            let mut process = Process {
+                id,
+                pid: 0,
+                pgid: None,
+                identity: None,
+                stale: false,
+                cgroup: None,
+                crash,
+                instance,
+                restarts: 0,
+                running: true,
+                children: vec![],
+                ready: ready::Ready::default(),
+                policy: options.policy.clone().unwrap_or_default(),
+                max_memory: options
+                    .max_memory
+                    .clone()
+                    .filter(|memory| memory.bytes().is_some()),
+                max_cpu: options.max_cpu.clone().filter(|cpu| cpu.is_enabled()),
+                cron: options
+                    .cron
+                    .clone()
+                    .filter(|expression| expression != "off"),
+                next_run: options
+                    .cron
+                    .as_ref()
+                    .and_then(|expression| cron::next(expression, Utc::now())),
+                out_file: options.out_file.clone().filter(|file| !file.is_empty()),
+                error_file: options.error_file.clone().filter(|file| !file.is_empty()),
+                health: health::Health::new(options.liveness.clone(), options.readiness.clone()),
+                depends: depends::Depends::new(
+                    options.depends_on.clone().unwrap_or_default(),
+                    options.wait_ready.unwrap_or_default(),
+                ),
+                stop: stop.clone(),
+                limits: (options.cgroup.as_ref()).and_then(|limits| limits.merge(None)),
+                exits: vec![],
+                path: path.clone(),
+                name: name.clone(),
+                watch: watch.clone(),
+                started: Utc::now(),
+                script: command.clone(),
+                env: env_mode.base(self.caller_vars()),
+                env_mode,
+                declared: declared.clone(),
+                resolved: BTreeMap::new(),
             };
 
-            let pid = process_run(ProcessMetadata {
+            process.resolve_env();
+            let temp_env = process.run_env(vec![]);
+            process.env.extend(process.instance_vars());
+
+            let spawned = process_run(ProcessMetadata {
                 args: config.args,
-                name: name.clone(),
+                name: process.log_name(),
+                path: path.clone(),
                 shell: config.shell,
                 command: command.clone(),
                 log_path: config.log_path,
-                env: unix::env(),
+                env: temp_env,
+                collector: config.logs.collector(process.id, &process.name),
+                out_file: process.out_file.clone(),
+                error_file: process.error_file.clone(),
+                cgroup: cgroup::prepare(process.id, process.limits.as_ref()),
+                clear_env: !process.env_mode.inherits(),
             })
             .unwrap_or_else(|err| crashln!("Failed to run process: {err}"));
 
-            self.list.insert(
-                id,
-                Process {
-                    id,
-                    pid,
-                    path,
-                    watch,
-                    crash,
-                    restarts: 0,
-                    running: true,
-                    children: vec![],
-                    name: name.clone(),
-                    started: Utc::now(),
-                    script: command.clone(),
-                    env: env::vars().collect(),
-                },
-            );
+            process.pid = spawned.pid;
+            process.pgid = Some(spawned.pid);
+            process.identity = identity::Identity::of(spawned.pid);
+            process.stale = false;
+            process.cgroup = spawned.cgroup;
+
+            self.list.insert(id, process);
         }
 
         self
@@ -354,57 +739,173 @@ impl Runner {
                     err
                 );
             };
+
+            return self;
+        }
+
+        let action = control::Action::Restart { id, dead };
+        then!(self.control(action), return self);
+        self.lock();
+        let (caller, caller_env) = (self.caller_vars(), self.caller_env());
+        let process = self.process(id);
+        let config = config::read().runner;
+        let Process { path, script, .. } = process.clone();
+
+        process
+            .terminate()
+            .unwrap_or_else(|err| crashln!("Failed to stop process: {err}"));
+
+        if let Err(err) = working_dir(&path) {
+            process.running = false;
+            process.children = vec![];
+            process.crash.crashed = true;
+            println!(
+                "{} Failed to set working directory {:?}\nError: {:#?}",
+                *helpers::FAIL,
+                path,
+                err
+            );
         } else {
-            let process = self.process(id);
-            let config = config::read().runner;
-            let Process {
-                path, script, name, ..
-            } = process.clone();
-
-            kill_children(process.children.clone());
-            process_stop(process.pid)
-                .unwrap_or_else(|err| crashln!("Failed to stop process: {err}"));
-
-            if let Err(err) = std::env::set_current_dir(&path) {
-                process.running = false;
-                process.children = vec![];
-                process.crash.crashed = true;
-                println!(
-                    "{} Failed to set working directory {:?}\nError: {:#?}",
+            process.resolve_env();
+
+            let spawned = process_run(ProcessMetadata {
+                args: config.args,
+                name: process.log_name(),
+                path: path.clone(),
+                shell: config.shell,
+                log_path: config.log_path,
+                command: script.to_string(),
+                env: process.run_env(caller_env),
+                collector: config.logs.collector(process.id, &process.name),
+                out_file: process.out_file.clone(),
+                error_file: process.error_file.clone(),
+                cgroup: cgroup::prepare(process.id, process.limits.as_ref()),
+                clear_env: !process.env_mode.inherits(),
+            })
+            .unwrap_or_else(|err| crashln!("Failed to run process: {err}"));
+
+            process.pid = spawned.pid;
+            process.pgid = Some(spawned.pid);
+            process.identity = identity::Identity::of(spawned.pid);
+            process.stale = false;
+            process.cgroup = spawned.cgroup;
+
+            process.running = true;
+            process.children = vec![];
+            process.started = Utc::now();
+            process.crash.crashed = false;
+            process.crash.since = None;
+            then!(process.env_mode.inherits(), process.env.extend(caller));
+            process.reset_readiness();
+            process.env.extend(process.instance_vars());
+
+            then!(dead, process.restarts += 1);
+            then!(dead, process.crash.value += 1);
+            then!(!dead, process.crash.value = 0);
+        }
+
+        self
+    }
+
+    /// Start a replacement, wait until it is ready, then stop the old process
+    pub fn reload(&mut self, id: usize) -> &mut Self {
+        if let Some(remote) = &self.remote {
+            if let Err(err) = http::reload(remote, id) {
+                crashln!(
+                    "{} Failed to reload process {id}\nError: {:#?}",
                     *helpers::FAIL,
-                    path,
                     err
                 );
-            } else {
-                let mut temp_env = process
-                    .env
-                    .iter()
-                    .map(|(key, value)| format!("{}={}", key, value))
-                    .collect::<Vec<String>>();
-                temp_env.extend(unix::env());
-
-                process.pid = process_run(ProcessMetadata {
-                    args: config.args,
-                    name: name.clone(),
-                    shell: config.shell,
-                    log_path: config.log_path,
-                    command: script.to_string(),
-                    env: temp_env,
-                })
-                .unwrap_or_else(|err| crashln!("Failed to run process: {err}"));
-
-                process.running = true;
-                process.children = vec![];
-                process.started = Utc::now();
-                process.crash.crashed = false;
-                process.env.extend(env::vars().collect::<Env>());
-
-                then!(dead, process.restarts += 1);
-                then!(dead, process.crash.value += 1);
-                then!(!dead, process.crash.value = 0);
-            }
+            };
+
+            return self;
+        }
+
+        then!(self.control(control::Action::Reload { id }), return self);
+        self.lock();
+
+        let mut process = self.process(id).clone();
+        let config = config::read().runner;
+
+        if !process.running || !process.is_alive() {
+            return self.restart(id, false);
+        }
+
+        if let Err(err) = working_dir(&process.path) {
+            println!(
+                "{} Failed to set working directory {:?}\nError: {:#?}",
+                *helpers::FAIL,
+                process.path,
+                err
+            );
+            return self;
+        }
+
+        let log_out = process.logs().out;
+        let offset = fs::metadata(&log_out).map(|m| m.len()).unwrap_or(0);
+        process.resolve_env();
+
+        let spawned = process_run(ProcessMetadata {
+            args: config.args,
+            name: process.log_name(),
+            path: process.path.clone(),
+            shell: config.shell,
+            log_path: config.log_path,
+            command: process.script.clone(),
+            env: process.run_env(self.caller_env()),
+            collector: config.logs.collector(process.id, &process.name),
+            out_file: process.out_file.clone(),
+            error_file: process.error_file.clone(),
+            cgroup: cgroup::prepare(process.id, process.limits.as_ref()),
+            clear_env: !process.env_mode.inherits(),
+        })
+        .unwrap_or_else(|err| crashln!("Failed to run process: {err}"));
+
+        // other commands go on while the replacement gets ready, the list is read again to commit
+        self.guard = None;
+        let ready = process.ready.wait(spawned.pid, &log_out, offset);
+        let stop = process.stop.clone().unwrap_or_default();
+
+        if !ready {
+            let _ = stop::terminate(spawned.pid, Some(spawned.pid), &[], &stop, || false);
+            println!(
+                "{} Replacement for ({id}) did not become ready, keeping pid {}",
+                *helpers::FAIL,
+                process.pid
+            );
+            return self;
+        }
+
+        self.lock();
+
+        // a process removed or restarted meanwhile keeps what the other command left
+        if self.info(id).is_none_or(|item| item.pid != process.pid) {
+            let _ = stop::terminate(spawned.pid, Some(spawned.pid), &[], &stop, || false);
+            println!(
+                "{} Process ({id}) changed during the reload, stopped the replacement",
+                *helpers::FAIL
+            );
+            return self;
         }
 
+        let item = self.process(id);
+        item.pid = spawned.pid;
+        item.pgid = Some(spawned.pid);
+        item.identity = identity::Identity::of(spawned.pid);
+        item.stale = false;
+        item.cgroup = spawned.cgroup;
+        item.resolved = process.resolved.clone();
+        item.running = true;
+        item.children = vec![];
+        item.started = Utc::now();
+        item.crash.crashed = false;
+        item.crash.value = 0;
+        item.crash.since = None;
+        item.reset_readiness();
+        self.save();
+
+        let _ = process.terminate();
+
         self
     }
 
@@ -417,24 +918,52 @@ impl Runner {
                     err
                 );
             };
-        } else {
-            self.stop(id);
-            self.list.remove(&id);
-            self.save();
+
+            return;
+        }
+
+        then!(self.control(control::Action::Remove { id }), return);
+        self.lock();
+        self.stop(id);
+
+        if let Some(path) = self.list.remove(&id).and_then(|process| process.cgroup) {
+            cgroup::remove(&path);
+        }
+
+        self.save();
+    }
+
+    /// Replace the whole list with a backup, nothing started from the
+    /// current one may keep running
+    pub fn load(&mut self, id: id::Id, list: BTreeMap<usize, Process>) -> &mut Self {
+        let action = control::Action::Load {
+            id: id.clone(),
+            list: list.values().cloned().collect(),
+        };
+        then!(self.control(action), return self);
+        self.lock();
+
+        for (id, item) in self.items() {
+            then!(item.running, {
+                self.stop(id);
+            });
         }
+
+        self.id = id;
+        self.list = list;
+        self
     }
 
     pub fn set_id(&mut self, id: id::Id) {
+        let action = control::Action::SetId { id: id.clone() };
+        then!(self.control(action), return);
+        self.lock();
+
         self.id = id;
         self.id.next();
         self.save();
     }
 
-    pub fn set_status(&mut self, id: usize, status: Status) {
-        self.process(id).running = status.to_bool();
-        self.save();
-    }
-
     pub fn items(&self) -> BTreeMap<usize, Process> {
         self.list.clone()
     }
@@ -443,8 +972,12 @@ impl Runner {
         &mut self.list
     }
 
-    pub fn save(&self) {
-        then!(self.remote.is_none(), dump::write(self))
+    pub fn save(&mut self) {
+        then!(
+            self.remote.is_none() && matches!(self.source, Source::Memory | Source::Dump),
+            dump::write(self)
+        );
+        self.guard = None;
     }
 
     pub fn count(&mut self) -> usize {
@@ -463,6 +996,22 @@ impl Runner {
         self.list.get(&id)
     }
 
+    /// Replace the values of secret variables of every process
+    pub fn redact(&mut self, patterns: &[String]) -> &mut Self {
+        self.list
+            .values_mut()
+            .for_each(|process| process.redact(patterns));
+        self
+    }
+
+    /// Fetch the values a remote redacted, its token needs the reveal scope
+    pub fn reveal(&mut self) -> Result<&mut Self, anyhow::Error> {
+        if let Some(remote) = &self.remote {
+            self.list = dump::from(&remote.address, remote.token.as_deref(), true)?.list;
+        }
+        Ok(self)
+    }
+
     pub fn try_info(&self, id: usize) -> &Process {
         self.list
             .get(&id)
@@ -503,6 +1052,13 @@ impl Runner {
     }
 
     pub fn set_env(&mut self, id: usize, env: Env) -> &mut Self {
+        let action = control::Action::SetEnv {
+            id,
+            env: env.clone(),
+        };
+        then!(self.control(action), return self);
+        self.lock();
+
         self.process(id).env.extend(env);
         self
     }
@@ -516,10 +1072,190 @@ impl Runner {
                     err
                 );
             };
-        } else {
-            self.process(id).env = BTreeMap::new();
+
+            return self;
+        }
+
+        then!(self.control(control::Action::ClearEnv { id }), return self);
+        self.lock();
+        let process = self.process(id);
+        process.env = process.env_mode.base(BTreeMap::new());
+
+        self
+    }
+
+    pub fn set_ready(&mut self, id: usize, ready: ready::Ready) -> &mut Self {
+        let action = control::Action::SetReady {
+            id,
+            ready: ready.clone(),
+        };
+        then!(self.control(action), return self);
+        self.lock();
+
+        self.process(id).ready = ready;
+        self
+    }
+
+    pub fn set_options(&mut self, id: usize, options: &Options) -> &mut Self {
+        let action = control::Action::SetOptions {
+            id,
+            options: options.clone(),
+        };
+        if let Some(remote) = &self.remote {
+            if let Err(err) = http::set_options(remote, id, options) {
+                crashln!(
+                    "{} Failed to set options of process {id}\nError: {:#?}",
+                    *helpers::FAIL,
+                    err
+                );
+            };
+            return self;
+        }
+
+        then!(self.control(action), return self);
+        self.lock();
+
+        let process = self.process(id);
+        let watched = (process.path.clone(), process.watch.path.clone());
+
+        if let Some(script) = &options.script {
+            process.script = script.clone();
+        }
+
+        if let Some(cwd) = &options.cwd {
+            process.path = cwd.clone();
+        }
+
+        if let Some(policy) = &options.policy {
+            process.policy = policy.clone();
+        }
+
+        if let Some(memory) = &options.max_memory {
+            process.max_memory = memory.bytes().map(|_| memory.clone());
+        }
+
+        if let Some(cpu) = &options.max_cpu {
+            process.max_cpu = cpu.is_enabled().then(|| cpu.clone());
+        }
+
+        if let Some(expression) = &options.cron {
+            process.cron = (expression != "off").then(|| expression.clone());
+            process.next_run = process
+                .cron
+                .as_ref()
+                .and_then(|expression| cron::next(expression, Utc::now()));
+        }
+
+        if let Some(file) = &options.out_file {
+            process.out_file = (!file.is_empty()).then(|| file.clone());
+        }
+
+        if let Some(file) = &options.error_file {
+            process.error_file = (!file.is_empty()).then(|| file.clone());
+        }
+
+        if options.liveness.is_some() || options.readiness.is_some() {
+            let current = process.health.take().unwrap_or_default();
+            let ready = current.ready;
+
+            process.health = health::Health::new(
+                options.liveness.clone().or(current.liveness),
+                options.readiness.clone().or(current.readiness),
+            )
+            .map(|health| health::Health { ready, ..health });
+        }
+
+        if options.depends_on.is_some() || options.wait_ready.is_some() {
+            let current = process.depends.take().unwrap_or_default();
+
+            process.depends = depends::Depends::new(
+                options.depends_on.clone().unwrap_or(current.on),
+                options.wait_ready.unwrap_or(current.wait),
+            );
+        }
+
+        if options.stop_signal.is_some() || options.kill_timeout.is_some() {
+            process.stop = options.stop(process.stop.as_ref()).unwrap_or_else(|err| {
+                crashln!(
+                    "{} Failed to set options on {id}\nError: {err}",
+                    *helpers::FAIL
+                )
+            });
+        }
+
+        if let Some(limits) = &options.cgroup {
+            process.limits = limits.merge(process.limits.as_ref());
+
+            if let Some(path) = &process.cgroup {
+                cgroup::apply(path, process.limits.as_ref());
+            }
+        }
+
+        if let Some(settings) = &options.watch {
+            settings.apply(&mut process.watch);
+        }
+
+        if (process.path.clone(), process.watch.path.clone()) != watched {
+            process.watch.hash = match process.watch.enabled {
+                true => hash::create(process.path.join(&process.watch.path)),
+                false => string!(""),
+            };
         }
 
+        if let Some(settings) = &options.env {
+            settings.apply(&mut process.declared);
+        }
+
+        // the current environment becomes the snapshot, clean mode drops it
+        if let Some(mode) = options.env_mode
+            && mode != process.env_mode
+        {
+            process.env_mode = mode;
+            process.env = mode.base(process.env.clone());
+        }
+
+        self
+    }
+
+    pub fn record_exit(&mut self, id: usize, exit: exit::Exit) -> &mut Self {
+        self.process(id).record_exit(exit);
+        self
+    }
+
+    pub fn set_crash_since(&mut self, id: usize, since: DateTime<Utc>) -> &mut Self {
+        self.process(id).crash.since = Some(since);
+        self
+    }
+
+    pub fn reset_crashes(&mut self, id: usize) -> &mut Self {
+        let process = self.process(id);
+        process.crash.value = 0;
+        process.crash.since = None;
+        self
+    }
+
+    pub fn set_next_run(&mut self, id: usize, next: Option<DateTime<Utc>>) -> &mut Self {
+        self.process(id).next_run = next;
+        self
+    }
+
+    /// Record the result of the readiness probe
+    pub fn set_readiness(&mut self, id: usize, ready: bool) -> &mut Self {
+        if let Some(health) = &mut self.process(id).health {
+            health.ready = ready;
+        }
+        self
+    }
+
+    /// Mark the process running again, its pid turned out to be alive
+    pub fn set_running(&mut self, id: usize) -> &mut Self {
+        self.process(id).running = true;
+        self
+    }
+
+    /// Mark the process stale, its pid belongs to another process now
+    pub fn set_stale(&mut self, id: usize) -> &mut Self {
+        self.process(id).stale = true;
         self
     }
 
@@ -542,28 +1278,36 @@ impl Runner {
                     err
                 );
             };
-        } else {
-            let process_to_stop = self.process(id);
-            let pid_to_check = process_to_stop.pid;
 
-            kill_children(process_to_stop.children.clone());
-            let _ = process_stop(pid_to_check); // Continue even if stopping fails
+            return self;
+        }
+
+        then!(self.control(control::Action::Stop { id }), return self);
+        self.lock();
+        let process_to_stop = self.process(id).clone();
+        let alive = process_to_stop.running && process_to_stop.is_alive();
 
-            // waiting until Process is terminated
-            for _ in 0..50 {
-                match unix::NativeProcess::new(pid_to_check as u32) {
-                    Ok(_p) => thread::sleep(Duration::from_millis(100)),
-                    Err(_) => break,
-                }
-            }
+        // Continue even if stopping fails
+        let (outcome, exit) =
+            (process_to_stop.terminate()).unwrap_or((stop::Outcome::Exited, None));
 
-            let process = self.process(id);
-            process.running = false;
-            process.crash.crashed = false;
-            process.crash.value = 0;
-            process.children = vec![];
+        if alive {
+            let mut exit = exit
+                .or_else(|| process_to_stop.reap(Duration::from_millis(REAP_TIMEOUT_MS)))
+                .unwrap_or_else(|| exit::Exit::unknown(process_to_stop.started));
+
+            exit.forced = outcome == stop::Outcome::Forced;
+            self.process(id).record_exit(exit);
         }
 
+        let process = self.process(id);
+        process.stale = outcome == stop::Outcome::Stale;
+        process.running = false;
+        process.crash.crashed = false;
+        process.crash.value = 0;
+        process.crash.since = None;
+        process.children = vec![];
+
         self
     }
 
@@ -576,10 +1320,13 @@ impl Runner {
                     err
                 );
             };
-        } else {
-            self.process(id).logs().flush();
+
+            return self;
         }
 
+        then!(self.control(control::Action::Flush { id }), return self);
+        self.process(id).logs().flush();
+
         self
     }
 
@@ -592,25 +1339,47 @@ impl Runner {
                     err
                 );
             };
-        } else {
-            self.process(id).name = name;
+
+            return self;
         }
 
+        let action = control::Action::Rename {
+            id,
+            name: name.clone(),
+        };
+        then!(self.control(action), return self);
+        self.lock();
+        self.process(id).name = name;
+
         self
     }
 
     pub fn watch(&mut self, id: usize, path: &str, enabled: bool) -> &mut Self {
+        let action = control::Action::Watch {
+            id,
+            path: string!(path),
+            enabled,
+        };
+        then!(self.control(action), return self);
+        self.lock();
+
         let process = self.process(id);
         process.watch = Watch {
             enabled,
             path: string!(path),
             hash: ternary!(enabled, hash::create(process.path.join(path)), string!("")),
+            ..process.watch.clone()
         };
 
         self
     }
 
     pub fn find(&self, name: &str, server_name: &String) -> Option<usize> {
+        self.find_all(name, server_name).first().copied()
+    }
+
+    /// Find every process sharing a name, which covers all instances of a group
+    pub fn find_all(&self, name: &str, server_name: &String) -> Vec<usize> {
         let mut runner = self.clone();
 
         if !matches!(&**server_name, "internal" | "local") {
@@ -635,8 +1404,9 @@ impl Runner {
         runner
             .list
             .iter()
-            .find(|(_, p)| p.name == name)
+            .filter(|(_, p)| p.name == name)
             .map(|(id, _)| *id)
+            .collect()
     }
 
     pub fn fetch(&self) -> Vec<ProcessItem> {
@@ -646,11 +1416,9 @@ impl Runner {
             let mut memory_usage: Option<MemoryInfo> = None;
             let mut cpu_percent: Option<f64> = None;
 
-            if let Ok(process) = unix::NativeProcess::new(item.pid as u32)
-                && let Ok(mem_info_native) = process.memory_info()
-            {
-                cpu_percent = Some(get_process_cpu_usage_percentage(item.pid));
-                memory_usage = Some(MemoryInfo::from(mem_info_native));
+            if let Some(memory) = item.memory_usage() {
+                cpu_percent = Some(item.cpu_usage());
+                memory_usage = Some(memory);
             }
 
             let cpu_percent = match cpu_percent {
@@ -663,14 +1431,7 @@ impl Runner {
                 None => string!("0b"),
             };
 
-            let status = if item.running {
-                string!("online")
-            } else {
-                match item.crash.crashed {
-                    true => string!("crashed"),
-                    false => string!("stopped"),
-                }
-            };
+            let status = string!(item.status());
 
             processes.push(ProcessItem {
                 id,
@@ -681,6 +1442,8 @@ impl Runner {
                 restarts: item.restarts,
                 name: item.name.clone(),
                 start_time: item.started,
+                instance: item.instance,
+                next_run: item.next_run,
                 watch_path: item.watch.path.clone(),
                 uptime: helpers::format_duration(item.started),
             });
@@ -709,17 +1472,242 @@ impl LogInfo {
                 self.error
             );
         }
+
+        rotate::remove(&self.out);
+        rotate::remove(&self.error);
     }
 }
 
 impl Process {
+    /// Longest a stop, restart or reload of the process can block, a reload
+    /// that times out stops the replacement before the old process
+    pub fn limit(&self) -> Duration {
+        let stop = self.stop.clone().unwrap_or_default();
+        stop.limit() * 2 + self.ready.limit()
+    }
+
+    /// Get the name used for log files, suffixed with the instance index
+    pub fn log_name(&self) -> String {
+        match self.instance {
+            Some(index) => format!("{}-{index}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Get the instance variables injected into the process environment
+    pub fn instance_vars(&self) -> Env {
+        match self.instance {
+            Some(index) => Env::from([(string!("PMC_INSTANCE_ID"), index.to_string())]),
+            None => Env::new(),
+        }
+    }
+
+    /// Get the instance variables formatted for `process_run`
+    pub fn instance_env(&self) -> Vec<String> {
+        self.instance_vars()
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect()
+    }
+
+    /// Read the declared variables again, sources that fail are left out
+    pub fn resolve_env(&mut self) {
+        let (resolved, errors) = self.declared.resolve(&self.path);
+
+        for err in errors {
+            log::warn!("[env] {} left out {err}", self.name);
+        }
+
+        self.resolved = resolved;
+    }
+
+    /// Get the declared variables formatted for `process_run`
+    pub fn declared_env(&self) -> Vec<String> {
+        self.resolved
+            .iter()
+            .map(|(key, var)| format!("{key}={}", var.value))
+            .collect()
+    }
+
+    /// Get the full environment passed to a (re)spawned process, `environ` is
+    /// the shell (re)starting it and only used in inherit mode
+    pub fn run_env(&self, environ: Vec<String>) -> Vec<String> {
+        let mut env = self
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<String>>();
+
+        then!(self.env_mode.inherits(), env.extend(environ));
+        env.extend(self.declared_env());
+        env.extend(self.instance_env());
+        env
+    }
+
+    /// Every variable of the process with its source, declared ones win over
+    /// the ones from its env mode
+    pub fn environment(&self) -> BTreeMap<String, environ::Var> {
+        let mut vars: BTreeMap<String, environ::Var> = (self.env.iter())
+            .map(|(key, value)| {
+                let var = environ::Var {
+                    value: value.clone(),
+                    source: string!(self.env_mode.source()),
+                };
+                (key.clone(), var)
+            })
+            .collect();
+
+        vars.extend(self.resolved.clone());
+        vars
+    }
+
+    /// Replace the values of secret variables everywhere they are kept
+    pub fn redact(&mut self, patterns: &[String]) {
+        environ::redact(patterns, &mut self.env);
+        environ::redact(patterns, &mut self.declared.vars);
+        for env in self.declared.profiles.values_mut() {
+            environ::redact(patterns, env);
+        }
+        environ::redact(
+            patterns,
+            (self.resolved.iter_mut()).map(|(key, var)| (key, &mut var.value)),
+        );
+    }
+
+    /// Status shown in lists, an online process failing its readiness probe is unready
+    pub fn status(&self) -> &'static str {
+        let ready = self.health.as_ref().is_none_or(health::Health::is_ready);
+
+        match (self.running, self.crash.crashed) {
+            (false, _) if self.stale => "stale",
+            (true, _) if !ready => "unready",
+            (true, _) => "online",
+            (false, true) => "crashed",
+            (false, false) => "stopped",
+        }
+    }
+
+    /// A freshly spawned process is unready until its readiness probe passes
+    fn reset_readiness(&mut self) {
+        if let Some(health) = &mut self.health {
+            health.ready = false;
+        }
+    }
+
+    /// Whether the process runs once per cron tick instead of staying up
+    pub fn is_job(&self) -> bool {
+        self.cron.is_some() && self.policy.mode == policy::Mode::Never
+    }
+
+    /// Memory of a running process, with what its cgroup is charged when it runs in one
+    pub fn memory_usage(&self) -> Option<MemoryInfo> {
+        let native = unix::NativeProcess::new(self.main_pid() as u32)
+            .ok()?
+            .memory_info()
+            .ok()?;
+
+        Some(MemoryInfo {
+            cgroup: self.cgroup.as_deref().and_then(cgroup::memory),
+            ..MemoryInfo::from(native)
+        })
+    }
+
+    /// Cpu usage of the process, read from its cgroup when it runs in one
+    pub fn cpu_usage(&self) -> f64 {
+        match self.cgroup.as_deref().and_then(cgroup::cpu_percent) {
+            Some(percent) => percent,
+            None => get_process_cpu_usage_percentage(self.main_pid()),
+        }
+    }
+
+    /// Pid running the command, usage is read from it instead of the shell
+    /// wrapping it. A shell that did not exec the command is left as the
+    /// group leader with the command as its only child
+    pub fn main_pid(&self) -> i64 {
+        let shell = config::read().runner.shell;
+        let wrapped = unix::get_process_name(self.pid as u32).is_ok_and(|name| {
+            Path::new(&shell)
+                .file_name()
+                .is_some_and(|shell| *shell == *name)
+        });
+        then!(!wrapped, return self.pid);
+
+        let direct: Vec<i64> = process_find_children(self.pid)
+            .into_iter()
+            .filter(|child| {
+                unix::get_parent_pid(*child as i32).is_ok_and(|ppid| ppid == Some(self.pid as i32))
+            })
+            .collect();
+
+        match direct.as_slice() {
+            [child] => *child,
+            _ => self.pid,
+        }
+    }
+
+    /// Whether the pid was taken by another process since it was spawned,
+    /// processes without a recorded identity are trusted
+    pub fn is_stale(&self) -> bool {
+        (self.identity.as_ref())
+            .is_some_and(|identity| stop::alive(self.pid) && !identity.matches(self.pid))
+    }
+
+    /// Whether the pid is alive and still the process that was spawned
+    pub fn is_alive(&self) -> bool {
+        stop::alive(self.pid) && !self.is_stale()
+    }
+
+    /// Collect the exit status when the process was spawned by the current process
+    pub fn reap(&self, timeout: Duration) -> Option<exit::Exit> {
+        exit::Exit::wait(self.pid, self.started, timeout)
+    }
+
+    /// Stop the process with its stop signal, killing it once the kill timeout
+    /// passes, and collect its exit when it was our child
+    pub fn terminate(&self) -> Result<(stop::Outcome, Option<exit::Exit>), String> {
+        // a reused pid belongs to someone else now, it is not ours to signal
+        if self.is_stale() {
+            log::warn!(
+                "pid {} of {} was reused, not signalling it",
+                self.pid,
+                self.name
+            );
+            return Ok((stop::Outcome::Stale, None));
+        }
+
+        let stop = self.stop.clone().unwrap_or_default();
+        let mut exit = None;
+
+        let outcome = stop::terminate(self.pid, self.pgid, &self.children, &stop, || {
+            exit = exit.take().or_else(|| self.reap(Duration::ZERO));
+            exit.is_some()
+        })?;
+
+        Ok((outcome, exit))
+    }
+
+    /// Append an exit record, keeping only the most recent ones
+    pub fn record_exit(&mut self, exit: exit::Exit) {
+        self.exits.push(exit);
+
+        if self.exits.len() > exit::MAX_EXITS {
+            self.exits.drain(..self.exits.len() - exit::MAX_EXITS);
+        }
+    }
+
     /// Get a log paths of the process item
     pub fn logs(&self) -> LogInfo {
-        let name = self.name.replace(" ", "_");
+        let name = self.log_name().replace(" ", "_");
 
         LogInfo {
-            out: global!("pmc.logs.out", name.as_str()),
-            error: global!("pmc.logs.error", name.as_str()),
+            out: match &self.out_file {
+                Some(file) => file.clone(),
+                None => global!("pmc.logs.out", name.as_str()),
+            },
+            error: match &self.error_file {
+                Some(file) => file.clone(),
+                None => global!("pmc.logs.error", name.as_str()),
+            },
         }
     }
 }
@@ -735,6 +1723,21 @@ impl ProcessWrapper {
         lock!(self.runner).restart(self.id, false).save();
     }
 
+    /// Reload the process item without downtime
+    pub fn reload(&mut self) {
+        lock!(self.runner).reload(self.id).save();
+    }
+
+    /// Set the readiness check used when reloading the process item
+    pub fn set_ready(&mut self, ready: ready::Ready) {
+        lock!(self.runner).set_ready(self.id, ready).save();
+    }
+
+    /// Apply optional settings to the process item
+    pub fn set_options(&mut self, options: &Options) {
+        lock!(self.runner).set_options(self.id, options).save();
+    }
+
     /// Rename the process item
     pub fn rename(&mut self, name: String) {
         lock!(self.runner).rename(self.id, name).save();
@@ -780,21 +1783,12 @@ impl ProcessWrapper {
         let mut memory_usage: Option<MemoryInfo> = None;
         let mut cpu_percent: Option<f64> = None;
 
-        if let Ok(process) = unix::NativeProcess::new(item.pid as u32)
-            && let Ok(mem_info_native) = process.memory_info()
-        {
-            cpu_percent = Some(get_process_cpu_usage_percentage(item.pid as i64));
-            memory_usage = Some(MemoryInfo::from(mem_info_native));
+        if let Some(memory) = item.memory_usage() {
+            cpu_percent = Some(item.cpu_usage());
+            memory_usage = Some(memory);
         }
 
-        let status = if item.running {
-            string!("online")
-        } else {
-            match item.crash.crashed {
-                true => string!("crashed"),
-                false => string!("stopped"),
-            }
-        };
+        let status = string!(item.status());
 
         ItemSingle {
             info: Info {
@@ -803,7 +1797,12 @@ impl ProcessWrapper {
                 pid: item.pid,
                 name: item.name.clone(),
                 path: item.path.clone(),
+                instance: item.instance,
                 children: item.children.clone(),
+                cron: item.cron.clone(),
+                next_run: item.next_run,
+                health: item.health.clone(),
+                depends: item.depends.clone(),
                 uptime: helpers::format_duration(item.started),
                 command: format!(
                     "{} {} '{}'",
@@ -818,11 +1817,7 @@ impl ProcessWrapper {
                 restarts: item.restarts,
                 start_time: item.started.timestamp_millis(),
             },
-            watch: Watch {
-                enabled: item.watch.enabled,
-                hash: item.watch.hash.clone(),
-                path: item.watch.path.clone(),
-            },
+            watch: item.watch.clone(),
             log: Log {
                 out: item.logs().out,
                 error: item.logs().error,
@@ -832,6 +1827,7 @@ impl ProcessWrapper {
                 crashed: item.crash.crashed,
                 crashes: item.crash.value,
             },
+            exits: item.exits.clone(),
         }
     }
 }
@@ -847,25 +1843,9 @@ pub fn get_process_cpu_usage_percentage(pid: i64) -> f64 {
     }
 }
 
-/// Stop the process
-pub fn process_stop(pid: i64) -> Result<(), String> {
-    let children = process_find_children(pid);
-
-    // Stop child processes first
-    for child_pid in children {
-        let _ = kill(Pid::from_raw(child_pid as i32), Signal::SIGTERM);
-        // Continue even if stopping child processes fails
-    }
-
-    // Stop parent process
-    match kill(Pid::from_raw(pid as i32), Signal::SIGTERM) {
-        Ok(_) => Ok(()),
-        Err(nix::errno::Errno::ESRCH) => {
-            // Process already terminated
-            Ok(())
-        }
-        Err(err) => Err(format!("Failed to stop process {}: {:?}", pid, err)),
-    }
+/// Stop the process with a stop signal, killing it once the kill timeout passes
+pub fn process_stop(pid: i64, stop: &stop::Stop) -> Result<stop::Outcome, String> {
+    stop::terminate(pid, None, &[], stop, || false)
 }
 
 /// Find the children of the process
@@ -933,32 +1913,36 @@ pub fn process_find_children(parent_pid: i64) -> Vec<i64> {
     children
 }
 
+/// Check a working directory is usable before anything is stopped or spawned
+fn working_dir(path: &Path) -> io::Result<()> {
+    match fs::metadata(path)?.is_dir() {
+        true => Ok(()),
+        false => Err(io::ErrorKind::NotADirectory.into()),
+    }
+}
+
 /// Run the process
-pub fn process_run(metadata: ProcessMetadata) -> Result<i64, String> {
+pub fn process_run(metadata: ProcessMetadata) -> Result<Spawned, String> {
     use std::fs::OpenOptions;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::process::CommandExt;
     use std::process::{Command, Stdio};
 
     let log_base = format!("{}/{}", metadata.log_path, metadata.name.replace(' ', "_"));
-    let stdout_path = format!("{}-out.log", log_base);
-    let stderr_path = format!("{}-error.log", log_base);
-
-    // Create log files
-    let stdout_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&stdout_path)
-        .map_err(|err| format!("Failed to open stdout log file {}: {:?}", stdout_path, err))?;
-
-    let stderr_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&stderr_path)
-        .map_err(|err| format!("Failed to open stderr log file {}: {:?}", stderr_path, err))?;
+    let relative = |file: &String| metadata.path.join(file).to_string_lossy().into_owned();
+    let stdout_path = (metadata.out_file.as_ref())
+        .map(relative)
+        .unwrap_or(format!("{}-out.log", log_base));
+    let stderr_path = (metadata.error_file.as_ref())
+        .map(relative)
+        .unwrap_or(format!("{}-error.log", log_base));
 
     // Execute process
     let mut cmd = Command::new(&metadata.shell);
+    then!(metadata.clear_env, cmd.env_clear());
     cmd.args(&metadata.args)
         .arg(&metadata.command)
+        .current_dir(&metadata.path)
         .envs(metadata.env.iter().map(|env_var| {
             let parts: Vec<&str> = env_var.splitn(2, '=').collect();
             if parts.len() == 2 {
@@ -967,18 +1951,68 @@ pub fn process_run(metadata: ProcessMetadata) -> Result<i64, String> {
                 (env_var.as_str(), "")
             }
         }))
-        .stdout(Stdio::from(stdout_file))
-        .stderr(Stdio::from(stderr_file))
-        .stdin(Stdio::null());
+        .stdin(Stdio::null())
+        // a group of its own lets stop and restart signal every descendant at once
+        .process_group(0);
+
+    // joining the cgroup before exec puts every descendant under its limits
+    let procs = (metadata.cgroup.as_ref()).and_then(|path| {
+        OpenOptions::new()
+            .write(true)
+            .open(path.join("cgroup.procs"))
+            .ok()
+    });
+
+    if let Some(fd) = procs.as_ref().map(|file| file.as_raw_fd()) {
+        unsafe {
+            cmd.pre_exec(move || {
+                libc::write(fd, b"0".as_ptr().cast(), 1);
+                Ok(())
+            });
+        }
+    }
 
-    let child = cmd
+    if metadata.collector.is_some() {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    } else {
+        // Create log files
+        let stdout_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&stdout_path)
+            .map_err(|err| format!("Failed to open stdout log file {}: {:?}", stdout_path, err))?;
+
+        let stderr_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&stderr_path)
+            .map_err(|err| format!("Failed to open stderr log file {}: {:?}", stderr_path, err))?;
+
+        cmd.stdout(Stdio::from(stdout_file))
+            .stderr(Stdio::from(stderr_file));
+    }
+
+    let mut child = cmd
         .spawn()
         .map_err(|err| format!("Failed to spawn process: {:?}", err))?;
 
-    let shell_pid = child.id() as i64;
-    let actual_pid = unix::get_actual_child_pid(shell_pid);
+    if let Some(collector) = &metadata.collector
+        && let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take())
+        && let Err(err) = collector.spawn(stdout, stderr, &stdout_path, &stderr_path)
+    {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(format!("Failed to start log collector: {:?}", err));
+    }
+
+    let pid = child.id() as i64;
+    let cgroup = metadata.cgroup.filter(|path| {
+        let joined = cgroup::contains(path, pid);
+        then!(!joined, cgroup::remove(path));
+        joined
+    });
 
-    Ok(actual_pid)
+    Ok(Spawned { pid, cgroup })
 }
 
 #[cfg(test)]
@@ -990,40 +2024,36 @@ mod tests {
 
     fn setup_test_runner() -> Runner {
         Runner {
+            version: schema::VERSION,
             id: id::Id::new(1),
             list: BTreeMap::new(),
             remote: None,
+            source: Source::Memory,
+            caller: None,
+            guard: None,
         }
     }
 
-    #[test]
-    fn test_environment_variables() {
-        let mut runner = setup_test_runner();
-        let id = runner.id.next();
-
-        let process = Process {
+    /// A running process every test case starts from
+    fn process(id: usize) -> Process {
+        Process {
             id,
             pid: 12345,
-            env: BTreeMap::new(),
             name: "test_process".to_string(),
             path: PathBuf::from("/tmp"),
             script: "echo 'hello world'".to_string(),
-            restarts: 0,
             running: true,
-            crash: Crash {
-                crashed: false,
-                value: 0,
-            },
-            watch: Watch {
-                enabled: false,
-                path: String::new(),
-                hash: String::new(),
-            },
-            children: vec![],
             started: Utc::now(),
-        };
+            ..Default::default()
+        }
+    }
 
-        runner.list.insert(id, process);
+    #[test]
+    fn test_environment_variables() {
+        let mut runner = setup_test_runner();
+        let id = runner.id.next();
+
+        runner.list.insert(id, process(id));
 
         // Test setting environment variables
         let mut env = BTreeMap::new();
@@ -1045,34 +2075,95 @@ mod tests {
     }
 
     #[test]
-    fn test_children_processes() {
+    fn test_env_modes() {
         let mut runner = setup_test_runner();
         let id = runner.id.next();
 
         let process = Process {
-            id,
-            pid: 12345,
-            env: BTreeMap::new(),
-            name: "test_process".to_string(),
-            path: PathBuf::from("/tmp"),
-            script: "echo 'hello world'".to_string(),
-            restarts: 0,
-            running: true,
-            crash: Crash {
-                crashed: false,
-                value: 0,
-            },
-            watch: Watch {
-                enabled: false,
-                path: String::new(),
-                hash: String::new(),
-            },
-            children: vec![],
-            started: Utc::now(),
+            env: BTreeMap::from([("TEST_VAR".to_string(), "test_value".to_string())]),
+            env_mode: environ::Mode::Inherit,
+            ..process(id)
         };
 
         runner.list.insert(id, process);
 
+        // Only inherit mode merges the shell restarting the process
+        let shell = vec!["SHELL_VAR=1".to_string()];
+        assert!(
+            runner
+                .try_info(id)
+                .run_env(shell.clone())
+                .contains(&shell[0])
+        );
+
+        let snapshot = Options {
+            env_mode: Some(environ::Mode::Snapshot),
+            ..Default::default()
+        };
+        runner.set_options(id, &snapshot);
+        assert!(
+            !runner
+                .try_info(id)
+                .run_env(shell.clone())
+                .contains(&shell[0])
+        );
+        assert!(runner.try_info(id).env.contains_key("TEST_VAR"));
+
+        let clean = Options {
+            env_mode: Some(environ::Mode::Clean),
+            ..Default::default()
+        };
+        runner.set_options(id, &clean);
+        assert!(!runner.try_info(id).env.contains_key("TEST_VAR"));
+        assert_eq!(runner.try_info(id).env["PATH"], environ::CLEAN_PATH);
+    }
+
+    #[test]
+    fn test_options_stop() {
+        let base = stop::Stop {
+            signal: string!("SIGQUIT"),
+            kill_timeout: 100,
+        };
+
+        let signal = Options {
+            stop_signal: Some(string!("int")),
+            ..Default::default()
+        };
+        assert_eq!(signal.stop(None).unwrap().unwrap().signal, "SIGINT");
+
+        let timeout = Options {
+            kill_timeout: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(
+            timeout.stop(Some(&base)),
+            Ok(Some(stop::Stop {
+                signal: string!("SIGQUIT"),
+                kill_timeout: 200
+            }))
+        );
+
+        let unknown = Options {
+            stop_signal: Some(string!("nope")),
+            ..Default::default()
+        };
+        assert!(unknown.stop(None).is_err());
+
+        let endless = Options {
+            kill_timeout: Some(stop::MAX_KILL_TIMEOUT_MS + 1),
+            ..Default::default()
+        };
+        assert!(endless.stop(None).is_err());
+        assert_eq!(Options::default().stop(None), Ok(None));
+    }
+
+    #[test]
+    fn test_children_processes() {
+        let mut runner = setup_test_runner();
+        let id = runner.id.next();
+
+        runner.list.insert(id, process(id));
+
         // Test setting children
         let children = vec![12346, 12347, 12348];
         runner.set_children(id, children.clone());
@@ -1080,6 +2171,66 @@ mod tests {
         assert_eq!(runner.info(id).unwrap().children, children);
     }
 
+    #[test]
+    fn test_instance_group() {
+        let mut runner = setup_test_runner();
+
+        for index in 0..3 {
+            let id = runner.id.next();
+            runner.list.insert(
+                id,
+                Process {
+                    name: "cluster".to_string(),
+                    script: "node index.js".to_string(),
+                    instance: Some(index),
+                    ..process(id)
+                },
+            );
+        }
+
+        let group = runner.find_all("cluster", &"local".to_string());
+        assert_eq!(group, vec![1, 2, 3]);
+        assert_eq!(runner.find("cluster", &"local".to_string()), Some(1));
+
+        let last = runner.info(3).unwrap();
+        assert_eq!(last.log_name(), "cluster-2");
+        assert_eq!(
+            last.instance_vars().get("PMC_INSTANCE_ID"),
+            Some(&"2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_main_pid() {
+        let config = config::read().runner;
+
+        // a compound command keeps the shell around with the command as its child
+        let mut shell = std::process::Command::new(&config.shell)
+            .args(&config.args)
+            .arg("sleep 5; true")
+            .spawn()
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let wrapped = Process {
+            pid: shell.id() as i64,
+            ..process(1)
+        };
+        let command = unix::get_process_name(wrapped.main_pid() as u32);
+
+        let _ = shell.kill();
+        let _ = shell.wait();
+
+        assert_eq!(command.as_deref(), Ok("sleep"));
+
+        // anything that is not the shell is measured as it is
+        let current = Process {
+            pid: std::process::id() as i64,
+            ..process(2)
+        };
+        assert_eq!(current.main_pid(), current.pid);
+    }
+
     #[test]
     fn test_cpu_usage_measurement() {
         // Test with current process (should return valid percentage)
@@ -1104,22 +2255,28 @@ mod tests {
     fn test_real_process_execution() {
         let metadata = ProcessMetadata {
             name: "test_echo".to_string(),
+            path: PathBuf::from("/tmp"),
             shell: "/bin/sh".to_string(),
             command: "echo 'Hello from test'".to_string(),
             log_path: "/tmp".to_string(),
             args: vec!["-c".to_string()],
             env: vec!["TEST_ENV=test_value".to_string()],
+            collector: None,
+            out_file: None,
+            error_file: None,
+            cgroup: None,
+            clear_env: false,
         };
 
         match process_run(metadata) {
-            Ok(pid) => {
-                assert!(pid > 0);
+            Ok(spawned) => {
+                assert!(spawned.pid > 0);
 
                 // Wait a bit for process to complete
                 thread::sleep(Duration::from_millis(100));
 
                 // Try to stop it (might already be finished)
-                let _ = process_stop(pid);
+                let _ = process_stop(spawned.pid, &stop::Stop::default());
             }
             Err(e) => {
                 panic!("Failed to run test process: {}", e);
@@ -0,0 +1,139 @@
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, time::Duration};
+use utoipa::ToSchema;
+
+/// When a dead process should be brought back up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    #[default]
+    Always,
+    OnFailure,
+    Never,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(Mode::Always),
+            "on-failure" | "on_failure" => Ok(Mode::OnFailure),
+            "never" => Ok(Mode::Never),
+            _ => Err(format!(
+                "invalid restart policy '{s}' (expected always, on-failure or never)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Always => write!(f, "always"),
+            Mode::OnFailure => write!(f, "on-failure"),
+            Mode::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// Restart policy with exponential backoff
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Policy {
+    pub mode: Mode,
+    /// Delay before the first restart in milliseconds
+    #[schema(example = 1000)]
+    pub delay: u64,
+    /// Factor the delay grows by on every consecutive crash
+    #[schema(example = 2.0)]
+    pub multiplier: f64,
+    /// Upper bound for the delay in milliseconds
+    #[schema(example = 30000)]
+    pub max_delay: u64,
+    /// Uptime in milliseconds after which the crash counter resets, 0 disables
+    #[schema(example = 60000)]
+    pub reset_after: u64,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            mode: Mode::Always,
+            delay: 0,
+            multiplier: 2.0,
+            max_delay: 30000,
+            reset_after: 0,
+        }
+    }
+}
+
+impl Policy {
+    /// Whether a process that exited with `code` should be restarted, unknown codes count as failures
+    pub fn should_restart(&self, code: Option<i32>) -> bool {
+        match self.mode {
+            Mode::Always => true,
+            Mode::OnFailure => code != Some(0),
+            Mode::Never => false,
+        }
+    }
+
+    /// Delay to wait before the restart following `crashes` consecutive crashes
+    pub fn backoff(&self, crashes: u64) -> Duration {
+        let delay = self.delay as f64 * self.multiplier.max(1.0).powi(crashes.min(64) as i32);
+        Duration::from_millis(delay.min(self.max_delay.max(self.delay) as f64) as u64)
+    }
+
+    /// Whether the process has been up long enough to forget previous crashes
+    pub fn is_stable(&self, uptime: Duration) -> bool {
+        self.reset_after > 0 && uptime >= Duration::from_millis(self.reset_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = Policy {
+            delay: 500,
+            multiplier: 2.0,
+            max_delay: 5000,
+            ..Policy::default()
+        };
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(3), Duration::from_millis(4000));
+        assert_eq!(policy.backoff(4), Duration::from_millis(5000));
+        assert_eq!(policy.backoff(1000), Duration::from_millis(5000));
+        assert_eq!(Policy::default().backoff(10), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_modes() {
+        let mut policy = Policy::default();
+        assert!(policy.should_restart(Some(0)));
+
+        policy.mode = "on-failure".parse().unwrap();
+        assert!(!policy.should_restart(Some(0)));
+        assert!(policy.should_restart(Some(1)));
+        assert!(policy.should_restart(None));
+
+        policy.mode = "never".parse().unwrap();
+        assert!(!policy.should_restart(Some(1)));
+        assert!("sometimes".parse::<Mode>().is_err());
+    }
+
+    #[test]
+    fn test_stable() {
+        let mut policy = Policy::default();
+        assert!(!policy.is_stable(Duration::from_secs(3600)));
+
+        policy.reset_after = 60000;
+        assert!(!policy.is_stable(Duration::from_secs(30)));
+        assert!(policy.is_stable(Duration::from_secs(60)));
+    }
+}
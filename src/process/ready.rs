@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_GRACE_MS: u64 = 1000;
+pub const READY_TIMEOUT_MS: u64 = 30000;
+const READY_POLL_MS: u64 = 250;
+
+/// Condition a replacement process has to meet before the old one is stopped
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Ready {
+    /// Wait a fixed amount of milliseconds
+    Grace(u64),
+    /// Wait until the process listens on a tcp port
+    Port(u16),
+    /// Wait until the text is written to the stdout log
+    Signal(String),
+}
+
+impl Default for Ready {
+    fn default() -> Self {
+        Ready::Grace(DEFAULT_GRACE_MS)
+    }
+}
+
+impl Ready {
+    /// Longest `wait` can block
+    pub fn limit(&self) -> Duration {
+        match self {
+            Ready::Grace(ms) => Duration::from_millis(*ms),
+            Ready::Port(_) | Ready::Signal(_) => Duration::from_millis(READY_TIMEOUT_MS),
+        }
+    }
+
+    /// Block until the process is ready, returns false on timeout or exit
+    pub fn wait(&self, pid: i64, log_path: &str, offset: u64) -> bool {
+        match self {
+            Ready::Grace(ms) => {
+                thread::sleep(Duration::from_millis(*ms));
+                alive(pid)
+            }
+            Ready::Port(port) => poll(pid, || listening(pid, *port)),
+            Ready::Signal(text) => poll(pid, || logged(log_path, offset, text)),
+        }
+    }
+}
+
+fn alive(pid: i64) -> bool {
+    unsafe { libc::kill(pid as i32, 0) == 0 }
+}
+
+fn poll(pid: i64, mut check: impl FnMut() -> bool) -> bool {
+    let started = Instant::now();
+
+    while started.elapsed() < Duration::from_millis(READY_TIMEOUT_MS) {
+        if check() {
+            return true;
+        }
+
+        if !alive(pid) {
+            return false;
+        }
+
+        thread::sleep(Duration::from_millis(READY_POLL_MS));
+    }
+
+    false
+}
+
+fn logged(path: &str, offset: u64, text: &str) -> bool {
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(offset)).is_ok()
+        && file.read_to_string(&mut contents).is_ok()
+        && contents.contains(text)
+}
+
+#[cfg(target_os = "linux")]
+fn listening(pid: i64, port: u16) -> bool {
+    use std::fs;
+
+    let mut inodes = Vec::new();
+
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(contents) = fs::read_to_string(table) else {
+            continue;
+        };
+
+        for line in contents.lines().skip(1) {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 10 || parts[3] != "0A" {
+                continue;
+            }
+
+            let local_port = parts[1]
+                .rsplit(':')
+                .next()
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok());
+
+            if local_port == Some(port) {
+                inodes.push(format!("socket:[{}]", parts[9]));
+            }
+        }
+    }
+
+    if inodes.is_empty() {
+        return false;
+    }
+
+    let mut pids = super::process_find_children(pid);
+    pids.push(pid);
+
+    pids.iter().any(|pid| {
+        let Ok(entries) = fs::read_dir(format!("/proc/{pid}/fd")) else {
+            return false;
+        };
+
+        entries.flatten().any(|entry| {
+            fs::read_link(entry.path())
+                .map(|link| inodes.iter().any(|inode| link.to_string_lossy() == *inode))
+                .unwrap_or(false)
+        })
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn listening(_pid: i64, port: u16) -> bool {
+    use std::net::{SocketAddr, TcpStream};
+
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+    TcpStream::connect_timeout(&address, Duration::from_millis(READY_POLL_MS)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::Write, net::TcpListener};
+
+    #[test]
+    fn test_ready_signal() {
+        let path = std::env::temp_dir().join(format!("pmc-ready-{}.log", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "server ready").unwrap();
+
+        let offset = fs::metadata(&path).unwrap().len();
+        let path = path.to_string_lossy().to_string();
+        assert!(!logged(&path, offset, "ready"));
+
+        writeln!(file, "listening, ready").unwrap();
+        assert!(logged(&path, offset, "ready"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ready_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let pid = std::process::id() as i64;
+
+        assert!(listening(pid, port));
+        drop(listener);
+        assert!(!listening(pid, port));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::{config::structs::Rotate, helpers};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    os::unix::fs::FileExt,
+    time::{Duration, SystemTime},
+};
+
+/// Path of the n-th rotated generation of a log, 1 being the newest
+pub fn generation(path: &str, n: usize) -> String {
+    format!("{path}.{n}.gz")
+}
+
+/// Paths of every rotated generation of a log, newest first
+pub fn generations(path: &str) -> Vec<String> {
+    (1..)
+        .map(|n| generation(path, n))
+        .take_while(|path| fs::metadata(path).is_ok())
+        .collect()
+}
+
+/// Whether a log has grown past the configured size or age
+pub fn is_due(path: &str, config: &Rotate) -> bool {
+    let Ok(metadata) = fs::metadata(path) else {
+        return false;
+    };
+
+    if metadata.len() == 0 {
+        return false;
+    }
+
+    if let Some(max_size) = config.max_size.as_deref().and_then(helpers::parse_size)
+        && metadata.len() >= max_size
+    {
+        return true;
+    }
+
+    if let Some(max_age) = config.max_age {
+        // the log is truncated in place, so its age counts from the last rotation
+        let since = match fs::metadata(generation(path, 1)) {
+            Ok(last) => last.modified(),
+            Err(_) => metadata.created(),
+        };
+
+        if let Ok(since) = since {
+            let age = SystemTime::now().duration_since(since).unwrap_or_default();
+            return age >= Duration::from_secs(max_age);
+        }
+    }
+
+    false
+}
+
+/// Stream the first `len` bytes of the log into a new generation, returning how many were copied
+fn compress(path: &str, len: u64) -> io::Result<u64> {
+    let file = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(generation(path, 1))?, Compression::default());
+
+    let copied = io::copy(&mut file.take(len), &mut encoder)?;
+    encoder.finish()?;
+    Ok(copied)
+}
+
+/// Drop the first `len` bytes of the log, moving whatever was written after them to the front.
+/// Processes append to their logs, so anything written after this lands behind the moved lines.
+fn discard(path: &str, len: u64) -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut rest = Vec::new();
+
+    file.seek(SeekFrom::Start(len))?;
+    file.read_to_end(&mut rest)?;
+
+    file.write_all_at(&rest, 0)?;
+    file.set_len(rest.len() as u64)
+}
+
+/// Compress the log into a new generation and truncate it, keeping `keep` generations.
+/// The file is truncated in place since the process keeps writing to its open descriptor,
+/// only the copied bytes are dropped so lines written during the copy stay in the log.
+pub fn rotate(path: &str, keep: usize) -> io::Result<()> {
+    let mut len = fs::metadata(path)?.len();
+
+    if keep > 0 {
+        let _ = fs::remove_file(generation(path, keep));
+
+        for n in (1..keep).rev() {
+            let from = generation(path, n);
+            if fs::metadata(&from).is_ok() {
+                fs::rename(&from, generation(path, n + 1))?;
+            }
+        }
+
+        len = compress(path, len)?;
+    }
+
+    discard(path, len)
+}
+
+/// Remove every rotated generation of a log
+pub fn remove(path: &str) {
+    for generation in generations(path) {
+        let _ = fs::remove_file(generation);
+    }
+}
+
+fn read_generation(path: &str) -> Vec<String> {
+    let Ok(file) = File::open(path) else {
+        return vec![];
+    };
+
+    let mut contents = String::new();
+    match GzDecoder::new(file).read_to_string(&mut contents) {
+        Ok(_) => contents.lines().map(String::from).collect(),
+        Err(_) => vec![],
+    }
+}
+
+fn read_current(path: &str) -> Vec<String> {
+    match File::open(path) {
+        Ok(file) => BufReader::new(file).lines().map_while(Result::ok).collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Read a log together with its rotated generations, oldest line first
+pub fn read_all(path: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for generation in generations(path).iter().rev() {
+        lines.extend(read_generation(generation));
+    }
+
+    lines.extend(read_current(path));
+    lines
+}
+
+/// Read the last `count` lines of a log, reaching into rotated generations when needed
+pub fn tail(path: &str, count: usize) -> Vec<String> {
+    let mut lines = read_current(path);
+
+    for generation in generations(path) {
+        if lines.len() >= count {
+            break;
+        }
+
+        let mut older = read_generation(&generation);
+        older.append(&mut lines);
+        lines = older;
+    }
+
+    let start = lines.len().saturating_sub(count);
+    lines.split_off(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_log(path: &str, lines: &[&str]) {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .unwrap();
+        for line in lines {
+            writeln!(file, "{line}").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rotate_generations() {
+        let dir = std::env::temp_dir().join(format!("pmc-rotate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app-out.log").to_string_lossy().to_string();
+
+        write_log(&path, &["one", "two"]);
+        rotate(&path, 2).unwrap();
+        write_log(&path, &["three"]);
+        rotate(&path, 2).unwrap();
+        write_log(&path, &["four"]);
+        rotate(&path, 2).unwrap();
+        write_log(&path, &["five", "six"]);
+
+        assert_eq!(generations(&path).len(), 2);
+        assert_eq!(read_all(&path), vec!["three", "four", "five", "six"]);
+        assert_eq!(tail(&path, 3), vec!["four", "five", "six"]);
+        assert_eq!(tail(&path, 1), vec!["six"]);
+
+        remove(&path);
+        assert!(generations(&path).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_keeps_later_lines() {
+        let dir = std::env::temp_dir().join(format!("pmc-discard-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app-out.log").to_string_lossy().to_string();
+
+        write_log(&path, &["one", "two"]);
+        let copied = compress(&path, fs::metadata(&path).unwrap().len()).unwrap();
+
+        // written after the copy, before the log is truncated
+        write_log(&path, &["three"]);
+        discard(&path, copied).unwrap();
+        write_log(&path, &["four"]);
+
+        assert_eq!(read_generation(&generation(&path, 1)), vec!["one", "two"]);
+        assert_eq!(read_current(&path), vec!["three", "four"]);
+        assert_eq!(read_all(&path), vec!["one", "two", "three", "four"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_due() {
+        let dir = std::env::temp_dir().join(format!("pmc-due-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app-error.log").to_string_lossy().to_string();
+
+        let config = Rotate {
+            max_size: Some(String::from("10b")),
+            max_age: None,
+            keep: 1,
+        };
+
+        write_log(&path, &["short"]);
+        assert!(!is_due(&path, &config));
+        write_log(&path, &["long enough"]);
+        assert!(is_due(&path, &config));
+        assert!(!is_due(&path, &Rotate::default()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,490 @@
+use crate::process::{Crash, Process, Runner, Source, stop};
+
+use macros_rs::{string, then};
+use nix::unistd::{Pid, getpgid, getpgrp};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Schema written into the dump, bump it together with a new step in `STEPS`
+pub const VERSION: u32 = 10;
+
+/// Upgrade of one process read from a dump of the previous version, `raw`
+/// holds its fields as written, returns what changed
+pub type Upgrade = fn(&ron::Map, &mut Process) -> Vec<String>;
+
+/// Steps of the migration chain, by the version they upgrade to
+pub const STEPS: &[(u32, &str, Upgrade)] = &[
+    (
+        1,
+        "store the schema version, fill in process fields added since",
+        |raw, _| {
+            added(
+                raw,
+                &[
+                    "instance",
+                    "ready",
+                    "policy",
+                    "max_memory",
+                    "max_cpu",
+                    "cron",
+                    "next_run",
+                    "out_file",
+                    "error_file",
+                    "exits",
+                    "crash.since",
+                ],
+            )
+        },
+    ),
+    (
+        2,
+        "add liveness and readiness probes to processes",
+        |raw, _| added(raw, &["health"]),
+    ),
+    (3, "add dependencies to processes", |raw, _| {
+        added(raw, &["depends"])
+    }),
+    (
+        4,
+        "add stop signal and kill timeout to processes",
+        |raw, _| added(raw, &["stop"]),
+    ),
+    (
+        5,
+        "run processes in their own process group, replace shell_pid with pgid",
+        v5,
+    ),
+    (6, "add cgroups and their limits to processes", |raw, _| {
+        added(raw, &["cgroup", "limits"])
+    }),
+    (
+        7,
+        "record the identity of process pids, mark reused pids stale",
+        |raw, _| added(raw, &["identity", "stale"]),
+    ),
+    (
+        8,
+        "add extra paths, ignore patterns, debounce and delay to watches",
+        |raw, _| {
+            added(
+                raw,
+                &[
+                    "watch.paths",
+                    "watch.ignore",
+                    "watch.debounce",
+                    "watch.delay",
+                ],
+            )
+        },
+    ),
+    (
+        9,
+        "add env files, env profiles and the source of declared variables",
+        |raw, _| added(raw, &["declared", "resolved"]),
+    ),
+    (
+        10,
+        "add the env mode to processes, inherit keeps the old behaviour",
+        |raw, _| added(raw, &["env_mode"]),
+    ),
+];
+
+/// Look up a field, `crash.since` reaches into the crash struct
+fn field<'a>(map: &'a ron::Map, path: &str) -> Option<&'a ron::Value> {
+    let (name, rest) = path.split_once('.').unwrap_or((path, ""));
+    let value = map
+        .iter()
+        .find(|(key, _)| matches!(key, ron::Value::String(key) if key == name))
+        .map(|(_, value)| value)?;
+
+    match (rest, value) {
+        ("", value) => Some(value),
+        (rest, ron::Value::Map(map)) => field(map, rest),
+        _ => None,
+    }
+}
+
+/// Fields a step added that the process was written without, they are
+/// filled in with their defaults when the dump is read
+fn added(raw: &ron::Map, names: &[&str]) -> Vec<String> {
+    let missing: Vec<&str> = (names.iter().copied())
+        .filter(|name| field(raw, name).is_none())
+        .collect();
+
+    match missing.is_empty() {
+        true => vec![],
+        false => vec![format!("add {}", missing.join(", "))],
+    }
+}
+
+/// Processes used to run under a shell in the group of the daemon that
+/// started them, a shell leading a group of its own becomes the pgid, any
+/// other shell still alive is tracked as a child so stopping reaches it
+fn v5(raw: &ron::Map, process: &mut Process) -> Vec<String> {
+    let shell = match field(raw, "shell_pid") {
+        Some(ron::Value::Number(number)) => number.as_i64().unwrap_or_default(),
+        _ => return vec![],
+    };
+
+    then!(
+        shell <= 0 || !stop::alive(shell),
+        return vec![string!("drop shell_pid")]
+    );
+
+    let group = getpgid(Some(Pid::from_raw(shell as i32)));
+    if group.is_ok_and(|group| group.as_raw() as i64 == shell && group != getpgrp()) {
+        process.pgid = Some(shell);
+        return vec![format!("replace shell_pid with pgid {shell}")];
+    }
+
+    then!(
+        shell == process.pid || process.children.contains(&shell),
+        return vec![string!("drop shell_pid")]
+    );
+
+    process.children.push(shell);
+    vec![format!("track shell_pid {shell} as a child")]
+}
+
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default)]
+    version: u32,
+}
+
+/// A dump upgraded to the current schema
+pub struct Migration {
+    /// Schema the dump was written with
+    pub from: u32,
+    pub runner: Runner,
+    /// What the upgrade changed, one line per change
+    pub changes: Vec<String>,
+}
+
+impl Migration {
+    pub fn is_current(&self) -> bool {
+        self.from == VERSION
+    }
+}
+
+/// Upgrade a dump written with any known schema to the current one, running
+/// every step above the version it was written with on each process
+pub fn migrate(bytes: &[u8]) -> Result<Migration, String> {
+    let Header { version } = ron::de::from_bytes(bytes).map_err(|err| string!(err))?;
+
+    let mut runner = match version {
+        0 => v0::read(bytes)?,
+        // fields added since are optional or defaulted, the steps fill in the rest
+        1..=VERSION => ron::de::from_bytes::<Runner>(bytes).map_err(|err| string!(err))?,
+        newer => {
+            return Err(format!(
+                "dump schema v{newer} is newer than v{VERSION}, update pmc to read it"
+            ));
+        }
+    };
+
+    let mut changes = vec![];
+    let raw = processes(bytes);
+
+    for (_, _, upgrade) in STEPS.iter().filter(|(step, ..)| *step > version) {
+        for (id, process) in runner.list.iter_mut() {
+            let Some(fields) = raw.get(id) else {
+                continue;
+            };
+
+            for change in upgrade(fields, process) {
+                changes.push(format!("process {id}: {change}"));
+            }
+        }
+    }
+
+    if version != VERSION {
+        runner.version = VERSION;
+        changes.insert(0, format!("set schema version to {VERSION}"));
+    }
+
+    Ok(Migration {
+        from: version,
+        runner,
+        changes,
+    })
+}
+
+/// Fields of every process as written, the typed structs fill in defaults
+/// silently so the raw values tell which were missing
+fn processes(bytes: &[u8]) -> BTreeMap<usize, ron::Map> {
+    let Ok(ron::Value::Map(root)) = ron::de::from_bytes::<ron::Value>(bytes) else {
+        return BTreeMap::new();
+    };
+
+    let Some(ron::Value::Map(list)) = field(&root, "list") else {
+        return BTreeMap::new();
+    };
+
+    list.iter()
+        .filter_map(|(id, item)| match item {
+            ron::Value::Map(fields) => {
+                Some((id.clone().into_rust::<usize>().ok()?, fields.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Dumps written before the schema was versioned, every field added to a
+/// process since the first release may be missing
+mod v0 {
+    use super::*;
+    use crate::process::{Env, Watch, exit, id, limit, policy, ready};
+
+    use chrono::serde::{ts_milliseconds, ts_milliseconds_option};
+    use chrono::{DateTime, Utc};
+    use std::path::PathBuf;
+
+    #[derive(Deserialize)]
+    struct Runner {
+        id: id::Id,
+        list: BTreeMap<usize, Process>,
+    }
+
+    #[derive(Deserialize)]
+    struct Process {
+        id: usize,
+        pid: i64,
+        env: Env,
+        name: String,
+        path: PathBuf,
+        script: String,
+        restarts: u64,
+        running: bool,
+        crash: Crash,
+        watch: Watch,
+        children: Vec<i64>,
+        #[serde(default)]
+        instance: Option<usize>,
+        #[serde(default)]
+        ready: ready::Ready,
+        #[serde(default)]
+        policy: policy::Policy,
+        #[serde(default)]
+        max_memory: Option<limit::Memory>,
+        #[serde(default)]
+        max_cpu: Option<limit::Cpu>,
+        #[serde(default)]
+        cron: Option<String>,
+        #[serde(default, with = "ts_milliseconds_option")]
+        next_run: Option<DateTime<Utc>>,
+        #[serde(default)]
+        out_file: Option<String>,
+        #[serde(default)]
+        error_file: Option<String>,
+        #[serde(default)]
+        exits: Vec<exit::Exit>,
+        #[serde(with = "ts_milliseconds")]
+        started: DateTime<Utc>,
+    }
+
+    #[derive(Deserialize)]
+    struct Crash {
+        crashed: bool,
+        value: u64,
+        #[serde(default, with = "ts_milliseconds_option")]
+        since: Option<DateTime<Utc>>,
+    }
+
+    pub(super) fn read(bytes: &[u8]) -> Result<super::Runner, String> {
+        let old: Runner = ron::de::from_bytes(bytes).map_err(|err| string!(err))?;
+
+        let list = old
+            .list
+            .into_iter()
+            .map(|(id, item)| (id, item.into()))
+            .collect();
+
+        Ok(super::Runner {
+            version: VERSION,
+            id: old.id,
+            list,
+            remote: None,
+            source: Source::Memory,
+            caller: None,
+            guard: None,
+        })
+    }
+
+    impl From<Process> for super::Process {
+        fn from(item: Process) -> Self {
+            super::Process {
+                id: item.id,
+                pid: item.pid,
+                pgid: None,
+                identity: None,
+                stale: false,
+                cgroup: None,
+                limits: None,
+                env: item.env,
+                env_mode: Default::default(),
+                declared: Default::default(),
+                resolved: BTreeMap::new(),
+                name: item.name,
+                path: item.path,
+                script: item.script,
+                restarts: item.restarts,
+                running: item.running,
+                crash: super::Crash {
+                    crashed: item.crash.crashed,
+                    value: item.crash.value,
+                    since: item.crash.since,
+                },
+                watch: item.watch,
+                children: item.children,
+                instance: item.instance,
+                ready: item.ready,
+                policy: item.policy,
+                max_memory: item.max_memory,
+                max_cpu: item.max_cpu,
+                cron: item.cron,
+                next_run: item.next_run,
+                out_file: item.out_file,
+                error_file: item.error_file,
+                health: None,
+                depends: None,
+                stop: None,
+                exits: item.exits,
+                started: item.started,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::environ::Mode;
+    use std::{os::unix::process::CommandExt, process::Command};
+
+    const V0: &str = r#"(id:(counter:2),list:{1:(id:1,pid:0,env:{},name:"api",path:"/tmp",script:"node index.js",restarts:0,running:false,crash:(crashed:false,value:0),watch:(enabled:false,path:"",hash:""),children:[],started:1700000000000)})"#;
+
+    #[test]
+    fn test_migrate_v0() {
+        let migration = migrate(V0.as_bytes()).unwrap();
+
+        assert_eq!(migration.from, 0);
+        assert!(!migration.is_current());
+        assert_eq!(migration.runner.version, VERSION);
+        assert_eq!(migration.runner.list[&1].name, "api");
+        assert!(migration.runner.list[&1].crash.since.is_none());
+        assert!(migration.changes[1].starts_with("process 1: add instance, ready"));
+        assert!(migration.changes[1].ends_with("exits, crash.since"));
+    }
+
+    #[test]
+    fn test_steps_reach_version() {
+        // every version bump has to describe its step
+        assert_eq!(STEPS.last().map(|step| step.0), Some(VERSION));
+        assert!(STEPS.windows(2).all(|pair| pair[0].0 + 1 == pair[1].0));
+    }
+
+    #[test]
+    fn test_migrate_current() {
+        let upgraded = migrate(V0.as_bytes()).unwrap().runner;
+        let encoded = ron::ser::to_string(&upgraded).unwrap();
+        let migration = migrate(encoded.as_bytes()).unwrap();
+
+        assert!(migration.is_current());
+        assert!(migration.changes.is_empty());
+    }
+
+    /// Fields as the current schema writes them for `V0`, by the version that added them
+    const ADDED: &[(u32, &str)] = &[
+        (2, ",health:None"),
+        (3, ",depends:None"),
+        (4, ",stop:None"),
+        (5, ",pgid:None"),
+        (6, ",cgroup:None"),
+        (6, ",limits:None"),
+        (7, ",identity:None,stale:false"),
+        (8, ",paths:[],ignore:[],debounce:200,delay:0"),
+        (
+            9,
+            ",declared:(files:[],vars:{},profiles:{},profile:None),resolved:{}",
+        ),
+        (10, ",env_mode:inherit"),
+    ];
+
+    /// `V0` as a dump of an older version would have written it
+    fn written_with(version: u32) -> String {
+        let current = ron::ser::to_string(&migrate(V0.as_bytes()).unwrap().runner).unwrap();
+
+        (ADDED.iter()).filter(|(added, _)| *added > version).fold(
+            current.replace(&format!("version:{VERSION}"), &format!("version:{version}")),
+            |dump, (_, field)| dump.replace(field, ""),
+        )
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let migration = migrate(written_with(1).as_bytes()).unwrap();
+
+        assert_eq!(migration.from, 1);
+        assert_eq!(migration.runner.version, VERSION);
+        assert!(migration.runner.list[&1].health.is_none());
+        assert_eq!(migration.runner.list[&1].watch.debounce, 200);
+        assert!(migration.runner.list[&1].declared.is_empty());
+        assert_eq!(migration.runner.list[&1].env_mode, Mode::Inherit);
+        assert_eq!(
+            migration.changes[0],
+            format!("set schema version to {VERSION}")
+        );
+        assert_eq!(migration.changes[1], "process 1: add health");
+        assert_eq!(migration.changes.last().unwrap(), "process 1: add env_mode");
+    }
+
+    #[test]
+    fn test_migrate_v4() {
+        // the shell leads a group of its own here, the daemon group never becomes the pgid
+        let mut shell = Command::new("sleep")
+            .arg("5")
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pid = shell.id() as i64;
+
+        let v4 = written_with(4).replace(",pid:0", &format!(",pid:{pid},shell_pid:{pid}"));
+        let migration = migrate(v4.as_bytes()).unwrap();
+
+        let _ = shell.kill();
+        let _ = shell.wait();
+
+        assert_eq!(migration.from, 4);
+        assert_eq!(migration.runner.list[&1].pgid, Some(pid));
+        assert!(
+            (migration.changes.iter())
+                .any(|change| *change == format!("process 1: replace shell_pid with pgid {pid}"))
+        );
+        assert!(
+            !migration
+                .changes
+                .iter()
+                .any(|change| change.contains("add stop"))
+        );
+    }
+
+    #[test]
+    fn test_migrate_v4_dead_shell() {
+        let v4 = written_with(4).replace(",pid:0", ",pid:0,shell_pid:0");
+        let migration = migrate(v4.as_bytes()).unwrap();
+
+        assert!(migration.runner.list[&1].pgid.is_none());
+        assert!(migration.runner.list[&1].children.is_empty());
+        assert_eq!(migration.changes[1], "process 1: drop shell_pid");
+    }
+
+    #[test]
+    fn test_migrate_newer() {
+        let err = migrate(b"(version:99,id:(counter:0),list:{})")
+            .err()
+            .unwrap();
+        assert!(err.contains("v99"));
+    }
+}
@@ -0,0 +1,360 @@
+use crate::process::process_find_children;
+
+use core::fmt;
+use macros_rs::{string, then};
+use nix::{
+    errno::Errno,
+    sys::signal::{Signal, kill, killpg},
+    unistd::{Pid, getpgrp},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::{
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_SIGNAL: &str = "SIGTERM";
+pub const DEFAULT_KILL_TIMEOUT_MS: u64 = 5000;
+
+/// Longest kill timeout accepted, so a stop never holds up its caller for good
+pub const MAX_KILL_TIMEOUT_MS: u64 = 600_000;
+
+/// How long to wait for the process group to die after SIGKILL
+const KILL_WAIT_MS: u64 = 1000;
+const STOP_POLL_MS: u64 = 50;
+
+/// How a process is asked to stop before it is killed
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Stop {
+    /// Signal sent first, like SIGINT or SIGQUIT
+    #[schema(example = "SIGTERM")]
+    pub signal: String,
+    /// Milliseconds to wait for an exit before SIGKILL is sent
+    #[schema(example = 5000)]
+    pub kill_timeout: u64,
+}
+
+impl Default for Stop {
+    fn default() -> Self {
+        Stop {
+            signal: string!(DEFAULT_SIGNAL),
+            kill_timeout: DEFAULT_KILL_TIMEOUT_MS,
+        }
+    }
+}
+
+impl Stop {
+    pub fn is_default(&self) -> bool {
+        self == &Stop::default()
+    }
+
+    /// Longest a stop can take, the kill timeout and the wait after SIGKILL
+    pub fn limit(&self) -> Duration {
+        Duration::from_millis(self.kill_timeout + KILL_WAIT_MS)
+    }
+
+    /// Signal to send first, SIGTERM for one saved before signals were checked
+    fn signal(&self) -> Signal {
+        Signal::from_str(&self.signal).unwrap_or_else(|_| {
+            log::warn!("unknown stop signal {}, sending SIGTERM", self.signal);
+            Signal::SIGTERM
+        })
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, kill after {}ms", self.signal, self.kill_timeout)
+    }
+}
+
+/// Normalize a signal name like int, sigint or SIGINT
+pub fn parse_signal(value: &str) -> Result<String, String> {
+    let name = value.to_uppercase();
+    let name = match name.starts_with("SIG") {
+        true => name,
+        false => format!("SIG{name}"),
+    };
+
+    Signal::from_str(&name)
+        .map(|signal| signal.as_str().to_string())
+        .map_err(|_| format!("unknown signal '{value}'"))
+}
+
+/// Check a kill timeout in milliseconds is not above the maximum
+pub fn check_kill_timeout(ms: u64) -> Result<u64, String> {
+    match ms <= MAX_KILL_TIMEOUT_MS {
+        true => Ok(ms),
+        false => Err(format!(
+            "kill timeout {ms}ms is above the maximum of {MAX_KILL_TIMEOUT_MS}ms"
+        )),
+    }
+}
+
+/// Parse a kill timeout in milliseconds
+pub fn parse_kill_timeout(value: &str) -> Result<u64, String> {
+    let ms = value
+        .parse()
+        .map_err(|_| format!("invalid kill timeout '{value}'"))?;
+    check_kill_timeout(ms)
+}
+
+/// How a process went away
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    /// It was not running anymore
+    Exited,
+    /// It exited after the stop signal
+    Graceful,
+    /// It outlived the kill timeout and was killed
+    Forced,
+    /// Its pid belongs to another process now, nothing was signalled
+    Stale,
+}
+
+/// Send the stop signal to the process group, then SIGKILL it once the kill
+/// timeout passes. `gone` is polled next to the pid so callers can reap the
+/// exit status as soon as it is available. Without a group the process and
+/// the children found under it are signalled one by one instead.
+pub fn terminate(
+    pid: i64,
+    group: Option<i64>,
+    children: &[i64],
+    stop: &Stop,
+    mut gone: impl FnMut() -> bool,
+) -> Result<Outcome, String> {
+    let group = group.filter(|group| *group > 0);
+    let mut dead = || (gone() || !alive(pid)) && group.is_none_or(|group| !group_alive(group));
+    then!(dead(), return Ok(Outcome::Exited));
+
+    let mut tree = vec![];
+
+    match group {
+        Some(group) => send_group(group, stop.signal()),
+        None => {
+            tree = process_find_children(pid);
+            for child in children {
+                then!(!tree.contains(child), tree.push(*child));
+            }
+
+            for child in &tree {
+                let _ = send(*child, stop.signal());
+            }
+
+            send(pid, stop.signal())
+        }
+    }
+    .map_err(|err| format!("Failed to stop process {pid}: {err:?}"))?;
+
+    if wait(&mut dead, Duration::from_millis(stop.kill_timeout)) {
+        return Ok(Outcome::Graceful);
+    }
+
+    match group {
+        Some(group) => {
+            let _ = send_group(group, Signal::SIGKILL);
+        }
+        None => {
+            for pid in tree.iter().chain([&pid]) {
+                let _ = send(*pid, Signal::SIGKILL);
+            }
+        }
+    }
+
+    wait(&mut dead, Duration::from_millis(KILL_WAIT_MS));
+
+    Ok(Outcome::Forced)
+}
+
+/// Send a signal to the process group, or to the process alone without one
+pub fn signal_process(pid: i64, group: Option<i64>, signal: Signal) -> Result<(), Errno> {
+    match group.filter(|group| *group > 0) {
+        Some(group) => send_group(group, signal),
+        None => send(pid, signal),
+    }
+}
+
+fn send(pid: i64, signal: Signal) -> Result<(), Errno> {
+    // 0 and negative pids address whole groups, never signal those by accident
+    then!(pid <= 0, return Ok(()));
+
+    match kill(Pid::from_raw(pid as i32), signal) {
+        Err(Errno::ESRCH) => Ok(()),
+        result => result,
+    }
+}
+
+fn send_group(group: i64, signal: Signal) -> Result<(), Errno> {
+    // never signal the group we run in, a process started before groups may still share it
+    then!(
+        group <= 1 || group == getpgrp().as_raw() as i64,
+        return Ok(())
+    );
+
+    match killpg(Pid::from_raw(group as i32), signal) {
+        Err(Errno::ESRCH) => Ok(()),
+        result => result,
+    }
+}
+
+fn wait(dead: &mut impl FnMut() -> bool, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if dead() {
+            return true;
+        }
+
+        if Instant::now() >= deadline {
+            return false;
+        }
+
+        thread::sleep(Duration::from_millis(STOP_POLL_MS));
+    }
+}
+
+/// Whether any process in the group has not exited
+pub fn group_alive(group: i64) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return false;
+        };
+
+        entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<i64>().ok())
+            .any(|pid| {
+                let Ok(stat) = std::fs::read_to_string(format!("/proc/{pid}/stat")) else {
+                    return false;
+                };
+
+                // fields after the command: state, ppid, pgrp
+                let fields: Vec<&str> = stat
+                    .rsplit_once(')')
+                    .map_or(vec![], |(_, rest)| rest.split_whitespace().collect());
+
+                fields.get(2) == Some(&group.to_string().as_str()) && fields.first() != Some(&"Z")
+            })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        killpg(Pid::from_raw(group as i32), None).is_ok()
+    }
+}
+
+/// Whether the pid belongs to a process that has not exited, zombies count as exited
+pub fn alive(pid: i64) -> bool {
+    if pid <= 0 || kill(Pid::from_raw(pid as i32), None).is_err() {
+        return false;
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Ok(stat) = std::fs::read_to_string(format!("/proc/{pid}/stat"))
+        && let Some((_, rest)) = stat.rsplit_once(')')
+    {
+        return !rest.trim_start().starts_with('Z');
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command};
+
+    fn spawn(script: &str) -> Child {
+        let child = (Command::new("sh").args(["-c", script]))
+            .process_group(0)
+            .spawn()
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(100));
+        child
+    }
+
+    fn stop(signal: &str) -> Stop {
+        Stop {
+            signal: string!(signal),
+            kill_timeout: 300,
+        }
+    }
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("int").unwrap(), "SIGINT");
+        assert_eq!(parse_signal("SIGQUIT").unwrap(), "SIGQUIT");
+        assert!(parse_signal("SIGNOPE").is_err());
+    }
+
+    #[test]
+    fn test_parse_kill_timeout() {
+        assert_eq!(parse_kill_timeout("3000"), Ok(3000));
+        assert_eq!(parse_kill_timeout("600000"), Ok(MAX_KILL_TIMEOUT_MS));
+        assert!(parse_kill_timeout("600001").is_err());
+        assert!(parse_kill_timeout("soon").is_err());
+    }
+
+    #[test]
+    fn test_terminate_graceful() {
+        let mut child = spawn("sleep 5");
+        let pid = child.id() as i64;
+
+        let outcome = terminate(pid, None, &[], &stop("SIGINT"), || {
+            child.try_wait().is_ok_and(|status| status.is_some())
+        });
+
+        assert_eq!(outcome, Ok(Outcome::Graceful));
+        assert_eq!(
+            terminate(pid, None, &[], &stop("SIGINT"), || true),
+            Ok(Outcome::Exited)
+        );
+    }
+
+    #[test]
+    fn test_terminate_forced() {
+        let mut child = spawn("trap '' TERM; while :; do sleep 0.1; done");
+        let pid = child.id() as i64;
+
+        let outcome = terminate(pid, None, &[], &stop("SIGTERM"), || {
+            child.try_wait().is_ok_and(|status| status.is_some())
+        });
+
+        assert_eq!(outcome, Ok(Outcome::Forced));
+        assert!(!alive(pid));
+    }
+
+    #[test]
+    fn test_terminate_group() {
+        // the grandchild ignores the stop signal and outlives the shell
+        let mut child = spawn("(trap '' TERM; sleep 30) & wait");
+        let pid = child.id() as i64;
+
+        let outcome = terminate(pid, Some(pid), &[], &stop("SIGTERM"), || {
+            child.try_wait().is_ok_and(|status| status.is_some())
+        });
+
+        assert_eq!(outcome, Ok(Outcome::Forced));
+        assert!(!group_alive(pid));
+    }
+
+    #[test]
+    fn test_signal_process() {
+        // the shell waits on its command, only the group reaches the command
+        let mut child = spawn("sleep 30; true");
+        let pid = child.id() as i64;
+
+        signal_process(pid, Some(pid), Signal::SIGTERM).unwrap();
+        let _ = child.wait();
+
+        wait(&mut || !group_alive(pid), Duration::from_secs(1));
+        assert!(!group_alive(pid));
+    }
+}
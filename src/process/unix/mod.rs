@@ -1,5 +1,4 @@
-use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::SystemTime;
 
 pub mod cpu;
 pub mod env;
@@ -61,62 +60,3 @@ impl NativeProcess {
         Ok(self.cpu_percent)
     }
 }
-
-#[cfg(target_os = "linux")]
-pub fn get_actual_child_pid(shell_pid: i64) -> i64 {
-    thread::sleep(Duration::from_millis(PROCESS_OPERATION_DELAY_MS));
-
-    let proc_path = format!("/proc/{}/task/{}/children", shell_pid, shell_pid);
-    if let Ok(contents) = std::fs::read_to_string(&proc_path)
-        && let Some(child_pid_str) = contents.split_whitespace().next()
-        && let Ok(child_pid) = child_pid_str.parse::<i64>()
-    {
-        return child_pid;
-    }
-
-    if let Ok(processes) = native_processes() {
-        processes
-            .iter()
-            .find(|process| {
-                if let Ok(Some(ppid)) = process.ppid() {
-                    ppid as i64 == shell_pid
-                } else {
-                    false
-                }
-            })
-            .map_or(shell_pid, |p| p.pid() as i64)
-    } else {
-        shell_pid
-    }
-}
-
-#[cfg(target_os = "macos")]
-pub fn get_actual_child_pid(shell_pid: i64) -> i64 {
-    // Wait for shell to spawn the actual command
-    thread::sleep(Duration::from_millis(PROCESS_OPERATION_DELAY_MS));
-
-    // Find children by iterating processes
-    if let Ok(processes) = native_processes() {
-        for process in processes {
-            let ppid = match process.ppid() {
-                Ok(Some(ppid)) => ppid as i64,
-                _ => continue,
-            };
-
-            if ppid == shell_pid {
-                return process.pid() as i64;
-            }
-        }
-    }
-
-    // Fallback: try using sysctl or other macOS specific methods
-    for test_pid in 1..32768 {
-        if let Ok(Some(ppid)) = get_parent_pid(test_pid)
-            && ppid as i64 == shell_pid
-        {
-            return test_pid as i64;
-        }
-    }
-
-    shell_pid
-}
@@ -1,5 +1,5 @@
 use std::time::SystemTime;
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "macos"))]
 use std::time::{Duration, UNIX_EPOCH};
 
 pub fn get_process_name(pid: u32) -> Result<String, String> {
@@ -74,6 +74,7 @@ pub fn get_process_name(pid: u32) -> Result<String, String> {
     }
 }
 
+/// Start time of the process. On linux it counts from boot, on macos from the epoch.
 pub fn get_process_start_time(_pid: u32) -> Result<SystemTime, String> {
     #[cfg(target_os = "linux")]
     {
@@ -83,18 +84,78 @@ pub fn get_process_start_time(_pid: u32) -> Result<SystemTime, String> {
         let stat_content = fs::read_to_string(&stat_path)
             .map_err(|e| format!("Failed to read process stat: {}", e))?;
 
-        let parts: Vec<&str> = stat_content.split_whitespace().collect();
-        if parts.len() > 21
-            && let Ok(start_time) = parts[21].parse::<u64>()
-        {
-            // Convert from clock ticks to seconds (simplified)
-            let uptime_secs = start_time / 100;
-            return Ok(UNIX_EPOCH + Duration::from_secs(uptime_secs));
+        // the command may contain spaces, count fields after its closing parenthesis
+        let start_time = stat_content
+            .rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().nth(19)?.parse::<u64>().ok());
+
+        if let Some(start_time) = start_time {
+            let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+            return Ok(UNIX_EPOCH + Duration::from_millis(start_time * 1000 / ticks));
         }
     }
 
-    // Fallback to current time for macOS and other systems
-    Ok(SystemTime::now())
+    #[cfg(target_os = "macos")]
+    {
+        use std::mem;
+
+        const PROC_PIDTBSDINFO: i32 = 3;
+
+        #[repr(C)]
+        struct ProcBsdInfo {
+            pbi_flags: u32,
+            pbi_status: u32,
+            pbi_xstatus: u32,
+            pbi_pid: u32,
+            pbi_ppid: u32,
+            pbi_uid: u32,
+            pbi_gid: u32,
+            pbi_ruid: u32,
+            pbi_rgid: u32,
+            pbi_svuid: u32,
+            pbi_svgid: u32,
+            rfu_1: u32,
+            pbi_comm: [libc::c_char; 16],
+            pbi_name: [libc::c_char; 32],
+            pbi_nfiles: u32,
+            pbi_pgid: u32,
+            pbi_pjobc: u32,
+            e_tdev: u32,
+            e_tpgid: u32,
+            pbi_nice: i32,
+            pbi_start_tvsec: u64,
+            pbi_start_tvusec: u64,
+        }
+
+        unsafe extern "C" {
+            fn proc_pidinfo(
+                pid: libc::c_int,
+                flavor: libc::c_int,
+                arg: u64,
+                buffer: *mut libc::c_void,
+                buffersize: libc::c_int,
+            ) -> libc::c_int;
+        }
+
+        let mut proc_info: ProcBsdInfo = unsafe { mem::zeroed() };
+        let result = unsafe {
+            proc_pidinfo(
+                _pid as i32,
+                PROC_PIDTBSDINFO,
+                0,
+                &mut proc_info as *mut _ as *mut libc::c_void,
+                mem::size_of::<ProcBsdInfo>() as i32,
+            )
+        };
+
+        if result > 0 {
+            return Ok(UNIX_EPOCH
+                + Duration::from_secs(proc_info.pbi_start_tvsec)
+                + Duration::from_micros(proc_info.pbi_start_tvusec));
+        }
+    }
+
+    Err(format!("Failed to get start time for PID {}", _pid))
 }
 
 /// Get parent process ID for a given process ID on macOS
@@ -215,13 +276,17 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_get_process_start_time() {
+        assert!(get_process_start_time(std::process::id()).is_ok());
+        assert!(get_process_start_time(999999).is_err());
+    }
+
     #[test]
     fn test_get_parent_pid_init() {
         // Test with init process (PID 1) - should have no parent or parent 0
-        if let Ok(parent) = get_parent_pid(1) {
-            if let Some(ppid) = parent {
-                assert_eq!(ppid, 0);
-            }
+        if let Ok(Some(ppid)) = get_parent_pid(1) {
+            assert_eq!(ppid, 0);
         }
     }
 }
@@ -0,0 +1,138 @@
+use crate::process::Watch;
+
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::Path};
+use utoipa::ToSchema;
+
+/// Quiet period after the last change before a watched process restarts
+pub const DEFAULT_DEBOUNCE_MS: u64 = 200;
+
+pub fn default_debounce() -> u64 {
+    DEFAULT_DEBOUNCE_MS
+}
+
+/// Watch settings besides the first path, unset fields keep the current value
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(default)]
+pub struct Settings {
+    /// First watched path, an empty path stops watching
+    #[schema(example = "src")]
+    pub path: Option<String>,
+    /// Paths watched besides the first one, an empty list removes them
+    #[schema(example = json!(["config"]))]
+    pub paths: Option<Vec<String>>,
+    /// Glob patterns changes are ignored in, an empty list removes them
+    #[schema(example = json!(["node_modules", ".git", "*.log"]))]
+    pub ignore: Option<Vec<String>>,
+    /// Milliseconds without changes before restarting
+    #[schema(example = 200)]
+    pub debounce: Option<u64>,
+    /// Milliseconds to wait after the debounce before restarting
+    #[schema(example = 0)]
+    pub delay: Option<u64>,
+}
+
+impl Settings {
+    pub fn apply(&self, watch: &mut Watch) {
+        if let Some(path) = &self.path {
+            watch.enabled = !path.is_empty();
+            watch.path = path.clone();
+        }
+        if let Some(paths) = &self.paths {
+            watch.paths = paths.clone();
+        }
+        if let Some(ignore) = &self.ignore {
+            watch.ignore = ignore.clone();
+        }
+        if let Some(debounce) = self.debounce {
+            watch.debounce = debounce;
+        }
+        if let Some(delay) = self.delay {
+            watch.delay = delay;
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self != &Settings::default()
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "debounce {}ms, delay {}ms", self.debounce, self.delay)?;
+        match self.ignore.is_empty() {
+            true => Ok(()),
+            false => write!(f, ", ignoring {}", self.ignore.join(", ")),
+        }
+    }
+}
+
+/// Whether a path below a watched root matches one of the ignore patterns.
+/// Patterns without a slash match any single component, like `node_modules`
+/// or `*.log`, patterns with one match the whole relative path.
+pub fn ignored(patterns: &[String], relative: &Path) -> bool {
+    let path = relative.to_string_lossy();
+
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.trim_matches('/');
+
+        match pattern.contains('/') {
+            true => matches(pattern.as_bytes(), path.as_bytes()),
+            false => (relative.components())
+                .any(|part| matches(pattern.as_bytes(), part.as_os_str().as_encoded_bytes())),
+        }
+    })
+}
+
+/// Glob match where `*` and `?` stay within a component and `**` crosses them
+pub(crate) fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=text.len()).any(|skip| matches(rest, &text[skip..]))
+        }
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|skip| *skip == 0 || text[skip - 1] != b'/')
+            .any(|skip| matches(rest, &text[skip..])),
+        [b'?', rest @ ..] => text.first().is_some_and(|c| *c != b'/') && matches(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(list: &[&str]) -> Vec<String> {
+        list.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn test_ignored() {
+        let list = patterns(&["node_modules", ".git", "*.log", "dist/**/*.map"]);
+
+        assert!(ignored(&list, Path::new("node_modules/react/index.js")));
+        assert!(ignored(&list, Path::new("packages/app/node_modules/a.js")));
+        assert!(ignored(&list, Path::new(".git/HEAD")));
+        assert!(ignored(&list, Path::new("logs/app.log")));
+        assert!(ignored(&list, Path::new("dist/js/app.js.map")));
+        assert!(!ignored(&list, Path::new("src/index.js")));
+        assert!(!ignored(&list, Path::new("dist/app.js")));
+        assert!(!ignored(&list, Path::new("src/logger.js")));
+    }
+
+    #[test]
+    fn test_settings_apply() {
+        let mut watch = Watch::default();
+        let settings = Settings {
+            ignore: Some(patterns(&["*.log"])),
+            delay: Some(500),
+            ..Default::default()
+        };
+
+        settings.apply(&mut watch);
+        assert_eq!(watch.ignore, patterns(&["*.log"]));
+        assert_eq!((watch.debounce, watch.delay), (DEFAULT_DEBOUNCE_MS, 500));
+    }
+}